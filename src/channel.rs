@@ -2,30 +2,35 @@ use std::clone::Clone;
 use std::vec::Vec;
 use std::fmt::Display;
 
+use async_trait::async_trait;
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 use serde_derive::{Serialize, Deserialize};
-use twilight::{
-    http::Client as HttpClient,
-    http::error::Error as DiscordError,
-    model::{
-        channel::{
-            Channel, ChannelType, GuildChannel,
-            permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
-        },
-        guild::Permissions,
-        id::{ChannelId, GuildId, UserId},
+use tracing::{debug, warn};
+use twilight::model::{
+    channel::{
+        Channel, ChannelType, GuildChannel,
+        permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
     },
+    guild::Permissions,
+    id::{ChannelId, GuildId, UserId},
 };
 
-use crate::role::has_role;
-use crate::roles::ORGANIZER;
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::config::Config;
+use crate::discord::{channel_id, ChannelSpec, DiscordApi};
+use crate::locale::{language, Language, Msg};
+use crate::log_channel::{log_action, Outcome};
+use crate::permission::Capability;
+use crate::retry::{retry, Retry};
 use crate::state::PersistentState;
+use crate::storage::Change;
 use crate::utils::{Result, send_message};
 
 lazy_static! {
     static ref INVALID_REGEX: Regex = Regex::new("[`|]+").unwrap();
     static ref MARKDOWN_ESCAPE_REGEX: Regex = Regex::new("[-_+*\"#=.⋅\\\\<>{}]+").unwrap();
+    static ref MARKDOWN_UNESCAPE_REGEX: Regex = Regex::new("\\\\([-_+*\"#=.⋅\\\\<>{}])").unwrap();
 }
 
 fn permissions_deny_send(user_id: UserId) -> Vec<PermissionOverwrite> {
@@ -44,7 +49,12 @@ fn to_markdown_safe<'a>(name: &'a str) -> String {
     ).to_string()
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/// Reverses `to_markdown_safe`
+fn from_markdown_safe(name: &str) -> String {
+    MARKDOWN_UNESCAPE_REGEX.replace_all(name, "$1").to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Team {
     game_name: String,
     category_id: ChannelId,
@@ -52,13 +62,31 @@ pub struct Team {
     voice_id: ChannelId,
 }
 
+impl Team {
+    pub fn category_id(&self) -> ChannelId {
+        self.category_id
+    }
+
+    pub fn text_id(&self) -> ChannelId {
+        self.text_id
+    }
+
+    pub fn voice_id(&self) -> ChannelId {
+        self.voice_id
+    }
+
+    pub fn game_name(&self) -> &str {
+        &self.game_name
+    }
+}
+
 pub async fn handle_create_channels<'a>(
     rest_command: &[&'a str],
     original_channel_id: ChannelId,
     guild_id: GuildId,
     user_id: UserId,
     current_user_id: UserId,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
 
     let result = create_team(
@@ -66,23 +94,27 @@ pub async fn handle_create_channels<'a>(
         guild_id,
         user_id,
         current_user_id,
-        &http
+        http
     ).await;
 
+    let language = language(guild_id, user_id).await;
     match result {
         Ok(team) => {
-            send_message(&http, original_channel_id, user_id,
-                format!(
-                    "Channels created for your game **{}** here: <#{}>",
-                    team.game_name, team.text_id
-                )
+            send_message(http, original_channel_id, user_id,
+                Msg::ChannelsCreated { game: &team.game_name, channel: team.text_id }.text(language)
             ).await?;
         }
         Err(ref e) => {
-            send_message(&http, original_channel_id, user_id,
-                format!("{}", e)
-            ).await?;
-            println!("Channel creation failed: {:?}", e);
+            send_message(http, original_channel_id, user_id, e.message(language)).await?;
+            warn!(guild = %guild_id, user = %user_id, error = ?e, "Channel creation failed");
+            if !e.is_user_error() {
+                log_action(http, guild_id, user_id, "Channel creation failed",
+                    Outcome::Failed(match std::error::Error::source(e) {
+                        Some(source) => format!("{}\n{}", e, source),
+                        None => e.to_string(),
+                    })
+                ).await;
+            }
         }
     }
     Ok(())
@@ -91,93 +123,109 @@ pub async fn handle_create_channels<'a>(
 pub async fn handle_rename_channels<'a>(
     rest_command: &[&'a str],
     original_channel_id: ChannelId,
+    guild_id: GuildId,
     user_id: UserId,
     current_user_id: UserId,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
 
     if rest_command.len() > 0 {
         let new_name = &*rest_command.join(" ");
+        let language = language(guild_id, user_id).await;
 
         if INVALID_REGEX.is_match(&new_name) {
-            send_message(&http, original_channel_id, user_id,
-                "Game names cannot contain the characters ` or |"
+            send_message(http, original_channel_id, user_id,
+                Msg::InvalidGameName.text(language)
             ).await?;
             return Ok(());
         }
 
-        if !PersistentState::instance().lock().unwrap().has_created_channel(user_id) {
-            send_message(&http, original_channel_id, user_id,
-                format!(
-                    "You have not created a channel yet.\n\
-                    Try using `!createchannels <game name>` instead."
-                )
-            ).await?;
-        }
-        else {
-            let mut team = PersistentState::instance().lock().unwrap().get_channel_info(user_id).cloned().unwrap();
-            team.game_name = to_markdown_safe(new_name);
-            PersistentState::instance().lock().unwrap().register_channel_creation(user_id, &team)?;
+        let state = PersistentState::for_guild(guild_id).await?;
+        let team = state.lock().await.get_channel_info(user_id).cloned();
 
+        if let Some(mut team) = team {
+            let game_name = to_markdown_safe(new_name);
+            let names = &Config::get().channels;
             let mut oks = Vec::new();
             let mut errs = Vec::new();
-            match http.update_channel(team.category_id)
-            .kind(ChannelType::GuildCategory)
-            .name(new_name)
-            .await {
+            let spec = ChannelSpec::new(names.category(new_name), ChannelType::GuildCategory);
+            match retry(Retry::Idempotent, "rename category",
+                || http.update_channel(team.category_id, spec.clone())
+            ).await {
                 Ok(Channel::Guild(GuildChannel::Category(category))) => {
-                    oks.push(format!("category to **{}**", category.name));
+                    oks.push(Msg::RenamedCategory { name: &category.name }.text(language));
                 }
                 _ => {
-                    errs.push("category".to_string());
+                    errs.push(Msg::Category.text(language));
                 }
             }
-            match http.update_channel(team.text_id)
-            .parent_id(team.category_id)
-            .kind(ChannelType::GuildText)
-            .topic(format!("Work on and playtesting of the game {}.", team.game_name))
-            .permission_overwrites(permissions_deny_send(current_user_id))
-            .name(new_name).await {
+            let spec = ChannelSpec {
+                parent_id: Some(team.category_id),
+                topic: Some(names.topic(&game_name)),
+                permission_overwrites: Some(permissions_deny_send(current_user_id)),
+                ..ChannelSpec::new(names.text(new_name), ChannelType::GuildText)
+            };
+            match retry(Retry::Idempotent, "rename text channel",
+                || http.update_channel(team.text_id, spec.clone())
+            ).await {
                 Ok(Channel::Guild(GuildChannel::Category(text))) => {
-                    oks.push(format!("text channel to **#{}** (found here: <#{}>)", text.name, text.id));
+                    oks.push(Msg::RenamedText { name: &text.name, channel: text.id }.text(language));
                 }
                 _ => {
-                    errs.push("text channel".to_string());
+                    errs.push(Msg::TextChannel.text(language));
                 }
             }
-            match http.update_channel(team.voice_id)
-            .parent_id(team.category_id)
-            .kind(ChannelType::GuildVoice)
-            .name(new_name).await {
+            let spec = ChannelSpec {
+                parent_id: Some(team.category_id),
+                ..ChannelSpec::new(names.voice(new_name), ChannelType::GuildVoice)
+            };
+            match retry(Retry::Idempotent, "rename voice channel",
+                || http.update_channel(team.voice_id, spec.clone())
+            ).await {
                 Ok(Channel::Guild(GuildChannel::Category(voice))) => {
-                    oks.push(format!("voice channel to **{}**", voice.name));
+                    oks.push(Msg::RenamedVoice { name: &voice.name }.text(language));
                 }
                 _ => {
-                    errs.push("voice channel".to_string());
+                    errs.push(Msg::VoiceChannel.text(language));
+                }
+            }
+
+            // The team only takes the new name once some of its channels have
+            // it, and not at all if an organizer removed the team meanwhile
+            if oks.len() > 0 {
+                let mut state = state.lock().await;
+                let current = state.get_channel_info(user_id).map(Team::category_id);
+                if current == Some(team.category_id) {
+                    team.game_name = game_name.clone();
+                    state.register_channel_creation(user_id, user_id, &team)?;
                 }
             }
 
             let message =
             if oks.len() > 0 {
                 if errs.len() > 0 {
-                    let have_has = if errs.len() > 1 { "have" } else { "has" };
-                    format!("Renamed {} for your game **{}** but its {} {} been removed, it seems.",
-                        list_strings(oks), team.game_name, list_strings(errs), have_has
-                    )
+                    Msg::RenamedPartly {
+                        renamed: &list_strings(oks, language),
+                        game: &game_name,
+                        count: errs.len(),
+                        missing: &list_strings(errs, language),
+                    }.text(language)
                 }
                 else {
-                    format!("Renamed {} for your game **{}**.",
-                        list_strings(oks), team.game_name
-                    )
+                    Msg::Renamed {
+                        renamed: &list_strings(oks, language),
+                        game: &game_name,
+                    }.text(language)
                 }
             }
             else {
-                format!("Category, text channel and voice channel for your game **{}** have been removed, it seems.",
-                    team.game_name
-                )
+                Msg::RenamedNone { game: &team.game_name }.text(language)
             };
 
-            send_message(&http, original_channel_id, user_id, message).await?;
+            send_message(http, original_channel_id, user_id, message).await?;
+        }
+        else {
+            send_message(http, original_channel_id, user_id, Msg::NoTeamYet.text(language)).await?;
         }
     }
     Ok(())
@@ -188,122 +236,119 @@ pub async fn handle_remove_channels<'a>(
     original_channel_id: ChannelId,
     guild_id: GuildId,
     author_id: UserId,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    // Only let organizers use this command
-    if !has_role(&http, guild_id, author_id, ORGANIZER).await? {
-        send_message(&http, original_channel_id, author_id,
-            format!("You need to be an **organizer** to use this command.")
-        ).await?
-    }
-    else {
-        if rest_command.len() > 0 {
+    let language = language(guild_id, author_id).await;
+    if rest_command.len() > 0 {
 
-            lazy_static! {
-                static ref USER_MENTION_REGEX: Regex =
-                    Regex::new(r"<@!(\d+)>").unwrap();
-            }
-            let id_str: String = match USER_MENTION_REGEX.captures(rest_command[0]) {
-                Some(user_ids) => {
-                    if user_ids.len() == 2 {
-                        user_ids[1].to_string()
-                    }
-                    else {
-                        send_message(&http, original_channel_id, author_id,
-                            "Invalid user reference."
-                        ).await?;
-                        return Ok(())
-                    }
+        lazy_static! {
+            static ref USER_MENTION_REGEX: Regex =
+                Regex::new(r"<@!(\d+)>").unwrap();
+        }
+        let id_str: String = match USER_MENTION_REGEX.captures(rest_command[0]) {
+            Some(user_ids) => {
+                if user_ids.len() == 2 {
+                    user_ids[1].to_string()
                 }
-                _ => {
-                    send_message(&http, original_channel_id, author_id,
-                        "Invalid user reference."
+                else {
+                    send_message(http, original_channel_id, author_id,
+                        Msg::InvalidUserReference.text(language)
                     ).await?;
                     return Ok(())
                 }
-            };
+            }
+            _ => {
+                send_message(http, original_channel_id, author_id,
+                    Msg::InvalidUserReference.text(language)
+                ).await?;
+                return Ok(())
+            }
+        };
 
-            let id = match id_str.parse::<u64>() {
-                Ok(id) => id,
-                Err(_) => {
-                    send_message(&http, original_channel_id, author_id,
-                        format!("That user id is invalid.")
-                    ).await?;
-                    return Ok(())
-                },
-            };
+        let id = match id_str.parse::<u64>() {
+            Ok(id) => id,
+            Err(_) => {
+                send_message(http, original_channel_id, author_id,
+                    Msg::InvalidUserId.text(language)
+                ).await?;
+                return Ok(())
+            },
+        };
 
-            let user_id = UserId(id);
+        let user_id = UserId(id);
 
-            if !PersistentState::instance().lock().unwrap().has_created_channel(user_id) {
-                send_message(&http, original_channel_id, author_id,
-                    format!("That user does not have any team channels.")
-                ).await?;
-            }
-            else {
-                let team = PersistentState::instance().lock().unwrap().get_channel_info(user_id).cloned().unwrap();
+        let team = PersistentState::for_guild(guild_id).await?.lock().await
+            .get_channel_info(user_id).cloned();
+        if let Some(team) = team {
 
-                let mut oks = Vec::new();
-                let mut errs = Vec::new();
-                match http.delete_channel(team.text_id).await {
-                    Ok(Channel::Guild(GuildChannel::Category(text))) => {
-                        oks.push(format!("text channel **#{}**", text.name));
-                    }
-                    _ => {
-                        errs.push("text channel".to_string());
-                    }
+            let mut oks = Vec::new();
+            let mut errs = Vec::new();
+            match retry(Retry::Idempotent, "delete channel", || http.delete_channel(team.text_id)).await {
+                Ok(Channel::Guild(GuildChannel::Category(text))) => {
+                    oks.push(Msg::RemovedText { name: &text.name }.text(language));
                 }
-                match http.delete_channel(team.voice_id).await {
-                    Ok(Channel::Guild(GuildChannel::Category(voice))) => {
-                        oks.push(format!("voice channel **{}**", voice.name));
-                    }
-                    _ => {
-                        errs.push("voice channel".to_string());
-                    }
+                _ => {
+                    errs.push(Msg::TextChannel.text(language));
                 }
-                // Placed last to avoid text and void channels from losing their
-                // parent category and being moved to base level before deletion.
-                match http.delete_channel(team.category_id).await {
-                    Ok(Channel::Guild(GuildChannel::Category(category))) => {
-                        oks.insert(0, format!("category **{}**", category.name)); // Push front
-                    }
-                    _ => {
-                        errs.insert(0, "category".to_string()); // Push front
-                    }
+            }
+            match retry(Retry::Idempotent, "delete channel", || http.delete_channel(team.voice_id)).await {
+                Ok(Channel::Guild(GuildChannel::Category(voice))) => {
+                    oks.push(Msg::RemovedVoice { name: &voice.name }.text(language));
+                }
+                _ => {
+                    errs.push(Msg::VoiceChannel.text(language));
                 }
+            }
+            // Placed last to avoid text and void channels from losing their
+            // parent category and being moved to base level before deletion.
+            match retry(Retry::Idempotent, "delete channel", || http.delete_channel(team.category_id)).await {
+                Ok(Channel::Guild(GuildChannel::Category(category))) => {
+                    oks.insert(0, Msg::RemovedCategory { name: &category.name }.text(language)); // Push front
+                }
+                _ => {
+                    errs.insert(0, Msg::Category.text(language)); // Push front
+                }
+            }
 
-                PersistentState::instance().lock().unwrap().remove_channel(user_id).unwrap();
+            PersistentState::for_guild(guild_id).await?.lock().await.remove_channel(author_id, user_id)?;
 
-                let message =
-                if oks.len() > 0 {
-                    if errs.len() > 0 {
-                        let have_has = if errs.len() > 1 { "have" } else { "has" };
-                        format!("Removed {} for the game **{}** but its {} {} already been removed.",
-                            list_strings(oks), team.game_name, list_strings(errs), have_has
-                        )
-                    }
-                    else {
-                        format!("Removed {} for the game **{}**.",
-                            list_strings(oks), team.game_name
-                        )
-                    }
+            let message =
+            if oks.len() > 0 {
+                if errs.len() > 0 {
+                    Msg::RemovedPartly {
+                        removed: &list_strings(oks, language),
+                        game: &team.game_name,
+                        count: errs.len(),
+                        missing: &list_strings(errs, language),
+                    }.text(language)
                 }
                 else {
-                    format!("Category, text channel and voice channel for the game **{}** have already been removed.",
-                        team.game_name
-                    )
-                };
-
-                send_message(&http, original_channel_id, author_id, message).await?;
+                    Msg::Removed {
+                        removed: &list_strings(oks, language),
+                        game: &team.game_name,
+                    }.text(language)
+                }
             }
+            else {
+                Msg::RemovedNone { game: &team.game_name }.text(language)
+            };
+
+            log_action(http, guild_id, author_id, "Team channels removed",
+                Outcome::Done(format!("{}\nThe team belonged to <@{}>.", message, user_id))
+            ).await;
+            send_message(http, original_channel_id, author_id, message).await?;
         }
         else {
-            send_message(&http, original_channel_id, author_id,
-                "You forgot to provide a user id."
+            send_message(http, original_channel_id, author_id,
+                Msg::NoTeamChannels.text(language)
             ).await?;
-            return Ok(())
         }
     }
+    else {
+        send_message(http, original_channel_id, author_id,
+            Msg::MissingUserId.text(language)
+        ).await?;
+    }
     Ok(())
 }
 
@@ -311,34 +356,123 @@ pub async fn handle_clear_channel_associations<'a>(
     original_channel_id: ChannelId,
     guild_id: GuildId,
     author_id: UserId,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    // Only let organizers use this command
-    if !has_role(&http, guild_id, author_id, ORGANIZER).await? {
-        send_message(&http, original_channel_id, author_id,
-            format!("You need to be an **organizer** to use this command.")
-        ).await?
+    let channel_count = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        let channel_count = state.get_channel_count();
+        state.remove_all_channels(author_id)?;
+        channel_count
+    };
+
+    let message = Msg::AssociationsCleared { count: channel_count }
+        .text(language(guild_id, author_id).await);
+    log_action(http, guild_id, author_id, "Channel associations cleared",
+        Outcome::Done(message.clone())
+    ).await;
+    send_message(http, original_channel_id, author_id, message).await?;
+    Ok(())
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(CreateChannels);
+    registry.register(RenameChannels);
+    registry.register(RemoveChannels);
+    registry.register(ClearAssociations);
+}
+
+struct CreateChannels;
+
+#[async_trait]
+impl Command for CreateChannels {
+    fn name(&self) -> &'static str { "createchannels" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageGameName) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeCreateChannels }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_create_channels(
+            args,
+            ctx.msg.channel_id,
+            ctx.guild_id,
+            ctx.msg.author.id,
+            ctx.current_user.id,
+            ctx.http
+        ).await
     }
-    else {
-        let channel_count = PersistentState::instance().lock().unwrap().get_channel_count();
-        PersistentState::instance().lock().unwrap().remove_all_channels().unwrap();
+}
 
-        send_message(&http, original_channel_id, author_id,
-            format!("Cleared all {} user–channel associations.\n\
-            All users can now create new channels.", channel_count)
-        ).await?;
+struct RenameChannels;
+
+#[async_trait]
+impl Command for RenameChannels {
+    fn name(&self) -> &'static str { "renamechannels" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageNewGameName) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeRenameChannels }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_rename_channels(
+            args,
+            ctx.msg.channel_id,
+            ctx.guild_id,
+            ctx.msg.author.id,
+            ctx.current_user.id,
+            ctx.http
+        ).await
+    }
+}
+
+struct RemoveChannels;
+
+#[async_trait]
+impl Command for RemoveChannels {
+    fn name(&self) -> &'static str { "removechannels" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageUserMention) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeRemoveChannels }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::TeamsRemove)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_remove_channels(
+            args,
+            ctx.msg.channel_id,
+            ctx.guild_id,
+            ctx.msg.author.id,
+            ctx.http
+        ).await
+    }
+}
+
+struct ClearAssociations;
+
+#[async_trait]
+impl Command for ClearAssociations {
+    fn name(&self) -> &'static str { "clearassociations" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeClearAssociations }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::TeamsClear)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_clear_channel_associations(
+            ctx.msg.channel_id,
+            ctx.guild_id,
+            ctx.msg.author.id,
+            ctx.http
+        ).await
     }
-    Ok(())
 }
 
 fn list_strings(
-    strings: Vec<String>
+    strings: Vec<String>,
+    language: Language
 ) -> String {
     let mut result = "".to_string();
     for i in 0..strings.len() {
         if i > 0 {
             if i == strings.len() - 1 {
-                result.push_str(" and ");
+                result.push_str(&format!(" {} ", Msg::And.text(language)));
             }
             else {
                 result.push_str(", ");
@@ -354,15 +488,18 @@ async fn create_team<'a>(
     guild: GuildId,
     user: UserId,
     current_user_id: UserId,
-    http: &HttpClient
+    http: &dyn DiscordApi
 ) -> std::result::Result<Team, ChannelCreationError<>> {
 
-    if PersistentState::instance().lock().unwrap().has_created_channel(user) {
-        Err(ChannelCreationError::AlreadyCreated(user))
+    let state = PersistentState::for_guild(guild).await
+        .map_err(ChannelCreationError::NotSaved)?;
+    let existing_team = state.lock().await.get_channel_info(user).cloned();
+    if let Some(team) = existing_team {
+        Err(ChannelCreationError::AlreadyCreated(team))
     }
     else {
         let game_name = &*rest_command.join(" ");
-        println!("Got a request for channels for the game {:?}", game_name);
+        debug!(guild = %guild, user = %user, game = game_name, "Got a request for channels");
         if rest_command.len() == 0 {
             Err(ChannelCreationError::NoName)
         }
@@ -370,75 +507,198 @@ async fn create_team<'a>(
             Err(ChannelCreationError::InvalidName)
         }
         else {
-            let category_name = format!("Team: {}", game_name);
-            // Create a category
-            let category = http.create_guild_channel(guild, category_name)
-                .kind(ChannelType::GuildCategory)
-                .await
-                .map_err(ChannelCreationError::CategoryCreationFailed)
-                .and_then(|maybe_category| {
-                    match maybe_category {
-                        GuildChannel::Category(category) => {
-                            Ok(category)
-                        }
-                        _ => Err(ChannelCreationError::CategoryNotCreated)
-                    }
-                })?;
-
-            let text = http.create_guild_channel(guild, game_name)
-                .parent_id(category.id)
-                .kind(ChannelType::GuildText)
-                .topic(format!("Work on and playtesting of the game {}.", game_name))
-                .permission_overwrites(permissions_deny_send(current_user_id))
-                .await
-                .map_err(|e| ChannelCreationError::TextCreationFailed(e))
-                .and_then(|maybe_text| {
-                    match maybe_text {
-                        GuildChannel::Category(text) => { // For some reason it isn't a GuildChannel::Text
-                            Ok(text)
-                        }
-                        _ => Err(ChannelCreationError::TextNotCreated)
-                    }
-                })?;
-
-            let voice = http.create_guild_channel(guild, game_name)
-                .parent_id(category.id)
-                .kind(ChannelType::GuildVoice)
-                .await
-                .map_err(|e| ChannelCreationError::VoiceCreationFailed(e))
-                .and_then(|maybe_voice| {
-                    match maybe_voice {
-                        GuildChannel::Category(voice) => { // For some reason it isn't a GuildChannel::Voice
-                            Ok(voice)
-                        }
-                        _ => Err(ChannelCreationError::VoiceNotCreated)
+            let team = create_channels(guild, game_name, current_user_id, http).await?;
+            let registered = state.lock().await.register_channel_creation(user, user, &team);
+            match registered {
+                Ok(()) => Ok(team),
+                Err(e) => {
+                    // A team that isn't saved would be forgotten on restart,
+                    // so take it back along with its channels
+                    {
+                        let mut state = state.lock().await;
+                        state.channel_creators.remove(&user);
+                        state.save_change(Change::Team(user)).ok();
                     }
-                })?;
+                    let created = vec!(team.category_id, team.text_id, team.voice_id);
+                    Err(roll_back(guild, created, ChannelCreationError::NotSaved(e), http).await)
+                }
+            }
+        }
+    }
+}
 
-            let team = Team {
-                game_name: to_markdown_safe(game_name),
-                category_id: category.id,
-                text_id: text.id,
-                voice_id: voice.id
-            };
-            PersistentState::instance().lock().unwrap()
-                .register_channel_creation(user, &team)
-                .unwrap();
+/**
+  Creates the category, text and voice channel of a team. If one of them
+  can't be created, the ones that already were are deleted again so that
+  nothing is left half done
+*/
+async fn create_channels(
+    guild: GuildId,
+    game_name: &str,
+    current_user_id: UserId,
+    http: &dyn DiscordApi
+) -> std::result::Result<Team, ChannelCreationError> {
+    let mut created = Vec::new();
+    match try_create_channels(guild, game_name, current_user_id, http, &mut created).await {
+        Ok(team) => Ok(team),
+        Err(e) => Err(roll_back(guild, created, e, http).await),
+    }
+}
+
+/// Creates the channels of a team, adding each one to `created` as soon as it exists
+async fn try_create_channels(
+    guild: GuildId,
+    game_name: &str,
+    current_user_id: UserId,
+    http: &dyn DiscordApi,
+    created: &mut Vec<ChannelId>,
+) -> std::result::Result<Team, ChannelCreationError> {
+    let names = &Config::get().channels;
+    // Create a category
+    let spec = ChannelSpec::new(names.category(game_name), ChannelType::GuildCategory);
+    let category = retry(Retry::Creating, "create category",
+            || http.create_guild_channel(guild, spec.clone())
+        )
+        .await
+        .map_err(ChannelCreationError::CategoryCreationFailed)?;
+    created.push(channel_id(&category));
+    let category = match category {
+        GuildChannel::Category(category) => category,
+        _ => return Err(ChannelCreationError::CategoryNotCreated)
+    };
 
-            Ok(team)
+    let spec = ChannelSpec {
+        parent_id: Some(category.id),
+        topic: Some(names.topic(game_name)),
+        permission_overwrites: Some(permissions_deny_send(current_user_id)),
+        ..ChannelSpec::new(names.text(game_name), ChannelType::GuildText)
+    };
+    let text = retry(Retry::Creating, "create text channel",
+            || http.create_guild_channel(guild, spec.clone())
+        )
+        .await
+        .map_err(|e| ChannelCreationError::TextCreationFailed(e))?;
+    created.push(channel_id(&text));
+    let text = match text {
+        GuildChannel::Category(text) => text, // For some reason it isn't a GuildChannel::Text
+        _ => return Err(ChannelCreationError::TextNotCreated)
+    };
+
+    let spec = ChannelSpec {
+        parent_id: Some(category.id),
+        ..ChannelSpec::new(names.voice(game_name), ChannelType::GuildVoice)
+    };
+    let voice = retry(Retry::Creating, "create voice channel",
+            || http.create_guild_channel(guild, spec.clone())
+        )
+        .await
+        .map_err(|e| ChannelCreationError::VoiceCreationFailed(e))?;
+    created.push(channel_id(&voice));
+    let voice = match voice {
+        GuildChannel::Category(voice) => voice, // For some reason it isn't a GuildChannel::Voice
+        _ => return Err(ChannelCreationError::VoiceNotCreated)
+    };
+
+    Ok(Team {
+        game_name: to_markdown_safe(game_name),
+        category_id: category.id,
+        text_id: text.id,
+        voice_id: voice.id
+    })
+}
+
+/**
+  Deletes the channels that were created before `cause` stopped the creation
+  of a team, last first so that the category goes after its channels.
+  Returns the error to report, which mentions the channels that couldn't be
+  deleted
+*/
+async fn roll_back(
+    guild: GuildId,
+    created: Vec<ChannelId>,
+    cause: ChannelCreationError,
+    http: &dyn DiscordApi
+) -> ChannelCreationError {
+    let left_behind = delete_channels(guild, created, http).await;
+    if left_behind.is_empty() {
+        cause
+    }
+    else {
+        ChannelCreationError::LeftBehind(Box::new(cause), left_behind)
+    }
+}
+
+/**
+  Deletes the channels of a team that couldn't be kept, newest first so that
+  no channel is left without its category. Returns the channels that
+  couldn't be deleted
+*/
+async fn delete_channels(
+    guild: GuildId,
+    channels: Vec<ChannelId>,
+    http: &dyn DiscordApi
+) -> Vec<ChannelId> {
+    let mut left_behind = Vec::new();
+    for channel_id in channels.into_iter().rev() {
+        let deleted = retry(Retry::Idempotent, "delete channel",
+            || http.delete_channel(channel_id)
+        ).await;
+        if let Err(e) = deleted {
+            warn!(guild = %guild, channel = %channel_id, error = ?e,
+                "Failed to delete a channel of a team that couldn't be kept");
+            left_behind.push(channel_id);
         }
     }
+    left_behind
+}
+
+/**
+  Deletes the channels that `restore_team_channels` recreated when the team
+  couldn't be restored after all. Returns what to tell the organizers about
+  the channels that couldn't be deleted, if there are any
+*/
+pub async fn delete_restored_channels(
+    guild: GuildId,
+    team: &Team,
+    language: Language,
+    http: &dyn DiscordApi
+) -> Option<String> {
+    let channels = vec!(team.category_id, team.text_id, team.voice_id);
+    let left_behind = delete_channels(guild, channels, http).await;
+    if left_behind.is_empty() {
+        return None
+    }
+    let channels = left_behind.iter().map(|id| format!("<#{}>", id)).collect();
+    Some(Msg::ChannelsLeftBehind { channels: &list_strings(channels, language) }.text(language))
+}
+
+/**
+  Recreates the channels of a team that was removed, unless they still
+  exist. Returns the team with the ids of the channels
+*/
+pub async fn restore_team_channels(
+    guild: GuildId,
+    team: &Team,
+    current_user_id: UserId,
+    http: &dyn DiscordApi
+) -> Result<Team> {
+    if let Ok(Some(_)) = http.channel(team.category_id).await {
+        return Ok(team.clone())
+    }
+    let game_name = from_markdown_safe(&team.game_name);
+    Ok(create_channels(guild, &game_name, current_user_id, http).await?)
 }
 
 /**
   Error type for channel creation attempts
 
-  The Display implementation is intended to be sent back to the user
+  `message` is intended to be sent back to the user. The Display
+  implementation gives the same text in English, for the logs
 */
 #[derive(Debug)]
 enum ChannelCreationError {
     /// The user has already created a channel
-    AlreadyCreated(UserId),
+    AlreadyCreated(Team),
     /// No name was specified
     NoName,
     /// The user used invalid characters in the channel name
@@ -453,37 +713,66 @@ enum ChannelCreationError {
     /// that was not a voice channel
     VoiceNotCreated,
     /// The discord API returned an error when creating category
-    CategoryCreationFailed(DiscordError),
+    CategoryCreationFailed(anyhow::Error),
     /// The discord API returned an error when creating text channel
-    TextCreationFailed(DiscordError),
+    TextCreationFailed(anyhow::Error),
     /// The discord API returned an error when creating voice channel
-    VoiceCreationFailed(DiscordError)
+    VoiceCreationFailed(anyhow::Error),
+    /// The state of the guild couldn't be loaded or saved
+    NotSaved(anyhow::Error),
+    /// Creation failed and some of the channels that had already been
+    /// created couldn't be deleted again
+    LeftBehind(Box<ChannelCreationError>, Vec<ChannelId>),
 }
 
 impl Display for ChannelCreationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let msg = match self {
-            Self::AlreadyCreated(user) => {
-                let mut ps = PersistentState::instance().lock().unwrap();
-                let team = ps.get_channel_info(*user).unwrap();
-                format!("You have already created channels for your game **{}** here: <#{}>\n\
-                    Try using `!renamechannels <new game name>` instead if you wish to rename them.",
-                    team.game_name, team.text_id)
+        write!(f, "{}", self.message(Language::En))
+    }
+}
+
+impl ChannelCreationError {
+    /// The error in the language, to send back to the user
+    fn message(&self, language: Language) -> String {
+        match self {
+            // Something was kept, so saying that nothing was would be wrong
+            Self::LeftBehind(cause, channels) => {
+                let channels = channels.iter().map(|id| format!("<#{}>", id)).collect();
+                format!("{} {}",
+                    cause.msg().text(language),
+                    Msg::ChannelsLeftBehind { channels: &list_strings(channels, language) }
+                        .text(language)
+                )
             }
-            Self::NoName => "You need to specify a game name.".to_string(),
-            Self::CategoryNotCreated =>
-                "I asked Discord for a category but got something else. 🤔".to_string(),
-            Self::TextNotCreated =>
-                "I asked Discord for a text channel but got something else. 🤔".to_string(),
-            Self::VoiceNotCreated =>
-                "I asked Discord for a voice channel but got something else. 🤔".to_string(),
-            Self::InvalidName =>
-                "Game names cannot contain the characters ` or |".to_string(),
-            Self::CategoryCreationFailed(_) => "Category creation failed.".to_string(),
-            Self::TextCreationFailed(_) => "Text channel creation failed.".to_string(),
-            Self::VoiceCreationFailed(_) => "Voice channel creation failed.".to_string(),
-        };
-        write!(f, "{}", msg)
+            _ if self.is_user_error() => self.msg().text(language),
+            _ => format!("{} {}", self.msg().text(language), Msg::NothingKept.text(language)),
+        }
+    }
+
+    /// What went wrong, without what became of the channels
+    fn msg(&self) -> Msg<'_> {
+        match self {
+            Self::LeftBehind(cause, _) => cause.msg(),
+            Self::AlreadyCreated(team) =>
+                Msg::TeamAlreadyCreated { game: &team.game_name, channel: team.text_id },
+            Self::NoName => Msg::NoGameName,
+            Self::CategoryNotCreated => Msg::CategoryNotCreated,
+            Self::TextNotCreated => Msg::TextNotCreated,
+            Self::VoiceNotCreated => Msg::VoiceNotCreated,
+            Self::InvalidName => Msg::InvalidGameName,
+            Self::CategoryCreationFailed(_) => Msg::CategoryCreationFailed,
+            Self::TextCreationFailed(_) => Msg::TextCreationFailed,
+            Self::VoiceCreationFailed(_) => Msg::VoiceCreationFailed,
+            Self::NotSaved(_) => Msg::TeamCreationFailed,
+        }
+    }
+
+    /// Whether the user asked for something invalid, as opposed to discord failing
+    fn is_user_error(&self) -> bool {
+        match self {
+            Self::AlreadyCreated(_) | Self::NoName | Self::InvalidName => true,
+            _ => false,
+        }
     }
 }
 
@@ -498,7 +787,344 @@ impl std::error::Error for ChannelCreationError {
                 | Self::InvalidName => None,
             Self::CategoryCreationFailed(e)
                 | Self::TextCreationFailed(e)
-                | Self::VoiceCreationFailed(e) => Some(e)
+                | Self::VoiceCreationFailed(e)
+                | Self::NotSaved(e) => Some(e.as_ref()),
+            Self::LeftBehind(cause, _) => std::error::Error::source(&**cause),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::fake::{test_guild, Call, FakeDiscord};
+    use crate::storage;
+
+    const BOT: UserId = UserId(1);
+    const USER: UserId = UserId(10);
+    const ORGANIZER: UserId = UserId(11);
+    const COMMANDS: ChannelId = ChannelId(20);
+
+    fn fail_creating(discord: &FakeDiscord, kind: ChannelType) {
+        discord.fail_on(move |call| match call {
+            Call::CreateGuildChannel(_, spec) => spec.kind == kind,
+            _ => false,
+        });
+    }
+
+    fn fail_deleting(discord: &FakeDiscord, channel_id: ChannelId) {
+        discord.fail_on(move |call| match call {
+            Call::DeleteChannel(id) => *id == channel_id,
+            _ => false,
+        });
+    }
+
+    fn fail_renaming(discord: &FakeDiscord, channel_id: ChannelId) {
+        discord.fail_on(move |call| match call {
+            Call::UpdateChannel(id, _) => *id == channel_id,
+            _ => false,
+        });
+    }
+
+    /// The channels the fake was asked to delete, in order
+    fn deleted(discord: &FakeDiscord) -> Vec<ChannelId> {
+        discord.calls().into_iter().filter_map(|call| match call {
+            Call::DeleteChannel(id) => Some(id),
+            _ => None,
+        }).collect()
+    }
+
+    /// The replies to the user, without the mention in front
+    fn replies(discord: &FakeDiscord, user_id: UserId) -> Vec<String> {
+        let mention = format!("<@{}> ", user_id);
+        discord.messages(COMMANDS).into_iter()
+            .filter_map(|message| message.strip_prefix(&mention).map(str::to_string))
+            .collect()
+    }
+
+    async fn team_of(guild: GuildId, user_id: UserId) -> Option<Team> {
+        PersistentState::for_guild(guild).await.unwrap().lock().await.get_channel_info(user_id).cloned()
+    }
+
+    #[tokio::test]
+    async fn create_team_creates_and_registers_the_channels() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+
+        let team = create_team(&["Space", "Game"], guild, USER, BOT, &discord).await.unwrap();
+
+        assert_eq!(team.game_name(), "Space Game");
+        assert_eq!(discord.channel_count(guild), 3);
+        assert_eq!(discord.channel_name(team.category_id()).unwrap(), "Team: Space Game");
+        assert_eq!(discord.channel_name(team.text_id()).unwrap(), "Space Game");
+        assert_eq!(discord.channel_name(team.voice_id()).unwrap(), "Space Game");
+        assert_eq!(team_of(guild, USER).await.unwrap().text_id(), team.text_id());
+    }
+
+    #[tokio::test]
+    async fn create_team_refuses_a_second_team() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        create_team(&["First"], guild, USER, BOT, &discord).await.unwrap();
+
+        match create_team(&["Second"], guild, USER, BOT, &discord).await {
+            Err(ChannelCreationError::AlreadyCreated(team)) => assert_eq!(team.game_name(), "First"),
+            other => panic!("Expected AlreadyCreated, got {:?}", other),
+        }
+        assert_eq!(discord.channel_count(guild), 3);
+    }
+
+    #[tokio::test]
+    async fn create_team_checks_the_name() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+
+        assert!(matches!(create_team(&[], guild, USER, BOT, &discord).await,
+            Err(ChannelCreationError::NoName)));
+        assert!(matches!(create_team(&["a|b"], guild, USER, BOT, &discord).await,
+            Err(ChannelCreationError::InvalidName)));
+        assert!(discord.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_category_leaves_nothing() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        fail_creating(&discord, ChannelType::GuildCategory);
+
+        let result = create_team(&["Game"], guild, USER, BOT, &discord).await;
+
+        assert!(matches!(result, Err(ChannelCreationError::CategoryCreationFailed(_))));
+        assert_eq!(discord.channel_count(guild), 0);
+        assert!(deleted(&discord).is_empty());
+        assert!(team_of(guild, USER).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_text_channel_deletes_the_category() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        fail_creating(&discord, ChannelType::GuildText);
+
+        let result = create_team(&["Game"], guild, USER, BOT, &discord).await;
+
+        assert!(matches!(result, Err(ChannelCreationError::TextCreationFailed(_))));
+        assert_eq!(discord.channel_count(guild), 0);
+        assert_eq!(deleted(&discord).len(), 1);
+        assert!(team_of(guild, USER).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_voice_channel_deletes_the_others() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        fail_creating(&discord, ChannelType::GuildVoice);
+
+        let result = create_team(&["Game"], guild, USER, BOT, &discord).await;
+
+        assert!(matches!(result, Err(ChannelCreationError::VoiceCreationFailed(_))));
+        assert_eq!(discord.channel_count(guild), 0);
+        // The text channel goes before its category
+        assert_eq!(deleted(&discord).len(), 2);
+        assert!(team_of(guild, USER).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn channels_that_cant_be_deleted_are_reported() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        fail_creating(&discord, ChannelType::GuildVoice);
+        discord.fail_on(|call| match call {
+            Call::DeleteChannel(_) => true,
+            _ => false,
+        });
+
+        let error = create_team(&["Game"], guild, USER, BOT, &discord).await.unwrap_err();
+
+        let message = error.message(Language::En);
+        assert!(!message.contains(&Msg::NothingKept.text(Language::En)), "{}", message);
+        match error {
+            ChannelCreationError::LeftBehind(cause, channels) => {
+                assert!(matches!(*cause, ChannelCreationError::VoiceCreationFailed(_)));
+                assert_eq!(channels.len(), 2);
+            }
+            other => panic!("Expected LeftBehind, got {:?}", other),
         }
+        assert_eq!(discord.channel_count(guild), 2);
+    }
+
+    #[tokio::test]
+    async fn unsaved_team_deletes_its_channels() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        // Loaded before saving fails, so that only registering the team does
+        team_of(guild, USER).await;
+        storage::fail_saves(guild);
+
+        let result = create_team(&["Game"], guild, USER, BOT, &discord).await;
+
+        assert!(matches!(result, Err(ChannelCreationError::NotSaved(_))));
+        assert_eq!(discord.channel_count(guild), 0);
+        assert_eq!(deleted(&discord).len(), 3);
+        assert!(team_of(guild, USER).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn unsaved_removals_keep_the_teams() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        create_team(&["Game"], guild, USER, BOT, &discord).await.unwrap();
+        storage::fail_saves(guild);
+
+        let state = PersistentState::for_guild(guild).await.unwrap();
+        assert!(state.lock().await.remove_channel(ORGANIZER, USER).is_err());
+        assert!(team_of(guild, USER).await.is_some());
+        assert!(state.lock().await.remove_all_channels(ORGANIZER).is_err());
+        assert!(team_of(guild, USER).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn rename_renames_every_channel() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let team = create_team(&["Old"], guild, USER, BOT, &discord).await.unwrap();
+
+        handle_rename_channels(&["New", "Name"], COMMANDS, guild, USER, BOT, &discord).await.unwrap();
+
+        assert_eq!(discord.channel_name(team.category_id()).unwrap(), "Team: New Name");
+        assert_eq!(discord.channel_name(team.text_id()).unwrap(), "New Name");
+        assert_eq!(discord.channel_name(team.voice_id()).unwrap(), "New Name");
+        assert_eq!(team_of(guild, USER).await.unwrap().game_name(), "New Name");
+        let renamed = list_strings(vec!(
+            Msg::RenamedCategory { name: "Team: New Name" }.text(Language::En),
+            Msg::RenamedText { name: "New Name", channel: team.text_id() }.text(Language::En),
+            Msg::RenamedVoice { name: "New Name" }.text(Language::En),
+        ), Language::En);
+        assert_eq!(replies(&discord, USER), vec!(
+            Msg::Renamed { renamed: &renamed, game: "New Name" }.text(Language::En)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rename_reports_the_channels_it_couldnt_rename() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let team = create_team(&["Old"], guild, USER, BOT, &discord).await.unwrap();
+        fail_renaming(&discord, team.text_id());
+
+        handle_rename_channels(&["New"], COMMANDS, guild, USER, BOT, &discord).await.unwrap();
+
+        assert_eq!(discord.channel_name(team.category_id()).unwrap(), "Team: New");
+        assert_eq!(discord.channel_name(team.text_id()).unwrap(), "Old");
+        assert_eq!(discord.channel_name(team.voice_id()).unwrap(), "New");
+        let renamed = list_strings(vec!(
+            Msg::RenamedCategory { name: "Team: New" }.text(Language::En),
+            Msg::RenamedVoice { name: "New" }.text(Language::En),
+        ), Language::En);
+        assert_eq!(replies(&discord, USER), vec!(Msg::RenamedPartly {
+            renamed: &renamed,
+            game: "New",
+            count: 1,
+            missing: &Msg::TextChannel.text(Language::En),
+        }.text(Language::En)));
+    }
+
+    #[tokio::test]
+    async fn failed_rename_keeps_the_old_name() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        create_team(&["Old"], guild, USER, BOT, &discord).await.unwrap();
+        discord.fail_on(|call| match call {
+            Call::UpdateChannel(..) => true,
+            _ => false,
+        });
+
+        handle_rename_channels(&["New"], COMMANDS, guild, USER, BOT, &discord).await.unwrap();
+
+        assert_eq!(team_of(guild, USER).await.unwrap().game_name(), "Old");
+        assert_eq!(replies(&discord, USER), vec!(Msg::RenamedNone { game: "Old" }.text(Language::En)));
+    }
+
+    #[tokio::test]
+    async fn rename_without_a_team() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+
+        handle_rename_channels(&["New"], COMMANDS, guild, USER, BOT, &discord).await.unwrap();
+
+        assert_eq!(replies(&discord, USER), vec!(Msg::NoTeamYet.text(Language::En)));
+        assert!(team_of(guild, USER).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn remove_deletes_the_channels_and_the_team() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let team = create_team(&["Game"], guild, USER, BOT, &discord).await.unwrap();
+
+        let mention = format!("<@!{}>", USER);
+        handle_remove_channels(&[&mention], COMMANDS, guild, ORGANIZER, &discord).await.unwrap();
+
+        assert_eq!(discord.channel_count(guild), 0);
+        // The category goes last so its channels aren't moved out of it first
+        assert_eq!(deleted(&discord), vec!(team.text_id(), team.voice_id(), team.category_id()));
+        assert!(team_of(guild, USER).await.is_none());
+        let removed = list_strings(vec!(
+            Msg::RemovedCategory { name: "Team: Game" }.text(Language::En),
+            Msg::RemovedText { name: "Game" }.text(Language::En),
+            Msg::RemovedVoice { name: "Game" }.text(Language::En),
+        ), Language::En);
+        assert_eq!(replies(&discord, ORGANIZER), vec!(
+            Msg::Removed { removed: &removed, game: "Game" }.text(Language::En)
+        ));
+    }
+
+    #[tokio::test]
+    async fn remove_reports_the_channels_it_couldnt_delete() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let team = create_team(&["Game"], guild, USER, BOT, &discord).await.unwrap();
+        fail_deleting(&discord, team.voice_id());
+
+        let mention = format!("<@!{}>", USER);
+        handle_remove_channels(&[&mention], COMMANDS, guild, ORGANIZER, &discord).await.unwrap();
+
+        assert_eq!(discord.channel_count(guild), 1);
+        assert!(team_of(guild, USER).await.is_none());
+        let removed = list_strings(vec!(
+            Msg::RemovedCategory { name: "Team: Game" }.text(Language::En),
+            Msg::RemovedText { name: "Game" }.text(Language::En),
+        ), Language::En);
+        assert_eq!(replies(&discord, ORGANIZER), vec!(Msg::RemovedPartly {
+            removed: &removed,
+            game: "Game",
+            count: 1,
+            missing: &Msg::VoiceChannel.text(Language::En),
+        }.text(Language::En)));
+    }
+
+    #[tokio::test]
+    async fn removals_that_cant_be_saved_fail() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        create_team(&["Game"], guild, USER, BOT, &discord).await.unwrap();
+        storage::fail_saves(guild);
+
+        let mention = format!("<@!{}>", USER);
+        assert!(handle_remove_channels(&[&mention], COMMANDS, guild, ORGANIZER, &discord).await.is_err());
+        assert!(handle_clear_channel_associations(COMMANDS, guild, ORGANIZER, &discord).await.is_err());
+        assert!(team_of(guild, USER).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn remove_without_a_team() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+
+        let mention = format!("<@!{}>", USER);
+        handle_remove_channels(&[&mention], COMMANDS, guild, ORGANIZER, &discord).await.unwrap();
+
+        assert!(deleted(&discord).is_empty());
+        assert_eq!(replies(&discord, ORGANIZER), vec!(Msg::NoTeamChannels.text(Language::En)));
     }
 }
@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use twilight::model::id::{GuildId, UserId};
+
+use crate::config::Config;
+use crate::permission::Capability;
+use crate::state::PersistentState;
+use crate::storage::storage;
+use crate::theme_template::{check_template, Category};
+use crate::utils::Result;
+
+/// Actor recorded in the journal for changes made from the command line
+const CONSOLE_USER: UserId = UserId(0);
+
+const USAGE: &'static str = "\
+Usage: gamejambot [<command>] [--guild <guild id>]
+
+Without a command the bot connects to discord. The commands work on the
+stored state directly and should only be used while the bot is stopped,
+since it doesn't notice the changes and overwrites them on its next save.
+
+Commands:
+    state show                        Print the state as json
+    state validate                    Check the state for problems
+    themes export [--format csv|json] Print all theme ideas
+    teams list                        Print all teams and their channels
+    teams remove <user id>            Forget the team of the user. Its channels
+                                      are not deleted
+    help                              Print this message
+
+--guild is only needed when there is state for several guilds.";
+
+/**
+  Runs a maintenance command given on the command line instead of starting
+  the bot
+*/
+pub async fn run(args: &[String]) -> Result<()> {
+    let (guild, args) = take_option(args, "--guild")?;
+    let (format, args) = take_option(&args, "--format")?;
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        ["state", "show"] => {
+            let state = PersistentState::for_guild(select_guild(guild)?).await?;
+            let state = state.lock().await;
+            println!("{}", serde_json::to_string_pretty(&*state)?);
+        }
+        ["state", "validate"] => {
+            let guilds = match guild {
+                Some(_) => vec!(select_guild(guild)?),
+                None => storage().guilds()?,
+            };
+            let mut problem_count = 0;
+            for guild_id in guilds {
+                let problems = validate(guild_id);
+                for problem in &problems {
+                    println!("{}: {}", guild_id, problem);
+                }
+                problem_count += problems.len();
+            }
+            if problem_count > 0 {
+                return Err(anyhow!("Found {} problems", problem_count))
+            }
+            println!("No problems found");
+        }
+        ["themes", "export"] => {
+            let state = PersistentState::for_guild(select_guild(guild)?).await?;
+            let state = state.lock().await;
+            let mut ideas = state.theme_ideas.iter().collect::<Vec<_>>();
+            ideas.sort_by_key(|(user_id, _)| **user_id);
+            match format.as_ref().map(String::as_str) {
+                Some("csv") | None => {
+                    println!("user_id,idea,category");
+                    for (user_id, user_ideas) in ideas {
+                        for idea in user_ideas {
+                            println!("{},{},{}", user_id, csv_field(&idea.text),
+                                idea.category.map_or("", Category::name)
+                            );
+                        }
+                    }
+                }
+                Some("json") => println!("{}", serde_json::to_string_pretty(&state.theme_ideas)?),
+                Some(other) => return Err(anyhow!("Unknown format {}, expected csv or json", other)),
+            }
+        }
+        ["teams", "list"] => {
+            let state = PersistentState::for_guild(select_guild(guild)?).await?;
+            let state = state.lock().await;
+            let mut teams = state.channel_creators.iter().collect::<Vec<_>>();
+            teams.sort_by_key(|(user_id, _)| **user_id);
+            for (user_id, team) in teams {
+                println!("{}\t{}\tcategory {}\ttext {}\tvoice {}",
+                    user_id, team.game_name(), team.category_id(), team.text_id(), team.voice_id()
+                );
+            }
+        }
+        ["teams", "remove", user_id] => {
+            let user_id = UserId(user_id.parse()
+                .map_err(|_| anyhow!("{} is not a user id", user_id))?
+            );
+            let state = PersistentState::for_guild(select_guild(guild)?).await?;
+            let mut state = state.lock().await;
+            match state.get_channel_info(user_id).cloned() {
+                Some(team) => {
+                    state.remove_channel(CONSOLE_USER, user_id)?;
+                    println!("Removed the team {} of {}. Delete its channels on discord by hand \
+                        if they are no longer needed", team.game_name(), user_id);
+                }
+                None => return Err(anyhow!("{} has no team", user_id)),
+            }
+        }
+        ["help"] => println!("{}", USAGE),
+        _ => return Err(anyhow!("Unknown command\n\n{}", USAGE)),
+    }
+    Ok(())
+}
+
+/// Removes `--name <value>` from the arguments, returning the value
+fn take_option(args: &[String], name: &str) -> Result<(Option<String>, Vec<String>)> {
+    let mut value = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == name {
+            value = Some(args.next()
+                .ok_or_else(|| anyhow!("{} needs a value", name))?
+                .clone());
+        }
+        else {
+            rest.push(arg.clone());
+        }
+    }
+    Ok((value, rest))
+}
+
+/// The guild given with --guild, or the only guild with stored state
+fn select_guild(guild: Option<String>) -> Result<GuildId> {
+    let guilds = storage().guilds()?;
+    if let Some(guild) = guild {
+        let guild_id = GuildId(guild.parse().map_err(|_| anyhow!("{} is not a guild id", guild))?);
+        if !guilds.contains(&guild_id) {
+            return Err(anyhow!("There is no stored state for the guild {}", guild_id))
+        }
+        return Ok(guild_id)
+    }
+    match guilds.as_slice() {
+        [guild_id] => Ok(*guild_id),
+        [] => Err(anyhow!("There is no stored state")),
+        _ => Err(anyhow!(
+            "There is state for several guilds, choose one with --guild: {}",
+            guilds.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Quotes a csv field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+    else {
+        value.to_string()
+    }
+}
+
+/// Lists everything that looks wrong with the stored state of the guild
+fn validate(guild_id: GuildId) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // Only inspected, so that checking doesn't import or otherwise change the state
+    let state = match storage().inspect(guild_id) {
+        Ok(Some(loaded)) => {
+            if let Some(backup) = &loaded.backup {
+                problems.push(format!("The state can't be read, the backup {} is used instead. \
+                    Changes made after it was saved are lost", backup.display()
+                ));
+            }
+            if let Some(path) = &loaded.unimported {
+                problems.push(format!("The state is not yet imported into the database, \
+                    {} is imported when the bot next loads it", path.display()
+                ));
+            }
+            loaded.state
+        }
+        Ok(None) => return vec!("No state is stored".to_string()),
+        Err(e) => return vec!(format!("The state can't be loaded: {:#}", e)),
+    };
+
+    for (user_id, ideas) in &state.theme_ideas {
+        if ideas.is_empty() {
+            problems.push(format!("{} has an empty list of theme ideas", user_id));
+        }
+        if ideas.len() > state.max_theme_ideas {
+            problems.push(format!("{} has {} theme ideas, more than the limit of {}",
+                user_id, ideas.len(), state.max_theme_ideas
+            ));
+        }
+        for idea in ideas {
+            if let Err(rejection) = state.theme_policy.check(&idea.text) {
+                problems.push(format!("A theme idea of {} breaks the theme policy: {}: {}",
+                    user_id, idea.text, rejection
+                ));
+            }
+        }
+    }
+    if state.theme_templates.is_empty() {
+        problems.push("There are no theme templates".to_string());
+    }
+    for template in &state.theme_templates {
+        if let Err(e) = check_template(template) {
+            problems.push(format!("The theme template {} is invalid: {}", template, e));
+        }
+    }
+
+    let mut channels = HashSet::new();
+    for (user_id, team) in &state.channel_creators {
+        if team.game_name().trim().is_empty() {
+            problems.push(format!("The team of {} has no name", user_id));
+        }
+        for channel_id in &[team.category_id(), team.text_id(), team.voice_id()] {
+            if channel_id.0 == 0 {
+                problems.push(format!("The team of {} is missing a channel", user_id));
+            }
+            else if !channels.insert(*channel_id) {
+                problems.push(format!("The channel {} of the team of {} belongs to another team too",
+                    channel_id, user_id
+                ));
+            }
+        }
+    }
+
+    let mut names = HashSet::new();
+    let mut emojis = HashSet::new();
+    for role in &state.requestable_roles {
+        if role.name.trim().is_empty() {
+            problems.push("A requestable role has no name".to_string());
+        }
+        if role.name.to_lowercase() == Config::get().organizer_role.to_lowercase() {
+            problems.push(format!("The organizer role {} is requestable", role.name));
+        }
+        if !names.insert(role.name.to_lowercase()) {
+            problems.push(format!("The role {} is requestable twice", role.name));
+        }
+        if !emojis.insert(&role.emoji) {
+            problems.push(format!("The emoji {} is used for more than one role", role.emoji));
+        }
+    }
+
+    for (capability, holders) in &state.permissions {
+        if Capability::from_name(capability).is_none() {
+            problems.push(format!("There is no permission called {}", capability));
+        }
+        for name in &holders.role_names {
+            problems.push(format!("The role {} with {} has not been found on the server",
+                name, capability
+            ));
+        }
+    }
+
+    if (state.get_role_assign_channel().0 == 0) != (state.get_role_assign_message().0 == 0) {
+        problems.push("The role assignment message is only partly set".to_string());
+    }
+
+    if let Err(e) = storage().load_journal(guild_id) {
+        problems.push(format!("The journal can't be read: {:#}", e));
+    }
+    problems
+}
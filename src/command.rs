@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use tracing::{error, info};
+use twilight::model::{
+    channel::Message,
+    id::GuildId,
+    user::CurrentUser,
+};
+
+use crate::config::Config;
+use crate::discord::DiscordApi;
+use crate::locale::{language, reply, Language, Msg};
+use crate::log_channel::{log_action, Outcome};
+use crate::permission::{capabilities, has_capability, Capability};
+use crate::utils::{Result, send_message};
+
+/// Everything a command needs to know about the message that invoked it
+pub struct CommandContext<'a> {
+    pub msg: &'a Message,
+    pub guild_id: GuildId,
+    pub http: &'a dyn DiscordApi,
+    pub current_user: &'a CurrentUser,
+    pub registry: &'a CommandRegistry,
+}
+
+/**
+  A command that can be issued in a guild channel.
+
+  The name, usage and description are used both for dispatching and for
+  building the help message, so they only need to be written once. The
+  usage and description are in the message catalog, see `locale.rs`.
+*/
+#[async_trait]
+pub trait Command: Send + Sync {
+    /// Name used to invoke the command, without the prefix
+    fn name(&self) -> &'static str;
+
+    /// Other names that also invoke the command
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Arguments shown after the command name in the help message
+    fn usage(&self) -> Option<Msg<'static>> {
+        None
+    }
+
+    /// Shown after the usage in the help message, e.g. "to generate a theme."
+    fn description(&self) -> Msg<'static>;
+
+    /// What the author needs to be allowed to run the command, see `permission.rs`
+    fn required_capability(&self) -> Option<Capability> {
+        None
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()>;
+}
+
+/// Keeps track of all registered commands and dispatches messages to them
+pub struct CommandRegistry {
+    commands: Vec<Arc<dyn Command>>,
+    by_name: HashMap<String, usize>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            by_name: HashMap::new(),
+        }
+    }
+
+    /**
+      Adds a command to the registry. Panics if the name or any of the
+      aliases are already taken, since that is a programming error
+    */
+    pub fn register(&mut self, command: impl Command + 'static) {
+        let index = self.commands.len();
+        let names = std::iter::once(command.name())
+            .chain(command.aliases().iter().cloned());
+        for name in names {
+            let previous = self.by_name.insert(name.to_string(), index);
+            assert!(previous.is_none(), "Command name {} registered twice", name);
+        }
+        self.commands.push(Arc::new(command));
+    }
+
+    /// Looks up a command by its exact name or one of its aliases
+    pub fn find(&self, name: &str) -> Option<&dyn Command> {
+        self.by_name.get(name)
+            .map(|index| &*self.commands[*index])
+    }
+
+    /// Runs the command in the message, if there is one
+    pub async fn handle_message(
+        &self,
+        msg: &Message,
+        http: &dyn DiscordApi,
+        current_user: &CurrentUser,
+    ) -> Result<()> {
+        let mut words = msg.content.split_ascii_whitespace();
+        let first = match words.next() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        let ctx = CommandContext {
+            msg,
+            guild_id: msg.guild_id.expect("Tried to issue a command in non-guild"),
+            http,
+            current_user,
+            registry: self,
+        };
+
+        let prefix = &Config::get().prefix;
+        if first.starts_with(prefix.as_str()) {
+            let name = &first[prefix.len()..];
+            match self.find(name) {
+                Some(command) => {
+                    let args = words.collect::<Vec<_>>();
+                    self.run_command(&ctx, command, &args).await?;
+                }
+                None => {
+                    reply(http, msg.channel_id, ctx.guild_id, msg.author.id,
+                        Msg::UnrecognisedCommand { command: first }
+                    ).await?;
+                    self.send_help(&ctx).await?;
+                }
+            }
+        }
+        // Not a command and probably not for us, unless we were mentioned
+        else if msg.mentions.contains_key(&current_user.id) {
+            self.send_help(&ctx).await?;
+        }
+        Ok(())
+    }
+
+    /**
+      Runs the command if the author may use it. A failure of the command is
+      logged, reported and answered here, so it isn't returned as an error
+      that would be logged again
+    */
+    async fn run_command(
+        &self,
+        ctx: &CommandContext<'_>,
+        command: &dyn Command,
+        args: &[&str],
+    ) -> Result<()> {
+        if let Some(capability) = command.required_capability() {
+            if !has_capability(ctx.http, ctx.guild_id, ctx.msg.author.id, capability).await? {
+                reply(ctx.http, ctx.msg.channel_id, ctx.guild_id, ctx.msg.author.id,
+                    Msg::MissingCapability { capability: capability.name(), command: command.name() }
+                ).await?;
+                info!(
+                    guild = %ctx.guild_id, user = %ctx.msg.author.id,
+                    command = command.name(), capability = capability.name(),
+                    "Tried to use a command without the required capability"
+                );
+                return Ok(());
+            }
+        }
+
+        let start = Instant::now();
+        let result = command.run(ctx, args).await;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        match result {
+            Ok(()) => info!(
+                guild = %ctx.guild_id, user = %ctx.msg.author.id,
+                command = command.name(), latency_ms,
+                "Handled command"
+            ),
+            Err(e) => {
+                error!(
+                    guild = %ctx.guild_id, user = %ctx.msg.author.id,
+                    command = command.name(), latency_ms, error = ?e,
+                    "Command failed"
+                );
+                log_action(ctx.http, ctx.guild_id, ctx.msg.author.id,
+                    format!("{}{} failed", Config::get().prefix, command.name()),
+                    Outcome::Failed(format!("{:?}", e))
+                ).await;
+                // The user would otherwise be left waiting for an answer
+                reply(ctx.http, ctx.msg.channel_id, ctx.guild_id, ctx.msg.author.id,
+                    Msg::CommandFailed { command: command.name() }
+                ).await.ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a help message listing the commands the author has access to
+    pub async fn send_help(&self, ctx: &CommandContext<'_>) -> Result<()> {
+        let language = language(ctx.guild_id, ctx.msg.author.id).await;
+        let mut help_message = Msg::HelpCommands {
+            commands: &self.describe(language, |command| command.required_capability().is_none())
+        }.text(language);
+
+        let capabilities = capabilities(ctx.http, ctx.guild_id, ctx.msg.author.id).await?;
+        let privileged = self.describe(language, |command| {
+            command.required_capability()
+                .map_or(false, |capability| capabilities.contains(&capability))
+        });
+        if !privileged.is_empty() {
+            help_message.push_str("\n\n");
+            help_message.push_str(&Msg::HelpPrivilegedCommands { commands: &privileged }.text(language));
+        }
+
+        send_message(ctx.http, ctx.msg.channel_id, ctx.msg.author.id, help_message).await
+    }
+
+    /// Lists the commands matching the filter, one per line
+    fn describe(&self, language: Language, filter: impl Fn(&dyn Command) -> bool) -> String {
+        let prefix = &Config::get().prefix;
+        self.commands.iter()
+            .filter(|command| filter(&***command))
+            .map(|command| {
+                let invocation = match command.usage() {
+                    Some(usage) => format!("{}{} {}", prefix, command.name(), usage.text(language)),
+                    None => format!("{}{}", prefix, command.name()),
+                };
+                format!("- `{}` {}", invocation, command.description().text(language))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Shows the commands available to the author
+pub struct Help;
+
+#[async_trait]
+impl Command for Help {
+    fn name(&self) -> &'static str { "help" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeHelp }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        ctx.registry.send_help(ctx).await
+    }
+}
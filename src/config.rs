@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use once_cell::sync::OnceCell;
+use serde_derive::{Serialize, Deserialize};
+use twilight::model::id::UserId;
+
+use crate::locale::Language;
+use crate::permission::Capability;
+use crate::theme_policy::ThemePolicy;
+use crate::theme_template::check_template;
+use crate::utils::Result;
+
+pub const FILENAME: &'static str = "gamejambot.toml";
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// A role that users can give themselves
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoleConfig {
+    pub name: String,
+    /// Reacting with this emoji on the role assignment message gives the role
+    pub emoji: String,
+}
+
+/**
+  Roles and users that have been given a capability, see `permission.rs`.
+  Roles are given by name here, they are looked up on each server when the
+  bot connects to it
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PermissionHolders {
+    pub roles: Vec<String>,
+    pub users: Vec<UserId>,
+}
+
+/**
+  Names of the channels created for each team. `{game}` is replaced by the
+  name of the game
+*/
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ChannelNames {
+    pub category: String,
+    pub text: String,
+    pub voice: String,
+    pub topic: String,
+}
+
+impl Default for ChannelNames {
+    fn default() -> Self {
+        Self {
+            category: "Team: {game}".to_string(),
+            text: "{game}".to_string(),
+            voice: "{game}".to_string(),
+            topic: "Work on and playtesting of the game {game}.".to_string(),
+        }
+    }
+}
+
+impl ChannelNames {
+    pub fn category(&self, game: &str) -> String {
+        self.category.replace("{game}", game)
+    }
+
+    pub fn text(&self, game: &str) -> String {
+        self.text.replace("{game}", game)
+    }
+
+    pub fn voice(&self, game: &str) -> String {
+        self.voice.replace("{game}", game)
+    }
+
+    pub fn topic(&self, game: &str) -> String {
+        self.topic.replace("{game}", game)
+    }
+}
+
+/// Where and how much to log, see `logging.rs`
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Filter such as `info` or `gamejambot=debug,warn`. Overridden by the
+    /// `RUST_LOG` environment variable
+    pub level: String,
+    /// Directory of the log files, which are rotated daily
+    pub directory: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            directory: "logs".to_string(),
+        }
+    }
+}
+
+/// Kinds of storage the state can be kept in, see `storage.rs`
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+/// Where the state of each guild is kept
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: Backend,
+    /// Path of the database when the backend is sqlite
+    pub database: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Json,
+            database: "state.sqlite".to_string(),
+        }
+    }
+}
+
+/// Limits on theme ideas, see `theme.rs`
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Number of ideas each user can have at once, until organizers change it
+    /// with the themelimit command
+    pub max_ideas: usize,
+    /// What ideas have to look like, until organizers change it with the
+    /// themepolicy command
+    pub policy: ThemePolicy,
+    /// What generated themes look like, until organizers change them with
+    /// the themetemplates command
+    pub templates: Vec<String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            max_ideas: 3,
+            policy: ThemePolicy::default(),
+            // Two ideas of any kind, the way themes were always generated
+            templates: vec!("{any} {any}".to_string()),
+        }
+    }
+}
+
+/// How failed discord requests are retried, see `retry.rs`
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Times a request is made at most, including the first one
+    pub attempts: u32,
+    /// Wait after the first failure, doubled for every failure after it
+    pub base_delay_ms: u64,
+    /// Longest wait between two attempts
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            base_delay_ms: 250,
+            max_delay_ms: 4000,
+        }
+    }
+}
+
+/**
+  Settings read from `gamejambot.toml` at startup. Everything has a default,
+  so the file is optional
+*/
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Prefix that all text commands start with
+    pub prefix: String,
+    /// Role that has every capability
+    pub organizer_role: String,
+    /// Language replies are in on servers whose organizers haven't chosen one
+    pub language: Language,
+    pub channels: ChannelNames,
+    pub themes: ThemeConfig,
+    /// Roles that users can give themselves, until organizers change them
+    /// with the role commands
+    pub roles: Vec<RoleConfig>,
+    /// Who has each capability besides the organizer role, keyed by the
+    /// name of the capability. Organizers can change it with the permission
+    /// commands
+    pub permissions: HashMap<String, PermissionHolders>,
+    pub log: LogConfig,
+    pub retry: RetryConfig,
+    pub storage: StorageConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let role = |name: &str, emoji: &str| RoleConfig {
+            name: name.to_string(),
+            emoji: emoji.to_string(),
+        };
+        Self {
+            prefix: "!".to_string(),
+            organizer_role: "Organizer".to_string(),
+            language: Language::En,
+            channels: ChannelNames::default(),
+            themes: ThemeConfig::default(),
+            log: LogConfig::default(),
+            retry: RetryConfig::default(),
+            storage: StorageConfig::default(),
+            permissions: HashMap::new(),
+            roles: vec!(
+                role("Programmer", "💻"),
+                role("2D Artist", "🎨"),
+                role("3D Artist", "🗿"),
+                role("Sound Designer", "🔊"),
+                role("Musician", "🎵"),
+                role("Idea Guy", "💡"),
+                role("Board Games", "🎲"),
+                role("Play Tester", "🎮"),
+            ),
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the config file. Must be called once at startup
+    pub fn init() -> Result<()> {
+        let config = if Path::new(FILENAME).exists() {
+            let content = fs::read_to_string(FILENAME)
+                .with_context(|| format!("Failed to read {}", FILENAME))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", FILENAME))?
+        }
+        else {
+            Config::default()
+        };
+        config.validate()
+            .with_context(|| format!("Invalid {}", FILENAME))?;
+        CONFIG.set(config).map_err(|_| anyhow!("The config was loaded twice"))?;
+        Ok(())
+    }
+
+    /// Uses the default config unless one was loaded already, for tests
+    #[cfg(test)]
+    pub fn init_default() {
+        CONFIG.get_or_init(Config::default);
+    }
+
+    /// The config loaded at startup
+    pub fn get() -> &'static Config {
+        CONFIG.get().expect("The config was used before it was loaded")
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.prefix.is_empty() || self.prefix.contains(char::is_whitespace) {
+            return Err(anyhow!("The prefix must be non-empty and contain no whitespace"));
+        }
+        if self.organizer_role.trim().is_empty() {
+            return Err(anyhow!("The organizer role must have a name"));
+        }
+        for (name, template) in &[
+            ("category", &self.channels.category),
+            ("text", &self.channels.text),
+            ("voice", &self.channels.voice),
+        ] {
+            if !template.contains("{game}") {
+                return Err(anyhow!("The {} channel name must contain {{game}}", name));
+            }
+        }
+        if self.themes.max_ideas == 0 {
+            return Err(anyhow!("Users must be allowed at least one theme idea"));
+        }
+        if self.themes.policy.max_words == 0 || self.themes.policy.max_length == 0 {
+            return Err(anyhow!("Theme ideas must be allowed at least one word and character"));
+        }
+        if self.themes.templates.is_empty() {
+            return Err(anyhow!("There must be at least one theme template"));
+        }
+        // The messages of template errors need the config, so they can't be used here
+        for template in &self.themes.templates {
+            check_template(template)
+                .map_err(|e| anyhow!("The theme template {} is invalid: {:?}", template, e))?;
+        }
+        if self.retry.attempts == 0 {
+            return Err(anyhow!("Requests must be attempted at least once"));
+        }
+        if self.retry.base_delay_ms > self.retry.max_delay_ms {
+            return Err(anyhow!("The base retry delay can not be longer than the maximum"));
+        }
+        if self.storage.backend == Backend::Sqlite && self.storage.database.trim().is_empty() {
+            return Err(anyhow!("The path of the database can not be empty"));
+        }
+
+        for (capability, holders) in &self.permissions {
+            if Capability::from_name(capability).is_none() {
+                return Err(anyhow!("There is no permission called {}", capability));
+            }
+            if holders.roles.iter().any(|role| role.trim().is_empty()) {
+                return Err(anyhow!("The roles with {} must have names", capability));
+            }
+        }
+
+        let mut names = HashSet::new();
+        let mut emojis = HashSet::new();
+        for role in &self.roles {
+            if role.name.trim().is_empty() {
+                return Err(anyhow!("Roles must have a name"));
+            }
+            if role.name.to_lowercase() == self.organizer_role.to_lowercase() {
+                return Err(anyhow!("The organizer role can not be requestable"));
+            }
+            if !names.insert(role.name.to_lowercase()) {
+                return Err(anyhow!("The role {} is listed twice", role.name));
+            }
+            if role.emoji.trim().is_empty() {
+                return Err(anyhow!("The role {} has no emoji", role.name));
+            }
+            if !emojis.insert(&role.emoji) {
+                return Err(anyhow!("The emoji {} is used for more than one role", role.emoji));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,448 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use twilight::{
+    cache::InMemoryCache,
+    http::{
+        Client as HttpClient,
+        error::{
+            Error as DiscordError,
+            ResponseError,
+        },
+    },
+    model::{
+        channel::{
+            Channel, ChannelType, GuildChannel, Message,
+            embed::Embed,
+            permission_overwrite::PermissionOverwrite,
+        },
+        guild::Role,
+        id::{ChannelId, GuildId, MessageId, RoleId, UserId},
+    },
+};
+
+use crate::utils::Result;
+
+#[cfg(test)]
+pub mod fake;
+
+/// Properties of a channel that is about to be created or updated
+#[derive(Clone, Debug)]
+pub struct ChannelSpec {
+    pub name: String,
+    pub kind: ChannelType,
+    pub parent_id: Option<ChannelId>,
+    pub topic: Option<String>,
+    pub permission_overwrites: Option<Vec<PermissionOverwrite>>,
+}
+
+impl ChannelSpec {
+    pub fn new(name: impl Into<String>, kind: ChannelType) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            parent_id: None,
+            topic: None,
+            permission_overwrites: None,
+        }
+    }
+}
+
+/// The id of a guild channel, whatever kind it is
+pub fn channel_id(channel: &GuildChannel) -> ChannelId {
+    match channel {
+        GuildChannel::Category(c) => c.id,
+        GuildChannel::Text(c) => c.id,
+        GuildChannel::Voice(c) => c.id,
+    }
+}
+
+/**
+  The parts of the Discord HTTP API that the bot uses.
+
+  Handlers only talk to Discord through this trait so that they don't
+  depend on a live connection.
+*/
+#[async_trait]
+pub trait DiscordApi: Send + Sync {
+    async fn channel(&self, channel_id: ChannelId) -> Result<Option<Channel>>;
+
+    async fn roles(&self, guild_id: GuildId) -> Result<Vec<Role>>;
+
+    /// The roles of the member, or None if the user isn't a member of the guild
+    async fn guild_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<Vec<RoleId>>>;
+
+    async fn create_guild_channel(
+        &self,
+        guild_id: GuildId,
+        spec: ChannelSpec,
+    ) -> Result<GuildChannel>;
+
+    async fn update_channel(
+        &self,
+        channel_id: ChannelId,
+        spec: ChannelSpec,
+    ) -> Result<Channel>;
+
+    async fn delete_channel(&self, channel_id: ChannelId) -> Result<Channel>;
+
+    async fn create_message(
+        &self,
+        channel_id: ChannelId,
+        content: String,
+    ) -> Result<Message>;
+
+    /// Sends a message with an embed, and optionally some text above it
+    async fn create_embed(
+        &self,
+        channel_id: ChannelId,
+        content: Option<String>,
+        embed: Embed,
+    ) -> Result<Message>;
+
+    async fn message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Result<Option<Message>>;
+
+    async fn create_reaction(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: String,
+    ) -> Result<()>;
+
+    async fn create_role(&self, guild_id: GuildId, name: String) -> Result<Role>;
+
+    async fn add_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()>;
+
+    async fn remove_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()>;
+}
+
+/**
+  Converts a twilight error into an anyhow error, including the body of the
+  response for client side errors since that is where discord explains what
+  was wrong with the request
+*/
+async fn describe_error(e: DiscordError) -> anyhow::Error {
+    match e {
+        DiscordError::Response{source: ResponseError::Client{response: r}} => {
+            anyhow!("The response was a client side error: {}",
+                match r.text().await {
+                    Ok(text) => text,
+                    _ => "(Response unavailable)".to_string(),
+                }
+            )
+        }
+        e => e.into(),
+    }
+}
+
+/// Whether discord answered the request with a server side error
+pub fn is_server_error(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<DiscordError>() {
+        Some(DiscordError::Response{source: ResponseError::Server{..}}) => true,
+        _ => false,
+    }
+}
+
+/**
+  Whether a failed request may succeed if it is sent again, because discord
+  had a problem or the request never got an answer. Client side errors are
+  turned into plain messages by `describe_error`, so they never are
+*/
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<DiscordError>() {
+        Some(DiscordError::RequestError{..}) => true,
+        _ => is_server_error(e),
+    }
+}
+
+#[async_trait]
+impl DiscordApi for HttpClient {
+    async fn channel(&self, channel_id: ChannelId) -> Result<Option<Channel>> {
+        Ok(HttpClient::channel(self, channel_id).await?)
+    }
+
+    async fn roles(&self, guild_id: GuildId) -> Result<Vec<Role>> {
+        Ok(HttpClient::roles(self, guild_id).await?)
+    }
+
+    async fn guild_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<Vec<RoleId>>> {
+        Ok(HttpClient::guild_member(self, guild_id, user_id).await?.map(|member| member.roles))
+    }
+
+    async fn create_guild_channel(
+        &self,
+        guild_id: GuildId,
+        spec: ChannelSpec,
+    ) -> Result<GuildChannel> {
+        let mut request = HttpClient::create_guild_channel(self, guild_id, spec.name)
+            .kind(spec.kind);
+        if let Some(parent_id) = spec.parent_id {
+            request = request.parent_id(parent_id);
+        }
+        if let Some(topic) = spec.topic {
+            request = request.topic(topic);
+        }
+        if let Some(overwrites) = spec.permission_overwrites {
+            request = request.permission_overwrites(overwrites);
+        }
+        match request.await {
+            Ok(channel) => Ok(channel),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn update_channel(
+        &self,
+        channel_id: ChannelId,
+        spec: ChannelSpec,
+    ) -> Result<Channel> {
+        let mut request = HttpClient::update_channel(self, channel_id)
+            .kind(spec.kind)
+            .name(spec.name);
+        if let Some(parent_id) = spec.parent_id {
+            request = request.parent_id(parent_id);
+        }
+        if let Some(topic) = spec.topic {
+            request = request.topic(topic);
+        }
+        if let Some(overwrites) = spec.permission_overwrites {
+            request = request.permission_overwrites(overwrites);
+        }
+        match request.await {
+            Ok(channel) => Ok(channel),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn delete_channel(&self, channel_id: ChannelId) -> Result<Channel> {
+        match HttpClient::delete_channel(self, channel_id).await {
+            Ok(channel) => Ok(channel),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn create_message(
+        &self,
+        channel_id: ChannelId,
+        content: String,
+    ) -> Result<Message> {
+        match HttpClient::create_message(self, channel_id).content(content).await {
+            Ok(message) => Ok(message),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn create_embed(
+        &self,
+        channel_id: ChannelId,
+        content: Option<String>,
+        embed: Embed,
+    ) -> Result<Message> {
+        let mut request = HttpClient::create_message(self, channel_id).embed(embed);
+        if let Some(content) = content {
+            request = request.content(content);
+        }
+        match request.await {
+            Ok(message) => Ok(message),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Result<Option<Message>> {
+        Ok(HttpClient::message(self, channel_id, message_id).await?)
+    }
+
+    async fn create_reaction(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: String,
+    ) -> Result<()> {
+        match HttpClient::create_reaction(self, channel_id, message_id, emoji).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn create_role(&self, guild_id: GuildId, name: String) -> Result<Role> {
+        match HttpClient::create_role(self, guild_id).name(name).await {
+            Ok(role) => Ok(role),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn add_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        match HttpClient::add_guild_member_role(self, guild_id, user_id, role_id).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+
+    async fn remove_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        match HttpClient::remove_guild_member_role(self, guild_id, user_id, role_id).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(describe_error(e).await),
+        }
+    }
+}
+
+/**
+  The HTTP API with roles, members and channels read from the cache of
+  gateway events instead when it has them, so that permission checks don't
+  cost requests. Anything the cache doesn't have is fetched over HTTP
+*/
+pub struct CachedClient {
+    pub http: HttpClient,
+    pub cache: InMemoryCache,
+}
+
+impl CachedClient {
+    /// All roles of the guild, or None unless the cache has every one of them
+    async fn cached_roles(&self, guild_id: GuildId) -> Option<Vec<Role>> {
+        let role_ids = self.cache.guild_roles(guild_id).await.ok()??;
+        let mut roles = Vec::new();
+        for role_id in role_ids {
+            roles.push((*self.cache.role(role_id).await.ok()??).clone());
+        }
+        Some(roles)
+    }
+}
+
+#[async_trait]
+impl DiscordApi for CachedClient {
+    async fn channel(&self, channel_id: ChannelId) -> Result<Option<Channel>> {
+        if let Ok(Some(channel)) = self.cache.guild_channel(channel_id).await {
+            return Ok(Some(Channel::Guild((*channel).clone())))
+        }
+        DiscordApi::channel(&self.http, channel_id).await
+    }
+
+    async fn roles(&self, guild_id: GuildId) -> Result<Vec<Role>> {
+        match self.cached_roles(guild_id).await {
+            Some(roles) => Ok(roles),
+            None => DiscordApi::roles(&self.http, guild_id).await,
+        }
+    }
+
+    async fn guild_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<Vec<RoleId>>> {
+        if let Ok(Some(member)) = self.cache.member(guild_id, user_id).await {
+            return Ok(Some(member.roles.clone()))
+        }
+        DiscordApi::guild_member_roles(&self.http, guild_id, user_id).await
+    }
+
+    async fn create_guild_channel(
+        &self,
+        guild_id: GuildId,
+        spec: ChannelSpec,
+    ) -> Result<GuildChannel> {
+        DiscordApi::create_guild_channel(&self.http, guild_id, spec).await
+    }
+
+    async fn update_channel(
+        &self,
+        channel_id: ChannelId,
+        spec: ChannelSpec,
+    ) -> Result<Channel> {
+        DiscordApi::update_channel(&self.http, channel_id, spec).await
+    }
+
+    async fn delete_channel(&self, channel_id: ChannelId) -> Result<Channel> {
+        DiscordApi::delete_channel(&self.http, channel_id).await
+    }
+
+    async fn create_message(
+        &self,
+        channel_id: ChannelId,
+        content: String,
+    ) -> Result<Message> {
+        DiscordApi::create_message(&self.http, channel_id, content).await
+    }
+
+    async fn create_embed(
+        &self,
+        channel_id: ChannelId,
+        content: Option<String>,
+        embed: Embed,
+    ) -> Result<Message> {
+        DiscordApi::create_embed(&self.http, channel_id, content, embed).await
+    }
+
+    async fn message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Result<Option<Message>> {
+        DiscordApi::message(&self.http, channel_id, message_id).await
+    }
+
+    async fn create_reaction(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: String,
+    ) -> Result<()> {
+        DiscordApi::create_reaction(&self.http, channel_id, message_id, emoji).await
+    }
+
+    async fn create_role(&self, guild_id: GuildId, name: String) -> Result<Role> {
+        DiscordApi::create_role(&self.http, guild_id, name).await
+    }
+
+    async fn add_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        DiscordApi::add_guild_member_role(&self.http, guild_id, user_id, role_id).await
+    }
+
+    async fn remove_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        DiscordApi::remove_guild_member_role(&self.http, guild_id, user_id, role_id).await
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use twilight::model::{
+    channel::{Channel, ChannelType, GuildChannel, Message, embed::Embed},
+    guild::Role,
+    id::{ChannelId, GuildId, MessageId, RoleId, UserId},
+    user::User,
+};
+
+use crate::config::Config;
+use crate::discord::{ChannelSpec, DiscordApi};
+use crate::utils::Result;
+
+/// A request made to the fake, with what it was made with
+#[derive(Clone, Debug)]
+// Tests only look at the parts they care about, the rest is for failure messages
+#[allow(dead_code)]
+pub enum Call {
+    Channel(ChannelId),
+    Roles(GuildId),
+    GuildMember(GuildId, UserId),
+    CreateGuildChannel(GuildId, ChannelSpec),
+    UpdateChannel(ChannelId, ChannelSpec),
+    DeleteChannel(ChannelId),
+    CreateMessage(ChannelId, String),
+    CreateEmbed(ChannelId, Option<String>, Embed),
+    Message(ChannelId, MessageId),
+    CreateReaction(ChannelId, MessageId, String),
+    CreateRole(GuildId, String),
+    AddGuildMemberRole(GuildId, UserId, RoleId),
+    RemoveGuildMemberRole(GuildId, UserId, RoleId),
+}
+
+/// Decides whether a call fails
+type Failure = Box<dyn Fn(&Call) -> bool + Send>;
+
+#[derive(Default)]
+struct Server {
+    calls: Vec<Call>,
+    failures: Vec<Failure>,
+    next_id: u64,
+    roles: HashMap<GuildId, Vec<Role>>,
+    /// The roles of each member
+    members: HashMap<(GuildId, UserId), Vec<RoleId>>,
+    /// The guild, name and kind of each channel
+    channels: HashMap<ChannelId, (GuildId, String, ChannelType)>,
+}
+
+impl Server {
+    fn id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/**
+  Discord kept in memory, for testing handlers. It remembers every call
+  made to it, and can be told to fail calls.
+
+  Models are built from the json discord would send, so they come out of
+  twilight the same way as real responses do
+*/
+pub struct FakeDiscord {
+    server: Mutex<Server>,
+}
+
+impl FakeDiscord {
+    pub fn new() -> Self {
+        Self {
+            server: Mutex::new(Server {
+                // Leave room for ids that tests pick themselves
+                next_id: 1_000_000,
+                ..Server::default()
+            }),
+        }
+    }
+
+    /// Makes every later call that matches the predicate fail
+    pub fn fail_on(&self, predicate: impl Fn(&Call) -> bool + Send + 'static) {
+        self.server.lock().unwrap().failures.push(Box::new(predicate));
+    }
+
+    /// All calls made so far, including the ones that failed
+    pub fn calls(&self) -> Vec<Call> {
+        self.server.lock().unwrap().calls.clone()
+    }
+
+    /// The messages sent to the channel, with and without embeds
+    pub fn messages(&self, channel_id: ChannelId) -> Vec<String> {
+        self.calls().into_iter().filter_map(|call| match call {
+            Call::CreateMessage(id, content) if id == channel_id => Some(content),
+            Call::CreateEmbed(id, content, embed) if id == channel_id => Some(format!("{}{}",
+                content.unwrap_or_default(),
+                embed.description.unwrap_or_default()
+            )),
+            _ => None,
+        }).collect()
+    }
+
+    /// Adds a role to the guild, returning its id
+    pub fn add_role(&self, guild_id: GuildId, name: &str) -> RoleId {
+        let mut server = self.server.lock().unwrap();
+        let role = role(RoleId(server.id()), name);
+        let id = role.id;
+        server.roles.entry(guild_id).or_default().push(role);
+        id
+    }
+
+    /// Renames the role, keeping its id
+    pub fn rename_role(&self, guild_id: GuildId, role_id: RoleId, name: &str) {
+        let mut server = self.server.lock().unwrap();
+        let roles = server.roles.entry(guild_id).or_default();
+        if let Some(existing) = roles.iter_mut().find(|existing| existing.id == role_id) {
+            *existing = role(role_id, name);
+        }
+    }
+
+    /// Makes the user a member of the guild with the roles
+    pub fn add_member(&self, guild_id: GuildId, user_id: UserId, roles: &[RoleId]) {
+        self.server.lock().unwrap().members.insert((guild_id, user_id), roles.to_vec());
+    }
+
+    /// The roles the member has, or None if the user isn't a member
+    pub fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Option<Vec<RoleId>> {
+        self.server.lock().unwrap().members.get(&(guild_id, user_id)).cloned()
+    }
+
+    /// The name of the channel, if it exists
+    pub fn channel_name(&self, channel_id: ChannelId) -> Option<String> {
+        self.server.lock().unwrap().channels.get(&channel_id).map(|(_, name, _)| name.clone())
+    }
+
+    /// The number of channels that exist in the guild
+    pub fn channel_count(&self, guild_id: GuildId) -> usize {
+        self.server.lock().unwrap().channels.values()
+            .filter(|(guild, _, _)| *guild == guild_id)
+            .count()
+    }
+
+    /// Records the call, and fails if it was asked to
+    fn call(&self, call: Call) -> Result<()> {
+        let mut server = self.server.lock().unwrap();
+        let fails = server.failures.iter().any(|failure| failure(&call));
+        server.calls.push(call.clone());
+        if fails {
+            Err(anyhow!("Injected failure of {:?}", call))
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+fn user(id: UserId) -> Value {
+    json!({
+        "id": id.to_string(),
+        "username": format!("user{}", id),
+        "discriminator": "0001",
+        "avatar": null,
+        "bot": false,
+    })
+}
+
+fn role(id: RoleId, name: &str) -> Role {
+    serde_json::from_value(json!({
+        "id": id.to_string(),
+        "name": name,
+        "color": 0,
+        "hoist": false,
+        "managed": false,
+        "mentionable": false,
+        "permissions": 0,
+        "position": 1,
+    })).unwrap()
+}
+
+fn channel_type(kind: ChannelType) -> u8 {
+    match kind {
+        ChannelType::GuildText => 0,
+        ChannelType::Private => 1,
+        ChannelType::GuildVoice => 2,
+        ChannelType::Group => 3,
+        ChannelType::GuildCategory => 4,
+        ChannelType::GuildNews => 5,
+        ChannelType::GuildStore => 6,
+    }
+}
+
+fn guild_channel(id: ChannelId, guild_id: GuildId, name: &str, kind: ChannelType) -> GuildChannel {
+    serde_json::from_value(json!({
+        "id": id.to_string(),
+        "guild_id": guild_id.to_string(),
+        "type": channel_type(kind),
+        "name": name,
+        "permission_overwrites": [],
+        "position": 0,
+        "parent_id": null,
+        "topic": null,
+        "nsfw": false,
+        "last_message_id": null,
+        "rate_limit_per_user": 0,
+        "bitrate": 64000,
+        "user_limit": 0,
+    })).unwrap()
+}
+
+/// A message from the user in a channel, as the handlers get them
+pub fn test_message(channel_id: ChannelId, author: UserId, content: &str) -> Message {
+    message(MessageId(1), channel_id, author, content)
+}
+
+fn message(id: MessageId, channel_id: ChannelId, author: UserId, content: &str) -> Message {
+    serde_json::from_value(json!({
+        "id": id.to_string(),
+        "channel_id": channel_id.to_string(),
+        "guild_id": null,
+        "author": user(author),
+        "content": content,
+        "timestamp": "2020-04-01T12:00:00+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "reactions": [],
+        "pinned": false,
+        "type": 0,
+    })).unwrap()
+}
+
+#[async_trait]
+impl DiscordApi for FakeDiscord {
+    async fn channel(&self, channel_id: ChannelId) -> Result<Option<Channel>> {
+        self.call(Call::Channel(channel_id))?;
+        let server = self.server.lock().unwrap();
+        Ok(server.channels.get(&channel_id).map(|(guild_id, name, kind)| {
+            Channel::Guild(guild_channel(channel_id, *guild_id, name, *kind))
+        }))
+    }
+
+    async fn roles(&self, guild_id: GuildId) -> Result<Vec<Role>> {
+        self.call(Call::Roles(guild_id))?;
+        Ok(self.server.lock().unwrap().roles.get(&guild_id).cloned().unwrap_or_default())
+    }
+
+    async fn guild_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<Vec<RoleId>>> {
+        self.call(Call::GuildMember(guild_id, user_id))?;
+        Ok(self.member_roles(guild_id, user_id))
+    }
+
+    async fn create_guild_channel(
+        &self,
+        guild_id: GuildId,
+        spec: ChannelSpec,
+    ) -> Result<GuildChannel> {
+        self.call(Call::CreateGuildChannel(guild_id, spec.clone()))?;
+        let mut server = self.server.lock().unwrap();
+        let id = ChannelId(server.id());
+        server.channels.insert(id, (guild_id, spec.name.clone(), spec.kind));
+        Ok(guild_channel(id, guild_id, &spec.name, spec.kind))
+    }
+
+    async fn update_channel(
+        &self,
+        channel_id: ChannelId,
+        spec: ChannelSpec,
+    ) -> Result<Channel> {
+        self.call(Call::UpdateChannel(channel_id, spec.clone()))?;
+        let mut server = self.server.lock().unwrap();
+        let (guild_id, name, _) = server.channels.get_mut(&channel_id)
+            .ok_or_else(|| anyhow!("Unknown channel {}", channel_id))?;
+        *name = spec.name.clone();
+        Ok(Channel::Guild(guild_channel(channel_id, *guild_id, &spec.name, spec.kind)))
+    }
+
+    async fn delete_channel(&self, channel_id: ChannelId) -> Result<Channel> {
+        self.call(Call::DeleteChannel(channel_id))?;
+        let (guild_id, name, kind) = self.server.lock().unwrap().channels.remove(&channel_id)
+            .ok_or_else(|| anyhow!("Unknown channel {}", channel_id))?;
+        Ok(Channel::Guild(guild_channel(channel_id, guild_id, &name, kind)))
+    }
+
+    async fn create_message(
+        &self,
+        channel_id: ChannelId,
+        content: String,
+    ) -> Result<Message> {
+        self.call(Call::CreateMessage(channel_id, content.clone()))?;
+        let id = MessageId(self.server.lock().unwrap().id());
+        Ok(message(id, channel_id, UserId(1), &content))
+    }
+
+    async fn create_embed(
+        &self,
+        channel_id: ChannelId,
+        content: Option<String>,
+        embed: Embed,
+    ) -> Result<Message> {
+        self.call(Call::CreateEmbed(channel_id, content.clone(), embed))?;
+        let id = MessageId(self.server.lock().unwrap().id());
+        Ok(message(id, channel_id, UserId(1), &content.unwrap_or_default()))
+    }
+
+    async fn message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Result<Option<Message>> {
+        self.call(Call::Message(channel_id, message_id))?;
+        Ok(Some(message(message_id, channel_id, UserId(1), "")))
+    }
+
+    async fn create_reaction(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: String,
+    ) -> Result<()> {
+        self.call(Call::CreateReaction(channel_id, message_id, emoji))
+    }
+
+    async fn create_role(&self, guild_id: GuildId, name: String) -> Result<Role> {
+        self.call(Call::CreateRole(guild_id, name.clone()))?;
+        let id = self.add_role(guild_id, &name);
+        Ok(role(id, &name))
+    }
+
+    async fn add_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        self.call(Call::AddGuildMemberRole(guild_id, user_id, role_id))?;
+        let mut server = self.server.lock().unwrap();
+        let roles = server.members.get_mut(&(guild_id, user_id))
+            .ok_or_else(|| anyhow!("Unknown member {}", user_id))?;
+        if !roles.contains(&role_id) {
+            roles.push(role_id);
+        }
+        Ok(())
+    }
+
+    async fn remove_guild_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        self.call(Call::RemoveGuildMemberRole(guild_id, user_id, role_id))?;
+        let mut server = self.server.lock().unwrap();
+        let roles = server.members.get_mut(&(guild_id, user_id))
+            .ok_or_else(|| anyhow!("Unknown member {}", user_id))?;
+        roles.retain(|id| *id != role_id);
+        Ok(())
+    }
+}
+
+/**
+  A guild that no other test uses, since the state of each guild is global.
+  Also loads the default config, which handlers need
+*/
+pub fn test_guild() -> GuildId {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT_GUILD: AtomicU64 = AtomicU64::new(1);
+    Config::init_default();
+    GuildId(NEXT_GUILD.fetch_add(1, Ordering::SeqCst))
+}
+
+/// A user that isn't a bot
+pub fn test_user(id: UserId) -> User {
+    serde_json::from_value(user(id)).unwrap()
+}
@@ -0,0 +1,352 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_derive::{Serialize, Deserialize};
+use tracing::warn;
+use twilight::model::{
+    id::{ChannelId, GuildId, UserId},
+    user::User,
+};
+
+use crate::channel::{delete_restored_channels, restore_team_channels, Team};
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::config::RoleConfig;
+use crate::discord::DiscordApi;
+use crate::locale::{language, Language, Msg};
+use crate::log_channel::{log_action, Outcome};
+use crate::permission::Capability;
+use crate::state::PersistentState;
+use crate::storage::{storage, Change};
+use crate::utils::{Result, send_message};
+
+/// What is needed to take back a destructive change
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Undo {
+    /// The team of the user was removed
+    Team(UserId, Team),
+    /// All of these teams were removed at once
+    Teams(HashMap<UserId, Team>),
+    /// The role stopped being requestable
+    RequestableRole(RoleConfig),
+}
+
+/**
+  An entry in the journal of a guild. Every change to the state of a guild
+  is appended to its journal, see `Storage::append_journal`. Entries are
+  never changed or removed, undoing a change adds a new entry instead
+*/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JournalEntry {
+    /// When the change was made, in RFC 3339
+    pub time: String,
+    /// The user that made the change
+    pub actor: UserId,
+    /// Description of the change
+    pub action: String,
+    /// How to take the change back, if it was destructive
+    #[serde(default)]
+    pub undo: Option<Undo>,
+    /// The index in the journal of the entry that this entry undid
+    #[serde(default)]
+    pub undid: Option<usize>,
+}
+
+impl PersistentState {
+    /// Appends a change to the journal of the guild. Call after saving the change
+    pub fn record(
+        &self,
+        actor: UserId,
+        action: impl Into<String>,
+        undo: Option<Undo>
+    ) -> Result<()> {
+        storage().append_journal(self.guild_id(), &JournalEntry {
+            time: Utc::now().to_rfc3339(),
+            actor,
+            action: action.into(),
+            undo,
+            undid: None,
+        })
+    }
+
+    /**
+      Takes back the destructive change of the journal entry at the index.
+      Parts that would overwrite something that has changed since are
+      skipped. Returns a description of what was restored in the language,
+      the journal gets it in english.
+
+      If the change can't be saved and journaled, the state is put back the
+      way it was
+    */
+    fn apply_undo(
+        &mut self,
+        actor: UserId,
+        index: usize,
+        undo: Undo,
+        language: Language,
+    ) -> Result<String> {
+        let teams = self.channel_creators.clone();
+        let roles = self.requestable_roles.clone();
+        let (change, description) = match &undo {
+            Undo::Team(user_id, team) => {
+                if self.channel_creators.contains_key(user_id) {
+                    return Err(anyhow!(
+                        Msg::TeamNotRestored { game: team.game_name(), user: *user_id }.text(language)
+                    ))
+                }
+                self.channel_creators.insert(*user_id, team.clone());
+                (
+                    Change::Team(*user_id),
+                    vec!(Msg::TeamRestored { game: team.game_name(), user: *user_id })
+                )
+            }
+            Undo::Teams(teams) => {
+                let mut skipped = 0;
+                for (user_id, team) in teams {
+                    if self.channel_creators.contains_key(user_id) {
+                        skipped += 1;
+                    }
+                    else {
+                        self.channel_creators.insert(*user_id, team.clone());
+                    }
+                }
+                let mut description = vec!(Msg::TeamsRestored { count: teams.len() - skipped });
+                if skipped > 0 {
+                    description.push(Msg::TeamsKept { count: skipped });
+                }
+                (Change::AllTeams, description)
+            }
+            Undo::RequestableRole(role) => {
+                if let Some(existing) = self.requestable_role(&role.name) {
+                    return Err(anyhow!(
+                        Msg::RoleAlreadyRequestable { role: &existing.name }.text(language)
+                    ))
+                }
+                if let Some(existing) = self.role_for_emoji(&role.emoji) {
+                    return Err(anyhow!(Msg::RoleNotRestored {
+                        role: &role.name,
+                        emoji: &role.emoji,
+                        used_by: &existing.name,
+                    }.text(language)))
+                }
+                self.requestable_roles.push(role.clone());
+                (
+                    Change::All,
+                    vec!(Msg::RoleRestored { role: &role.name, emoji: &role.emoji })
+                )
+            }
+        };
+        let text = |language| description.iter()
+            .map(|msg| msg.text(language))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let saved = self.save_change(change).and_then(|_| {
+            storage().append_journal(self.guild_id(), &JournalEntry {
+                time: Utc::now().to_rfc3339(),
+                actor,
+                action: format!("Undo: {}", text(Language::En)),
+                undo: None,
+                undid: Some(index),
+            })
+        });
+        if let Err(e) = saved {
+            self.channel_creators = teams;
+            self.requestable_roles = roles;
+            // The change may have been saved without being journaled
+            self.save_change(change).ok();
+            return Err(e)
+        }
+        Ok(text(language))
+    }
+}
+
+/// The latest destructive entry in the journal that hasn't been undone yet
+fn last_undoable(entries: &[JournalEntry]) -> Option<(usize, &JournalEntry)> {
+    let undone = entries.iter()
+        .filter_map(|entry| entry.undid)
+        .collect::<HashSet<_>>();
+    entries.iter()
+        .enumerate()
+        .rev()
+        .find(|(index, entry)| entry.undo.is_some() && !undone.contains(index))
+}
+
+pub async fn handle_undo(
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    current_user_id: UserId,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let language = language(guild_id, author.id).await;
+    let entries = storage().load_journal(guild_id)?;
+    let (index, entry) = match last_undoable(&entries) {
+        Some((index, entry)) => (index, entry.clone()),
+        None => {
+            send_message(http, original_channel, author.id,
+                Msg::NothingToUndo.text(language)
+            ).await?;
+            return Ok(())
+        }
+    };
+
+    let mut undo = entry.undo.unwrap();
+    // Channels recreated below, which are deleted again if the team isn't restored
+    let mut recreated = None;
+    // The channels of a removed team are deleted, so they have to be
+    // recreated before the team can be restored. Unless the user has a new
+    // team, in which case apply_undo refuses below
+    if let Undo::Team(user_id, team) = undo.clone() {
+        let has_new_team = PersistentState::for_guild(guild_id).await?.lock().await
+            .channel_creators.contains_key(&user_id);
+        if !has_new_team {
+            match restore_team_channels(guild_id, &team, current_user_id, http).await {
+                Ok(restored) => {
+                    // The channels still existed if the ids are the same
+                    if restored.category_id() != team.category_id() {
+                        recreated = Some(restored.clone());
+                    }
+                    undo = Undo::Team(user_id, restored);
+                }
+                Err(e) => {
+                    let message = Msg::UndoChannelsFailed {
+                        game: team.game_name(),
+                        error: &e.to_string(),
+                    }.text(language);
+                    log_action(http, guild_id, author.id, "Undo failed",
+                        Outcome::Failed(message.clone())
+                    ).await;
+                    send_message(http, original_channel, author.id, message).await?;
+                    return Ok(())
+                }
+            }
+        }
+    }
+
+    let restored_role = match &undo {
+        Undo::RequestableRole(role) => Some(role.emoji.clone()),
+        _ => None,
+    };
+    let (result, assign_channel, assign_message) = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        (
+            state.apply_undo(author.id, index, undo, language),
+            state.get_role_assign_channel(),
+            state.get_role_assign_message(),
+        )
+    };
+
+    match result {
+        Ok(description) => {
+            if let Some(emoji) = restored_role {
+                if assign_message.0 != 0 {
+                    if let Err(e) = http.create_reaction(assign_channel, assign_message, emoji).await {
+                        warn!(guild = %guild_id, error = ?e,
+                            "Failed to react with the emoji of a restored role");
+                    }
+                }
+            }
+            let message = Msg::Undid {
+                action: &entry.action,
+                actor: entry.actor,
+                time: &entry.time,
+                description: &description,
+            }.text(language);
+            log_action(http, guild_id, author.id, "Undo", Outcome::Done(message.clone())).await;
+            send_message(http, original_channel, author.id, message).await?;
+        }
+        Err(e) => {
+            let mut message = Msg::UndoFailed { action: &entry.action, error: &e.to_string() }
+                .text(language);
+            if let Some(team) = &recreated {
+                if let Some(left_behind) = delete_restored_channels(guild_id, team, language, http).await {
+                    message = format!("{} {}", message, left_behind);
+                }
+            }
+            log_action(http, guild_id, author.id, "Undo failed",
+                Outcome::Failed(message.clone())
+            ).await;
+            send_message(http, original_channel, author.id, message).await?;
+        }
+    }
+    Ok(())
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(UndoCommand);
+}
+
+struct UndoCommand;
+
+#[async_trait]
+impl Command for UndoCommand {
+    fn name(&self) -> &'static str { "undo" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeUndo }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_undo(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.current_user.id, ctx.http)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{handle_create_channels, handle_remove_channels};
+    use crate::discord::fake::{test_guild, test_user, Call, FakeDiscord};
+    use crate::storage;
+
+    const BOT: UserId = UserId(1);
+    const USER: UserId = UserId(10);
+    const ORGANIZER: UserId = UserId(11);
+    const COMMANDS: ChannelId = ChannelId(20);
+
+    fn deleted_count(discord: &FakeDiscord) -> usize {
+        discord.calls().iter().filter(|call| matches!(call, Call::DeleteChannel(_))).count()
+    }
+
+    async fn has_team(guild: GuildId) -> bool {
+        PersistentState::for_guild(guild).await.unwrap().lock().await
+            .channel_creators.contains_key(&USER)
+    }
+
+    #[tokio::test]
+    async fn undo_restores_a_removed_team() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        handle_create_channels(&["Game"], COMMANDS, guild, USER, BOT, &discord).await.unwrap();
+        let mention = format!("<@!{}>", USER);
+        handle_remove_channels(&[&mention], COMMANDS, guild, ORGANIZER, &discord).await.unwrap();
+
+        handle_undo(COMMANDS, guild, &test_user(ORGANIZER), BOT, &discord).await.unwrap();
+
+        assert!(has_team(guild).await);
+        assert_eq!(discord.channel_count(guild), 3);
+        let entries = storage().load_journal(guild).unwrap();
+        assert_eq!(entries.last().unwrap().undid, Some(entries.len() - 2));
+    }
+
+    #[tokio::test]
+    async fn unsaved_undo_deletes_the_recreated_channels() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        handle_create_channels(&["Game"], COMMANDS, guild, USER, BOT, &discord).await.unwrap();
+        let mention = format!("<@!{}>", USER);
+        handle_remove_channels(&[&mention], COMMANDS, guild, ORGANIZER, &discord).await.unwrap();
+        storage::fail_saves(guild);
+
+        handle_undo(COMMANDS, guild, &test_user(ORGANIZER), BOT, &discord).await.unwrap();
+
+        assert!(!has_team(guild).await);
+        assert_eq!(discord.channel_count(guild), 0);
+        assert_eq!(deleted_count(&discord), 6);
+        let reply = discord.messages(COMMANDS).pop().unwrap();
+        assert!(reply.contains("Could not undo"), "{}", reply);
+        assert!(storage().load_journal(guild).unwrap().iter().all(|entry| entry.undid.is_none()));
+    }
+}
@@ -0,0 +1,1526 @@
+use async_trait::async_trait;
+use serde_derive::{Serialize, Deserialize};
+use twilight::model::{
+    id::{ChannelId, GuildId, UserId},
+    user::User,
+};
+
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::config::Config;
+use crate::discord::DiscordApi;
+use crate::permission::Capability;
+use crate::state::PersistentState;
+use crate::storage::Change;
+use crate::theme_policy::Script;
+use crate::theme_template::Category;
+use crate::utils::{Result, send_message};
+
+/// A language the bot can reply in
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    En,
+    Sv,
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[Language::En, Language::Sv];
+
+    /// The code used to choose the language, e.g. `sv`
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Sv => "sv",
+        }
+    }
+
+    /// Finds the language with the code, ignoring case
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.iter()
+            .cloned()
+            .find(|language| language.code() == code.to_lowercase())
+    }
+}
+
+/**
+  Every text the bot sends to users, with the values that go into it.
+
+  The translations are exhaustive matches below, so a message that is
+  missing in some language is a compile error rather than something that
+  shows up in the middle of a jam
+*/
+pub enum Msg<'a> {
+    // Commands and help
+    UnrecognisedCommand { command: &'a str },
+    MissingCapability { capability: &'a str, command: &'a str },
+    CommandFailed { command: &'a str },
+    HelpCommands { commands: &'a str },
+    HelpPrivilegedCommands { commands: &'a str },
+    ProperUsage { command: &'a str, usage: &'a str },
+    InvalidChannelReference { usage: &'a str },
+
+    DescribeHelp,
+    DescribeCreateChannels,
+    DescribeRenameChannels,
+    DescribeRemoveChannels,
+    DescribeClearAssociations,
+    DescribeGiveRole,
+    DescribeLeaveRole,
+    DescribeListRoles,
+    DescribeAddRole,
+    DescribeRemoveRole,
+    DescribeGenerateTheme,
+    DescribeShowAllThemes,
+    DescribeShowThemeCount,
+    DescribeSetRoleAssign,
+    DescribeSetLogChannel,
+    DescribeUndo,
+    DescribeLanguage,
+    DescribeServerLanguage,
+    DescribeGrant,
+    DescribeRevoke,
+    DescribePermissions,
+    DescribeThemeLimit,
+    DescribeThemePolicy,
+    DescribeStartVote,
+    DescribeThemeTemplates,
+    DescribeCategorize,
+    DescribeCloseVote,
+
+    UsageGameName,
+    UsageNewGameName,
+    UsageUserMention,
+    UsageRoleName,
+    UsageRoleAndEmoji,
+    UsageMessageReference,
+    UsageLogChannel,
+    UsageLanguage,
+    UsageServerLanguage,
+    UsagePermission,
+    UsageThemeLimit,
+    UsageThemePolicy,
+    UsageStartVote,
+    UsageThemeTemplates,
+    UsageCategorize,
+
+    // Team channels
+    ChannelsCreated { game: &'a str, channel: ChannelId },
+    TeamAlreadyCreated { game: &'a str, channel: ChannelId },
+    NoGameName,
+    InvalidGameName,
+    CategoryNotCreated,
+    TextNotCreated,
+    VoiceNotCreated,
+    CategoryCreationFailed,
+    TextCreationFailed,
+    VoiceCreationFailed,
+    TeamCreationFailed,
+    NothingKept,
+    ChannelsLeftBehind { channels: &'a str },
+    Category,
+    TextChannel,
+    VoiceChannel,
+    And,
+    RenamedCategory { name: &'a str },
+    RenamedText { name: &'a str, channel: ChannelId },
+    RenamedVoice { name: &'a str },
+    Renamed { renamed: &'a str, game: &'a str },
+    RenamedPartly { renamed: &'a str, game: &'a str, missing: &'a str, count: usize },
+    RenamedNone { game: &'a str },
+    NoTeamYet,
+    InvalidUserReference,
+    InvalidUserId,
+    RemovedCategory { name: &'a str },
+    RemovedText { name: &'a str },
+    RemovedVoice { name: &'a str },
+    Removed { removed: &'a str, game: &'a str },
+    RemovedPartly { removed: &'a str, game: &'a str, missing: &'a str, count: usize },
+    RemovedNone { game: &'a str },
+    NoTeamChannels,
+    MissingUserId,
+    AssociationsCleared { count: usize },
+
+    // Roles
+    AvailableRoles { roles: &'a str },
+    RoleAssigned { role: &'a str },
+    RoleAssignFailed { error: &'a str },
+    RoleRemoved { role: &'a str },
+    RoleRemoveFailed { error: &'a str },
+    RoleCannotBeRequestable { role: &'a str },
+    RoleAlreadyRequestable { role: &'a str },
+    EmojiAlreadyUsed { emoji: &'a str, role: &'a str },
+    OnlyStandardEmojis,
+    OfferCreateRole { role: &'a str, emoji: &'a str },
+    RoleCreationFailed { role: &'a str, error: &'a str },
+    RoleMadeRequestable { role: &'a str, emoji: &'a str },
+    RoleNoLongerRequestable { role: &'a str },
+    NoRequestableRoles,
+    RequestableRoles { roles: &'a str },
+    DiscordError { error: &'a str },
+    InvalidRoleName { name: &'a str },
+    RoleAlreadySet { role: &'a str },
+    RoleNotSet { role: &'a str },
+
+    // Themes
+    IdeaEmpty,
+    IdeaTooManyWords { max: usize },
+    IdeaTooLong { max: usize },
+    IdeaInvisible,
+    IdeaScript { character: &'a str },
+    IdeaLink,
+    IdeaMention,
+    ThemeRegistered { idea: &'a str, count: usize, limit: usize },
+    ThemeReplaced { idea: &'a str, previous: &'a str },
+    ThemeDeleted { idea: &'a str },
+    TooManyIdeas { limit: usize },
+    ThemeIdeas { ideas: &'a str, count: usize, limit: usize },
+    NoThemeIdeas { limit: usize },
+    NoSuchIdea { number: &'a str, count: usize },
+    ThemePmHelp { limit: usize },
+    ThemeLimitSet { limit: usize },
+    InvalidThemeLimit { limit: &'a str },
+    ThemePolicyShown { words: usize, length: usize, scripts: &'a str, links: bool, mentions: bool },
+    UnknownPolicySetting { setting: &'a str, settings: &'a str },
+    InvalidPolicyValue { setting: &'a str, value: &'a str },
+    Theme { theme: &'a str },
+    IdeaCategorySet { idea: &'a str, category: &'a str },
+    IdeasCategorized { idea: &'a str, category: &'a str, count: usize },
+    NoMatchingIdeas { idea: &'a str },
+    InvalidCategory { category: &'a str },
+    ThemeTemplates { templates: &'a str },
+    TemplateAdded { template: &'a str },
+    TemplateRemoved { template: &'a str },
+    NoSuchTemplate { number: &'a str },
+    LastTemplate,
+    TemplateNoSlots,
+    TemplateUnknownSlot { slot: &'a str },
+    TemplateUnclosed,
+    NotEnoughIdeas,
+    ThemeSendFailed,
+    AllThemesTitle,
+    AllThemesSendFailed,
+    NoIdeasSubmitted,
+    ThemeCount { count: usize },
+    ThemeCountSendFailed,
+    NotAMember,
+    SeveralServers { guilds: &'a str },
+
+    // Theme vote
+    VoteAnnouncement { round: usize, rounds: usize, candidates: &'a str, deadline: &'a str },
+    VoteStarted { channel: ChannelId },
+    VoteAlreadyOpen { channel: ChannelId },
+    NoVoteOpen,
+    InvalidCandidateCount { max: usize },
+    InvalidDuration { duration: &'a str },
+    TooManyRounds { max: usize },
+    VoteCount { theme: &'a str, votes: usize },
+    VoteRoundEnded { round: usize, results: &'a str, dropped: &'a str },
+    VoteWinner { theme: &'a str, results: &'a str },
+    VoteTie { themes: &'a str, results: &'a str },
+    VoteNoVotes,
+    VoteClosed { channel: ChannelId },
+
+    // Reaction messages
+    RoleAssignMessage,
+    ReactionMessageSet { kind: &'a str, author: UserId, channel: ChannelId, content: &'a str },
+    ReactionMessageFailed { kind: &'a str },
+    MessageNotFound { message: u64, channel: u64 },
+    MessageIdNotNumber { usage: &'a str },
+
+    // Log channel
+    NotATextChannel,
+    LogChannelOff,
+    LogChannelSet { channel: ChannelId },
+
+    // Undo
+    NothingToUndo,
+    UndoChannelsFailed { game: &'a str, error: &'a str },
+    Undid { action: &'a str, actor: UserId, time: &'a str, description: &'a str },
+    UndoFailed { action: &'a str, error: &'a str },
+    TeamRestored { game: &'a str, user: UserId },
+    TeamNotRestored { game: &'a str, user: UserId },
+    TeamsRestored { count: usize },
+    TeamsKept { count: usize },
+    RoleRestored { role: &'a str, emoji: &'a str },
+    RoleNotRestored { role: &'a str, emoji: &'a str, used_by: &'a str },
+
+    // Languages
+    LanguageName { language: Language },
+    UserLanguageSet { language: Language },
+    UserLanguageReset { language: Language },
+    ServerLanguageSet { language: Language },
+    UnknownLanguage { code: &'a str },
+
+    // Permissions
+    UnknownCapability { name: &'a str, names: &'a str },
+    UnknownHolder { holder: &'a str },
+    PermissionGranted { capability: &'a str, holder: &'a str },
+    PermissionAlreadyHeld { capability: &'a str, holder: &'a str },
+    PermissionRevoked { capability: &'a str, holder: &'a str },
+    PermissionNotHeld { capability: &'a str, holder: &'a str },
+    Nobody,
+    Permissions { organizer_role: &'a str, table: &'a str },
+}
+
+impl Msg<'_> {
+    /// The message in the language
+    pub fn text(&self, language: Language) -> String {
+        match language {
+            Language::En => self.en(),
+            Language::Sv => self.sv(),
+        }
+    }
+
+    fn en(&self) -> String {
+        let prefix = &Config::get().prefix;
+        match self {
+            Self::UnrecognisedCommand { command } => format!("Unrecognised command `{}`.", command),
+            Self::MissingCapability { capability, command } => format!(
+                "Since you lack the **{}** permission, you are not allowed to use `{}{}`.",
+                capability, prefix, command
+            ),
+            Self::CommandFailed { command } => format!(
+                "Something went wrong with `{}{}`. The organizers have been told.",
+                prefix, command
+            ),
+            Self::HelpCommands { commands } =>
+                format!("You have access to the following commands:\n{}", commands),
+            Self::HelpPrivilegedCommands { commands } => format!(
+                "Your permissions also give you access to the following commands:\n{}", commands
+            ),
+            Self::ProperUsage { command, usage } =>
+                format!("Proper usage: `{}{} {}`", prefix, command, usage),
+            Self::InvalidChannelReference { usage } =>
+                format!("Invalid channel reference.\n{}", usage),
+
+            Self::DescribeHelp => "to show this message.".to_string(),
+            Self::DescribeCreateChannels =>
+                "to ask for text and voice channels for your game.".to_string(),
+            Self::DescribeRenameChannels => "to rename the channels for your game.".to_string(),
+            Self::DescribeRemoveChannels => "to remove a user's created channels.".to_string(),
+            Self::DescribeClearAssociations => "to clear all user–channel associations.".to_string(),
+            Self::DescribeGiveRole => "to get a role to signify one of your skill sets.".to_string(),
+            Self::DescribeLeaveRole => "to leave a role.".to_string(),
+            Self::DescribeListRoles => "to list the roles you can request.".to_string(),
+            Self::DescribeAddRole => "to make a role requestable.".to_string(),
+            Self::DescribeRemoveRole => "to stop a role from being requestable.".to_string(),
+            Self::DescribeGenerateTheme => "to generate a theme.".to_string(),
+            Self::DescribeShowAllThemes =>
+                "to view all the theme ideas that have been submitted.".to_string(),
+            Self::DescribeShowThemeCount =>
+                "to see the number of theme ideas that have been submitted.".to_string(),
+            Self::DescribeSetRoleAssign => "to set the server's role assignment message.".to_string(),
+            Self::DescribeSetLogChannel =>
+                "to set the channel where organizer actions and errors are logged.".to_string(),
+            Self::DescribeUndo => "to undo the last removal of teams or roles.".to_string(),
+            Self::DescribeLanguage => "to choose the language I reply to you in.".to_string(),
+            Self::DescribeServerLanguage =>
+                "to choose the language I reply in by default on this server.".to_string(),
+            Self::DescribeGrant => "to give a role or user a permission.".to_string(),
+            Self::DescribeRevoke => "to take a permission from a role or user.".to_string(),
+            Self::DescribePermissions => "to list who has which permissions.".to_string(),
+            Self::DescribeThemeLimit =>
+                "to set how many theme ideas each user can have.".to_string(),
+            Self::DescribeThemePolicy =>
+                "to show or change what theme ideas have to look like.".to_string(),
+            Self::DescribeStartVote =>
+                "to let everyone vote on a shortlist of themes by reacting.".to_string(),
+            Self::DescribeThemeTemplates =>
+                "to show or change the templates that generated themes follow.".to_string(),
+            Self::DescribeCategorize =>
+                "to set the category of every theme idea with the text.".to_string(),
+            Self::DescribeCloseVote =>
+                "to end the current round of the theme vote before its deadline.".to_string(),
+
+            Self::UsageGameName => "<game name>".to_string(),
+            Self::UsageNewGameName => "<new game name>".to_string(),
+            Self::UsageUserMention => "<mention of user>".to_string(),
+            Self::UsageRoleName => "<role name>".to_string(),
+            Self::UsageRoleAndEmoji => "<role name> <emoji>".to_string(),
+            Self::UsageMessageReference =>
+                "<mention of channel with the message> <message ID>".to_string(),
+            Self::UsageLogChannel => "<mention of channel | off>".to_string(),
+            Self::UsageLanguage => format!("<{} | default>", language_codes(" | ")),
+            Self::UsageServerLanguage => format!("<{}>", language_codes(" | ")),
+            Self::UsagePermission => "<permission> <mention of role or user | role name>".to_string(),
+            Self::UsageThemeLimit => "<number>".to_string(),
+            Self::UsageThemePolicy => "[<words | length | scripts | links | mentions> <value>]".to_string(),
+            Self::UsageStartVote =>
+                "<mention of channel> <number of themes> <duration of each round, like 30m 2h 1d> [generated | pool]".to_string(),
+            Self::UsageThemeTemplates => "[add <template> | remove <number>]".to_string(),
+            Self::UsageCategorize => format!("<{} | none> <idea>", category_names(" | ")),
+
+            Self::ChannelsCreated { game, channel } =>
+                format!("Channels created for your game **{}** here: <#{}>", game, channel),
+            Self::TeamAlreadyCreated { game, channel } => format!(
+                "You have already created channels for your game **{}** here: <#{}>\n\
+                Try using `{}renamechannels <new game name>` instead if you wish to rename them.",
+                game, channel, prefix
+            ),
+            Self::NoGameName => "You need to specify a game name.".to_string(),
+            Self::InvalidGameName => "Game names cannot contain the characters ` or |".to_string(),
+            Self::CategoryNotCreated =>
+                "I asked Discord for a category but got something else. 🤔".to_string(),
+            Self::TextNotCreated =>
+                "I asked Discord for a text channel but got something else. 🤔".to_string(),
+            Self::VoiceNotCreated =>
+                "I asked Discord for a voice channel but got something else. 🤔".to_string(),
+            Self::CategoryCreationFailed => "Category creation failed.".to_string(),
+            Self::TextCreationFailed => "Text channel creation failed.".to_string(),
+            Self::VoiceCreationFailed => "Voice channel creation failed.".to_string(),
+            Self::TeamCreationFailed => "Your team couldn't be saved.".to_string(),
+            Self::NothingKept => "Nothing was kept, so you can try again in a while.".to_string(),
+            Self::ChannelsLeftBehind { channels } => format!(
+                "Some of the channels couldn't be removed again, an organizer has to remove {}.",
+                channels
+            ),
+            Self::Category => "category".to_string(),
+            Self::TextChannel => "text channel".to_string(),
+            Self::VoiceChannel => "voice channel".to_string(),
+            Self::And => "and".to_string(),
+            Self::RenamedCategory { name } => format!("category to **{}**", name),
+            Self::RenamedText { name, channel } =>
+                format!("text channel to **#{}** (found here: <#{}>)", name, channel),
+            Self::RenamedVoice { name } => format!("voice channel to **{}**", name),
+            Self::Renamed { renamed, game } =>
+                format!("Renamed {} for your game **{}**.", renamed, game),
+            Self::RenamedPartly { renamed, game, missing, count } => format!(
+                "Renamed {} for your game **{}** but its {} {} been removed, it seems.",
+                renamed, game, missing, if *count > 1 { "have" } else { "has" }
+            ),
+            Self::RenamedNone { game } => format!(
+                "Category, text channel and voice channel for your game **{}** have been removed, it seems.",
+                game
+            ),
+            Self::NoTeamYet => format!(
+                "You have not created a channel yet.\n\
+                Try using `{}createchannels <game name>` instead.",
+                prefix
+            ),
+            Self::InvalidUserReference => "Invalid user reference.".to_string(),
+            Self::InvalidUserId => "That user id is invalid.".to_string(),
+            Self::RemovedCategory { name } => format!("category **{}**", name),
+            Self::RemovedText { name } => format!("text channel **#{}**", name),
+            Self::RemovedVoice { name } => format!("voice channel **{}**", name),
+            Self::Removed { removed, game } =>
+                format!("Removed {} for the game **{}**.", removed, game),
+            Self::RemovedPartly { removed, game, missing, count } => format!(
+                "Removed {} for the game **{}** but its {} {} already been removed.",
+                removed, game, missing, if *count > 1 { "have" } else { "has" }
+            ),
+            Self::RemovedNone { game } => format!(
+                "Category, text channel and voice channel for the game **{}** have already been removed.",
+                game
+            ),
+            Self::NoTeamChannels => "That user does not have any team channels.".to_string(),
+            Self::MissingUserId => "You forgot to provide a user id.".to_string(),
+            Self::AssociationsCleared { count } => format!(
+                "Cleared all {} user–channel associations.\n\
+                All users can now create new channels.",
+                count
+            ),
+
+            Self::AvailableRoles { roles } => format!(
+                "You need to to specify a valid role.\nAvailable roles are:```\n{}```", roles
+            ),
+            Self::RoleAssigned { role } => format!("You have been assigned the role **{}**.", role),
+            Self::RoleAssignFailed { error } => format!("Couldn't assign role to you: {}", error),
+            Self::RoleRemoved { role } => format!("You have been stripped of the role **{}**.", role),
+            Self::RoleRemoveFailed { error } => format!("Couldn't strip you of role: {}", error),
+            Self::RoleCannotBeRequestable { role } =>
+                format!("The role **{}** can not be requestable.", role),
+            Self::RoleAlreadyRequestable { role } => format!("**{}** is already requestable.", role),
+            Self::EmojiAlreadyUsed { emoji, role } =>
+                format!("{} is already used for **{}**.", emoji, role),
+            Self::OnlyStandardEmojis => "Only standard emojis can be used for roles.".to_string(),
+            Self::OfferCreateRole { role, emoji } => format!(
+                "There is no role named **{}** on this server. \
+                React with {} to create it and make it requestable.",
+                role, emoji
+            ),
+            Self::RoleCreationFailed { role, error } =>
+                format!("Could not create the role **{}**: {}", role, error),
+            Self::RoleMadeRequestable { role, emoji } =>
+                format!("**{}** can now be requested with {}.", role, emoji),
+            Self::RoleNoLongerRequestable { role } =>
+                format!("**{}** can no longer be requested. Users that have it keep it.", role),
+            Self::NoRequestableRoles => "There are no requestable roles.".to_string(),
+            Self::RequestableRoles { roles } => format!("The requestable roles are:\n{}", roles),
+            Self::DiscordError { error } => format!("Discord error: {}", error),
+            Self::InvalidRoleName { name } => format!("Invalid role name \"{}\"", name),
+            Self::RoleAlreadySet { role } => format!("Role **{}** already set", role),
+            Self::RoleNotSet { role } => format!("Role **{}** not set", role),
+
+            Self::IdeaEmpty => "That theme idea is empty.".to_string(),
+            Self::IdeaTooManyWords { max } =>
+                format!("Theme ideas can be at most {} words long.", max),
+            Self::IdeaTooLong { max } =>
+                format!("Theme ideas can be at most {} characters long.", max),
+            Self::IdeaInvisible =>
+                "Theme ideas can't contain invisible characters like zero width spaces.".to_string(),
+            Self::IdeaScript { character } => format!(
+                "Theme ideas on this server can't contain \"{}\", it isn't in an allowed script.",
+                character
+            ),
+            Self::IdeaLink => "Theme ideas can't contain links.".to_string(),
+            Self::IdeaMention => "Theme ideas can't mention users, roles or channels.".to_string(),
+            Self::ThemeRegistered { idea, count, limit } => format!(
+                "Theme idea \"{}\" registered, thanks! You have {} of {} ideas.",
+                idea, count, limit
+            ),
+            Self::ThemeReplaced { idea, previous } => format!(
+                "Theme idea \"{}\" registered, replacing your previous submission \"{}\".",
+                idea, previous
+            ),
+            Self::ThemeDeleted { idea } => format!("Theme idea \"{}\" deleted.", idea),
+            Self::TooManyIdeas { limit } => format!(
+                "You already have {} theme ideas, which is as many as you can have. \
+                Replace one with `{}replace <number> <idea>` or delete one with `{}delete <number>`. \
+                `{}ideas` shows the numbers.",
+                limit, prefix, prefix, prefix
+            ),
+            Self::ThemeIdeas { ideas, count, limit } =>
+                format!("Your theme ideas, {} of {}:\n{}", count, limit, ideas),
+            Self::NoThemeIdeas { limit } => format!(
+                "You haven't submitted any theme ideas yet. Send me one to submit it, \
+                you can have up to {}.",
+                limit
+            ),
+            Self::NoSuchIdea { number, count } => format!(
+                "You don't have a theme idea number {}, you have {}. `{}ideas` shows them.",
+                number, count, prefix
+            ),
+            Self::ThemePmHelp { limit } => format!(
+                "Send me a theme idea to submit it, you can have up to {}.\n\
+                - `{}ideas` lists your ideas.\n\
+                - `{}replace <number> <idea>` replaces one of them.\n\
+                - `{}delete <number>` deletes one of them.\n\
+                - `{}category <number> <{} | none>` tells what kind of word one of them is.",
+                limit, prefix, prefix, prefix, prefix, category_names(" | ")
+            ),
+            Self::ThemeLimitSet { limit } =>
+                format!("Users can have up to **{}** theme ideas each.", limit),
+            Self::InvalidThemeLimit { limit } =>
+                format!("{} is not a whole number above zero.", limit),
+            Self::ThemePolicyShown { words, length, scripts, links, mentions } => format!(
+                "Theme ideas can have up to **{}** words and **{}** characters, in the scripts **{}**. \
+                Links are **{}** and mentions are **{}**.",
+                words, length, scripts,
+                if *links { "on" } else { "off" },
+                if *mentions { "on" } else { "off" }
+            ),
+            Self::UnknownPolicySetting { setting, settings } =>
+                format!("There is no setting called {}. The settings are {}.", setting, settings),
+            Self::InvalidPolicyValue { setting, value } => format!(
+                "{} is not a valid value for {}. Words and length take a number above zero, \
+                scripts takes any or a comma separated list of {}, and links and mentions take on or off.",
+                value, setting, script_names()
+            ),
+            Self::Theme { theme } => format!("The theme is: {}", theme),
+            Self::IdeaCategorySet { idea, category } =>
+                format!("The category of \"{}\" is now {}.", idea, category),
+            Self::IdeasCategorized { idea, category, count } =>
+                format!("The category of {} theme ideas \"{}\" is now {}.", count, idea, category),
+            Self::NoMatchingIdeas { idea } => format!("Nobody has submitted the theme idea \"{}\".", idea),
+            Self::InvalidCategory { category } => format!(
+                "There is no category called {}. The categories are {} and none.",
+                category, category_names(", ")
+            ),
+            Self::ThemeTemplates { templates } => format!(
+                "Generated themes follow one of these templates, where {{any}} is any idea \
+                and {{{}}} an idea of that category:\n{}",
+                category_names("}, {"), templates
+            ),
+            Self::TemplateAdded { template } => format!("Added the template `{}`.", template),
+            Self::TemplateRemoved { template } => format!("Removed the template `{}`.", template),
+            Self::NoSuchTemplate { number } => format!(
+                "There is no template number {}. `{}themetemplates` shows the numbers.",
+                number, prefix
+            ),
+            Self::LastTemplate =>
+                "The last template can't be removed. Add another one first.".to_string(),
+            Self::TemplateNoSlots =>
+                "A template needs at least one slot for an idea, like {noun}.".to_string(),
+            Self::TemplateUnknownSlot { slot } => format!(
+                "{{{}}} is not a slot. The slots are {{any}} and {{{}}}.",
+                slot, category_names("}, {")
+            ),
+            Self::TemplateUnclosed => "Every { in a template needs a } after it.".to_string(),
+            Self::NotEnoughIdeas => "Not enough ideas have been submitted yet.".to_string(),
+            Self::ThemeSendFailed => "Failed to send theme. Has someone been naughty? 🤔".to_string(),
+            Self::AllThemesTitle => "Submitted theme ideas".to_string(),
+            Self::AllThemesSendFailed =>
+                "Failed to send all themes. I don't know how this happened.".to_string(),
+            Self::NoIdeasSubmitted => "No theme ideas have been submitted yet.".to_string(),
+            Self::ThemeCount { count } => format!("There are **{}** submitted theme ideas.", count),
+            Self::ThemeCountSendFailed =>
+                "Failed to send theme idea count. I don't know how this happened.".to_string(),
+            Self::NotAMember => "You need to be a member of a server I'm in to do that.".to_string(),
+            Self::SeveralServers { guilds } => format!(
+                "You are a member of several servers I'm in. Start your message \
+                with the ID of the server it is meant for, one of: {}",
+                guilds
+            ),
+
+            Self::VoteAnnouncement { round, rounds, candidates, deadline } => format!(
+                "**Vote for the theme of the jam!**{} React with the number of every theme \
+                you would like, until {}.\n\n{}",
+                if *rounds > 1 {
+                    format!(" This is round {} of {}, and the lower half of the themes \
+                        is dropped after every round but the last.", round, rounds)
+                }
+                else {
+                    String::new()
+                },
+                deadline, candidates
+            ),
+            Self::VoteStarted { channel } => format!("The theme vote has started in <#{}>.", channel),
+            Self::VoteAlreadyOpen { channel } => format!(
+                "There is already a theme vote in <#{}>. Use `{}closevote` to end it first.",
+                channel, prefix
+            ),
+            Self::NoVoteOpen => "There is no theme vote going on.".to_string(),
+            Self::InvalidCandidateCount { max } =>
+                format!("The number of themes has to be between 2 and {}.", max),
+            Self::InvalidDuration { duration } => format!(
+                "{} is not a duration. Write a number followed by m, h or d, like 30m, 2h or 1d.",
+                duration
+            ),
+            Self::TooManyRounds { max } =>
+                format!("A vote can have at most {} rounds, one duration each.", max),
+            Self::VoteCount { theme, votes } => format!(
+                "{}: {} {}", theme, votes, if *votes == 1 { "vote" } else { "votes" }
+            ),
+            Self::VoteRoundEnded { round, results, dropped } => format!(
+                "Round {} is over and these themes are out: **{}**\n\n{}",
+                round, dropped, results
+            ),
+            Self::VoteWinner { theme, results } => format!(
+                "The vote is over! The theme is **{}**\n\n{}",
+                theme, results
+            ),
+            Self::VoteTie { themes, results } => format!(
+                "The vote is over, and it's a tie between **{}**\n\n{}",
+                themes, results
+            ),
+            Self::VoteNoVotes => "The vote is over, but nobody voted.".to_string(),
+            Self::VoteClosed { channel } =>
+                format!("The round is over and the result is posted in <#{}>.", channel),
+
+            Self::RoleAssignMessage => "role assignment message".to_string(),
+            Self::ReactionMessageSet { kind, author, channel, content } => format!(
+                "Server {} set to the following messege by <@{}> in <#{}>:\n>>> {}",
+                kind, author, channel, content
+            ),
+            Self::ReactionMessageFailed { kind } =>
+                format!("Could not set server {}. Check the logs for details.", kind),
+            Self::MessageNotFound { message, channel } =>
+                format!("No message with ID {} was found in <#{}>", message, channel),
+            Self::MessageIdNotNumber { usage } => format!("Message ID must be a number.\n{}", usage),
+
+            Self::NotATextChannel => "That is not a text channel on this server.".to_string(),
+            Self::LogChannelOff =>
+                "Organizer actions and errors are no longer logged to a channel.".to_string(),
+            Self::LogChannelSet { channel } =>
+                format!("Organizer actions and errors will be logged in <#{}>.", channel),
+
+            Self::NothingToUndo => "There is nothing to undo.".to_string(),
+            Self::UndoChannelsFailed { game, error } =>
+                format!("Could not recreate the channels of **{}**: {}", game, error),
+            Self::Undid { action, actor, time, description } =>
+                format!("Undid \"{}\" by <@{}> at {}.\n{}", action, actor, time, description),
+            Self::UndoFailed { action, error } => format!("Could not undo \"{}\": {}", action, error),
+            Self::TeamRestored { game, user } => format!("Restored the team **{}** of <@{}>.", game, user),
+            Self::TeamNotRestored { game, user } => format!(
+                "<@{}> has created another team since, so the team **{}** was not restored.",
+                user, game
+            ),
+            Self::TeamsRestored { count } => format!("Restored {} user–channel associations.", count),
+            Self::TeamsKept { count } =>
+                format!("{} users have created new channels since and kept those.", count),
+            Self::RoleRestored { role, emoji } =>
+                format!("**{}** can be requested with {} again.", role, emoji),
+            Self::RoleNotRestored { role, emoji, used_by } =>
+                format!("{} is now used for **{}**, so **{}** was not restored.", emoji, used_by, role),
+
+            Self::LanguageName { language } => match language {
+                Language::En => "English",
+                Language::Sv => "Swedish",
+            }.to_string(),
+            Self::UserLanguageSet { language } => format!(
+                "I will reply to you in {}.", Msg::LanguageName { language: *language }.en()
+            ),
+            Self::UserLanguageReset { language } => format!(
+                "I will reply to you in the language of the server, {}.",
+                Msg::LanguageName { language: *language }.en()
+            ),
+            Self::ServerLanguageSet { language } => format!(
+                "I will reply in {} on this server, unless users choose another language \
+                with `{}language`.",
+                Msg::LanguageName { language: *language }.en(), prefix
+            ),
+            Self::UnknownLanguage { code } => format!(
+                "I don't know the language `{}`. The languages I know are {}.",
+                code, language_codes(", ")
+            ),
+
+            Self::UnknownCapability { name, names } =>
+                format!("There is no permission called `{}`. The permissions are {}.", name, names),
+            Self::UnknownHolder { holder } =>
+                format!("There is no role or user called **{}** on this server.", holder),
+            Self::PermissionGranted { capability, holder } =>
+                format!("{} now has the **{}** permission.", holder, capability),
+            Self::PermissionAlreadyHeld { capability, holder } =>
+                format!("{} already has the **{}** permission.", holder, capability),
+            Self::PermissionRevoked { capability, holder } =>
+                format!("{} no longer has the **{}** permission.", holder, capability),
+            Self::PermissionNotHeld { capability, holder } =>
+                format!("{} doesn't have the **{}** permission.", holder, capability),
+            Self::Nobody => "nobody".to_string(),
+            Self::Permissions { organizer_role, table } => format!(
+                "The **{}** role has every permission. Besides it:\n{}", organizer_role, table
+            ),
+        }
+    }
+
+    fn sv(&self) -> String {
+        let prefix = &Config::get().prefix;
+        match self {
+            Self::UnrecognisedCommand { command } => format!("Okänt kommando `{}`.", command),
+            Self::MissingCapability { capability, command } => format!(
+                "Eftersom du saknar behörigheten **{}** får du inte använda `{}{}`.",
+                capability, prefix, command
+            ),
+            Self::CommandFailed { command } => format!(
+                "Något gick fel med `{}{}`. Arrangörerna har fått veta det.",
+                prefix, command
+            ),
+            Self::HelpCommands { commands } =>
+                format!("Du har tillgång till följande kommandon:\n{}", commands),
+            Self::HelpPrivilegedCommands { commands } => format!(
+                "Dina behörigheter ger dig också tillgång till följande kommandon:\n{}", commands
+            ),
+            Self::ProperUsage { command, usage } =>
+                format!("Användning: `{}{} {}`", prefix, command, usage),
+            Self::InvalidChannelReference { usage } =>
+                format!("Ogiltig kanalreferens.\n{}", usage),
+
+            Self::DescribeHelp => "för att visa det här meddelandet.".to_string(),
+            Self::DescribeCreateChannels =>
+                "för att be om text- och röstkanaler för ditt spel.".to_string(),
+            Self::DescribeRenameChannels => "för att byta namn på kanalerna för ditt spel.".to_string(),
+            Self::DescribeRemoveChannels =>
+                "för att ta bort kanalerna som en användare har skapat.".to_string(),
+            Self::DescribeClearAssociations =>
+                "för att rensa alla kopplingar mellan användare och kanaler.".to_string(),
+            Self::DescribeGiveRole =>
+                "för att få en roll som visar en av dina färdigheter.".to_string(),
+            Self::DescribeLeaveRole => "för att lämna en roll.".to_string(),
+            Self::DescribeListRoles => "för att lista rollerna du kan be om.".to_string(),
+            Self::DescribeAddRole => "för att låta användare be om en roll.".to_string(),
+            Self::DescribeRemoveRole => "för att sluta låta användare be om en roll.".to_string(),
+            Self::DescribeGenerateTheme => "för att slumpa fram ett tema.".to_string(),
+            Self::DescribeShowAllThemes =>
+                "för att se alla temaidéer som har skickats in.".to_string(),
+            Self::DescribeShowThemeCount =>
+                "för att se hur många temaidéer som har skickats in.".to_string(),
+            Self::DescribeSetRoleAssign =>
+                "för att välja serverns meddelande för rolltilldelning.".to_string(),
+            Self::DescribeSetLogChannel =>
+                "för att välja kanalen där arrangörernas åtgärder och fel loggas.".to_string(),
+            Self::DescribeUndo =>
+                "för att ångra den senaste borttagningen av lag eller roller.".to_string(),
+            Self::DescribeLanguage => "för att välja vilket språk jag svarar dig på.".to_string(),
+            Self::DescribeServerLanguage =>
+                "för att välja vilket språk jag svarar på som standard på den här servern.".to_string(),
+            Self::DescribeGrant => "för att ge en roll eller användare en behörighet.".to_string(),
+            Self::DescribeRevoke => "för att ta en behörighet från en roll eller användare.".to_string(),
+            Self::DescribePermissions => "för att lista vem som har vilka behörigheter.".to_string(),
+            Self::DescribeThemeLimit =>
+                "för att välja hur många temaidéer varje användare kan ha.".to_string(),
+            Self::DescribeThemePolicy =>
+                "för att visa eller ändra hur temaidéer måste se ut.".to_string(),
+            Self::DescribeStartVote =>
+                "för att låta alla rösta på ett urval av teman med reaktioner.".to_string(),
+            Self::DescribeThemeTemplates =>
+                "för att visa eller ändra mallarna som slumpade teman följer.".to_string(),
+            Self::DescribeCategorize =>
+                "för att sätta kategorin för alla temaidéer med texten.".to_string(),
+            Self::DescribeCloseVote =>
+                "för att avsluta den pågående omgången av temaomröstningen i förtid.".to_string(),
+
+            Self::UsageGameName => "<spelets namn>".to_string(),
+            Self::UsageNewGameName => "<spelets nya namn>".to_string(),
+            Self::UsageUserMention => "<omnämnande av användare>".to_string(),
+            Self::UsageRoleName => "<rollens namn>".to_string(),
+            Self::UsageRoleAndEmoji => "<rollens namn> <emoji>".to_string(),
+            Self::UsageMessageReference =>
+                "<omnämnande av kanalen med meddelandet> <meddelandets ID>".to_string(),
+            Self::UsageLogChannel => "<omnämnande av kanal | off>".to_string(),
+            Self::UsageLanguage => format!("<{} | default>", language_codes(" | ")),
+            Self::UsageServerLanguage => format!("<{}>", language_codes(" | ")),
+            Self::UsagePermission =>
+                "<behörighet> <omnämnande av roll eller användare | rollens namn>".to_string(),
+            Self::UsageThemeLimit => "<antal>".to_string(),
+            Self::UsageThemePolicy => "[<words | length | scripts | links | mentions> <värde>]".to_string(),
+            Self::UsageStartVote =>
+                "<omnämnande av kanal> <antal teman> <tid för varje omgång, som 30m 2h 1d> [generated | pool]".to_string(),
+            Self::UsageThemeTemplates => "[add <mall> | remove <nummer>]".to_string(),
+            Self::UsageCategorize => format!("<{} | none> <idé>", category_names(" | ")),
+
+            Self::ChannelsCreated { game, channel } =>
+                format!("Kanaler har skapats för ditt spel **{}** här: <#{}>", game, channel),
+            Self::TeamAlreadyCreated { game, channel } => format!(
+                "Du har redan skapat kanaler för ditt spel **{}** här: <#{}>\n\
+                Använd `{}renamechannels <spelets nya namn>` istället om du vill byta namn på dem.",
+                game, channel, prefix
+            ),
+            Self::NoGameName => "Du måste ange ett namn på spelet.".to_string(),
+            Self::InvalidGameName => "Spelnamn får inte innehålla tecknen ` eller |".to_string(),
+            Self::CategoryNotCreated =>
+                "Jag bad Discord om en kategori men fick något annat. 🤔".to_string(),
+            Self::TextNotCreated =>
+                "Jag bad Discord om en textkanal men fick något annat. 🤔".to_string(),
+            Self::VoiceNotCreated =>
+                "Jag bad Discord om en röstkanal men fick något annat. 🤔".to_string(),
+            Self::CategoryCreationFailed => "Det gick inte att skapa kategorin.".to_string(),
+            Self::TextCreationFailed => "Det gick inte att skapa textkanalen.".to_string(),
+            Self::VoiceCreationFailed => "Det gick inte att skapa röstkanalen.".to_string(),
+            Self::TeamCreationFailed => "Det gick inte att spara ditt lag.".to_string(),
+            Self::NothingKept => "Inget sparades, så du kan försöka igen om en stund.".to_string(),
+            Self::ChannelsLeftBehind { channels } => format!(
+                "Några av kanalerna gick inte att ta bort igen, en arrangör måste ta bort {}.",
+                channels
+            ),
+            Self::Category => "kategori".to_string(),
+            Self::TextChannel => "textkanal".to_string(),
+            Self::VoiceChannel => "röstkanal".to_string(),
+            Self::And => "och".to_string(),
+            Self::RenamedCategory { name } => format!("kategorin till **{}**", name),
+            Self::RenamedText { name, channel } =>
+                format!("textkanalen till **#{}** (finns här: <#{}>)", name, channel),
+            Self::RenamedVoice { name } => format!("röstkanalen till **{}**", name),
+            Self::Renamed { renamed, game } =>
+                format!("Bytte namn på {} för ditt spel **{}**.", renamed, game),
+            Self::RenamedPartly { renamed, game, missing, count: _ } => format!(
+                "Bytte namn på {} för ditt spel **{}** men dess {} verkar ha tagits bort.",
+                renamed, game, missing
+            ),
+            Self::RenamedNone { game } => format!(
+                "Kategorin, textkanalen och röstkanalen för ditt spel **{}** verkar ha tagits bort.",
+                game
+            ),
+            Self::NoTeamYet => format!(
+                "Du har inte skapat några kanaler än.\n\
+                Använd `{}createchannels <spelets namn>` istället.",
+                prefix
+            ),
+            Self::InvalidUserReference => "Ogiltig användarreferens.".to_string(),
+            Self::InvalidUserId => "Det användar-id:t är ogiltigt.".to_string(),
+            Self::RemovedCategory { name } => format!("kategorin **{}**", name),
+            Self::RemovedText { name } => format!("textkanalen **#{}**", name),
+            Self::RemovedVoice { name } => format!("röstkanalen **{}**", name),
+            Self::Removed { removed, game } =>
+                format!("Tog bort {} för spelet **{}**.", removed, game),
+            Self::RemovedPartly { removed, game, missing, count: _ } => format!(
+                "Tog bort {} för spelet **{}** men dess {} hade redan tagits bort.",
+                removed, game, missing
+            ),
+            Self::RemovedNone { game } => format!(
+                "Kategorin, textkanalen och röstkanalen för spelet **{}** hade redan tagits bort.",
+                game
+            ),
+            Self::NoTeamChannels => "Den användaren har inga lagkanaler.".to_string(),
+            Self::MissingUserId => "Du glömde att ange en användare.".to_string(),
+            Self::AssociationsCleared { count } => format!(
+                "Rensade alla {} kopplingar mellan användare och kanaler.\n\
+                Alla användare kan nu skapa nya kanaler.",
+                count
+            ),
+
+            Self::AvailableRoles { roles } => format!(
+                "Du måste ange en giltig roll.\nTillgängliga roller är:```\n{}```", roles
+            ),
+            Self::RoleAssigned { role } => format!("Du har fått rollen **{}**.", role),
+            Self::RoleAssignFailed { error } => format!("Kunde inte ge dig rollen: {}", error),
+            Self::RoleRemoved { role } => format!("Rollen **{}** har tagits bort från dig.", role),
+            Self::RoleRemoveFailed { error } =>
+                format!("Kunde inte ta bort rollen från dig: {}", error),
+            Self::RoleCannotBeRequestable { role } =>
+                format!("Användare kan inte få be om rollen **{}**.", role),
+            Self::RoleAlreadyRequestable { role } => format!("Det går redan att be om **{}**.", role),
+            Self::EmojiAlreadyUsed { emoji, role } =>
+                format!("{} används redan för **{}**.", emoji, role),
+            Self::OnlyStandardEmojis => "Bara vanliga emojier kan användas för roller.".to_string(),
+            Self::OfferCreateRole { role, emoji } => format!(
+                "Det finns ingen roll som heter **{}** på den här servern. \
+                Reagera med {} för att skapa den och låta användare be om den.",
+                role, emoji
+            ),
+            Self::RoleCreationFailed { role, error } =>
+                format!("Kunde inte skapa rollen **{}**: {}", role, error),
+            Self::RoleMadeRequestable { role, emoji } =>
+                format!("Nu går det att be om **{}** med {}.", role, emoji),
+            Self::RoleNoLongerRequestable { role } => format!(
+                "Det går inte längre att be om **{}**. Användare som har rollen behåller den.", role
+            ),
+            Self::NoRequestableRoles => "Det finns inga roller att be om.".to_string(),
+            Self::RequestableRoles { roles } => format!("Rollerna du kan be om är:\n{}", roles),
+            Self::DiscordError { error } => format!("Fel från Discord: {}", error),
+            Self::InvalidRoleName { name } => format!("Ogiltigt rollnamn \"{}\"", name),
+            Self::RoleAlreadySet { role } => format!("Du har redan rollen **{}**", role),
+            Self::RoleNotSet { role } => format!("Du har inte rollen **{}**", role),
+
+            Self::IdeaEmpty => "Den temaidén är tom.".to_string(),
+            Self::IdeaTooManyWords { max } =>
+                format!("Temaidéer får vara högst {} ord långa.", max),
+            Self::IdeaTooLong { max } =>
+                format!("Temaidéer får vara högst {} tecken långa.", max),
+            Self::IdeaInvisible =>
+                "Temaidéer får inte innehålla osynliga tecken som nollbreddsmellanslag.".to_string(),
+            Self::IdeaScript { character } => format!(
+                "Temaidéer på den här servern får inte innehålla \"{}\", det är inte i ett tillåtet skriftsystem.",
+                character
+            ),
+            Self::IdeaLink => "Temaidéer får inte innehålla länkar.".to_string(),
+            Self::IdeaMention =>
+                "Temaidéer får inte nämna användare, roller eller kanaler.".to_string(),
+            Self::ThemeRegistered { idea, count, limit } => format!(
+                "Temaidén \"{}\" är registrerad, tack! Du har {} av {} idéer.",
+                idea, count, limit
+            ),
+            Self::ThemeReplaced { idea, previous } => format!(
+                "Temaidén \"{}\" är registrerad och ersätter din tidigare idé \"{}\".",
+                idea, previous
+            ),
+            Self::ThemeDeleted { idea } => format!("Temaidén \"{}\" är borttagen.", idea),
+            Self::TooManyIdeas { limit } => format!(
+                "Du har redan {} temaidéer, vilket är så många du kan ha. \
+                Ersätt en med `{}replace <nummer> <idé>` eller ta bort en med `{}delete <nummer>`. \
+                `{}ideas` visar numren.",
+                limit, prefix, prefix, prefix
+            ),
+            Self::ThemeIdeas { ideas, count, limit } =>
+                format!("Dina temaidéer, {} av {}:\n{}", count, limit, ideas),
+            Self::NoThemeIdeas { limit } => format!(
+                "Du har inte skickat in några temaidéer än. Skicka en till mig för att \
+                skicka in den, du kan ha upp till {}.",
+                limit
+            ),
+            Self::NoSuchIdea { number, count } => format!(
+                "Du har ingen temaidé nummer {}, du har {}. `{}ideas` visar dem.",
+                number, count, prefix
+            ),
+            Self::ThemePmHelp { limit } => format!(
+                "Skicka en temaidé till mig för att skicka in den, du kan ha upp till {}.\n\
+                - `{}ideas` listar dina idéer.\n\
+                - `{}replace <nummer> <idé>` ersätter en av dem.\n\
+                - `{}delete <nummer>` tar bort en av dem.\n\
+                - `{}category <nummer> <{} | none>` talar om vilken sorts ord en av dem är.",
+                limit, prefix, prefix, prefix, prefix, category_names(" | ")
+            ),
+            Self::ThemeLimitSet { limit } =>
+                format!("Användare kan ha upp till **{}** temaidéer var.", limit),
+            Self::InvalidThemeLimit { limit } =>
+                format!("{} är inte ett heltal större än noll.", limit),
+            Self::ThemePolicyShown { words, length, scripts, links, mentions } => format!(
+                "Temaidéer kan ha upp till **{}** ord och **{}** tecken, i skriftsystemen **{}**. \
+                Länkar är **{}** och omnämnanden är **{}**.",
+                words, length, scripts,
+                if *links { "on" } else { "off" },
+                if *mentions { "on" } else { "off" }
+            ),
+            Self::UnknownPolicySetting { setting, settings } =>
+                format!("Det finns ingen inställning som heter {}. Inställningarna är {}.", setting, settings),
+            Self::InvalidPolicyValue { setting, value } => format!(
+                "{} är inte ett giltigt värde för {}. Words och length tar ett tal större än noll, \
+                scripts tar any eller en kommaseparerad lista av {}, och links och mentions tar on eller off.",
+                value, setting, script_names()
+            ),
+            Self::Theme { theme } => format!("Temat är: {}", theme),
+            Self::IdeaCategorySet { idea, category } =>
+                format!("Kategorin för \"{}\" är nu {}.", idea, category),
+            Self::IdeasCategorized { idea, category, count } =>
+                format!("Kategorin för {} temaidéer \"{}\" är nu {}.", count, idea, category),
+            Self::NoMatchingIdeas { idea } => format!("Ingen har skickat in temaidén \"{}\".", idea),
+            Self::InvalidCategory { category } => format!(
+                "Det finns ingen kategori som heter {}. Kategorierna är {} och none.",
+                category, category_names(", ")
+            ),
+            Self::ThemeTemplates { templates } => format!(
+                "Slumpade teman följer en av de här mallarna, där {{any}} är vilken idé som helst \
+                och {{{}}} en idé i den kategorin:\n{}",
+                category_names("}, {"), templates
+            ),
+            Self::TemplateAdded { template } => format!("Lade till mallen `{}`.", template),
+            Self::TemplateRemoved { template } => format!("Tog bort mallen `{}`.", template),
+            Self::NoSuchTemplate { number } => format!(
+                "Det finns ingen mall nummer {}. `{}themetemplates` visar numren.",
+                number, prefix
+            ),
+            Self::LastTemplate =>
+                "Den sista mallen kan inte tas bort. Lägg till en annan först.".to_string(),
+            Self::TemplateNoSlots =>
+                "En mall behöver minst en plats för en idé, som {noun}.".to_string(),
+            Self::TemplateUnknownSlot { slot } => format!(
+                "{{{}}} är ingen plats. Platserna är {{any}} och {{{}}}.",
+                slot, category_names("}, {")
+            ),
+            Self::TemplateUnclosed => "Varje { i en mall behöver en } efter sig.".to_string(),
+            Self::NotEnoughIdeas => "Det har inte skickats in tillräckligt många idéer än.".to_string(),
+            Self::ThemeSendFailed => "Kunde inte skicka temat. Har någon varit stygg? 🤔".to_string(),
+            Self::AllThemesTitle => "Inskickade temaidéer".to_string(),
+            Self::AllThemesSendFailed =>
+                "Kunde inte skicka alla temaidéer. Jag vet inte hur det gick till.".to_string(),
+            Self::NoIdeasSubmitted => "Inga temaidéer har skickats in än.".to_string(),
+            Self::ThemeCount { count } => format!("Det finns **{}** inskickade temaidéer.", count),
+            Self::ThemeCountSendFailed =>
+                "Kunde inte skicka antalet temaidéer. Jag vet inte hur det gick till.".to_string(),
+            Self::NotAMember =>
+                "Du måste vara medlem på en server som jag är med i för att göra det.".to_string(),
+            Self::SeveralServers { guilds } => format!(
+                "Du är medlem på flera servrar som jag är med i. Börja ditt meddelande \
+                med id:t för servern det är till, ett av: {}",
+                guilds
+            ),
+
+            Self::VoteAnnouncement { round, rounds, candidates, deadline } => format!(
+                "**Rösta på jammets tema!**{} Reagera med numret på alla teman \
+                du gillar, fram till {}.\n\n{}",
+                if *rounds > 1 {
+                    format!(" Det här är omgång {} av {}, och den sämre halvan av temana \
+                        åker ut efter varje omgång utom den sista.", round, rounds)
+                }
+                else {
+                    String::new()
+                },
+                deadline, candidates
+            ),
+            Self::VoteStarted { channel } => format!("Temaomröstningen har börjat i <#{}>.", channel),
+            Self::VoteAlreadyOpen { channel } => format!(
+                "Det pågår redan en temaomröstning i <#{}>. Använd `{}closevote` för att avsluta den först.",
+                channel, prefix
+            ),
+            Self::NoVoteOpen => "Det pågår ingen temaomröstning.".to_string(),
+            Self::InvalidCandidateCount { max } =>
+                format!("Antalet teman måste vara mellan 2 och {}.", max),
+            Self::InvalidDuration { duration } => format!(
+                "{} är ingen tid. Skriv ett tal följt av m, h eller d, som 30m, 2h eller 1d.",
+                duration
+            ),
+            Self::TooManyRounds { max } =>
+                format!("En omröstning kan ha högst {} omgångar, med en tid för varje.", max),
+            Self::VoteCount { theme, votes } => format!(
+                "{}: {} {}", theme, votes, if *votes == 1 { "röst" } else { "röster" }
+            ),
+            Self::VoteRoundEnded { round, results, dropped } => format!(
+                "Omgång {} är över och de här temana har åkt ut: **{}**\n\n{}",
+                round, dropped, results
+            ),
+            Self::VoteWinner { theme, results } => format!(
+                "Omröstningen är över! Temat är **{}**\n\n{}",
+                theme, results
+            ),
+            Self::VoteTie { themes, results } => format!(
+                "Omröstningen är över, och det blev lika mellan **{}**\n\n{}",
+                themes, results
+            ),
+            Self::VoteNoVotes => "Omröstningen är över, men ingen röstade.".to_string(),
+            Self::VoteClosed { channel } =>
+                format!("Omgången är över och resultatet finns i <#{}>.", channel),
+
+            Self::RoleAssignMessage => "meddelande för rolltilldelning".to_string(),
+            Self::ReactionMessageSet { kind, author, channel, content } => format!(
+                "Serverns {} är nu följande meddelande av <@{}> i <#{}>:\n>>> {}",
+                kind, author, channel, content
+            ),
+            Self::ReactionMessageFailed { kind } =>
+                format!("Kunde inte ställa in serverns {}. Se loggarna för detaljer.", kind),
+            Self::MessageNotFound { message, channel } =>
+                format!("Inget meddelande med id {} hittades i <#{}>", message, channel),
+            Self::MessageIdNotNumber { usage } =>
+                format!("Meddelandets id måste vara ett nummer.\n{}", usage),
+
+            Self::NotATextChannel => "Det är inte en textkanal på den här servern.".to_string(),
+            Self::LogChannelOff =>
+                "Arrangörernas åtgärder och fel loggas inte längre i någon kanal.".to_string(),
+            Self::LogChannelSet { channel } =>
+                format!("Arrangörernas åtgärder och fel loggas nu i <#{}>.", channel),
+
+            Self::NothingToUndo => "Det finns inget att ångra.".to_string(),
+            Self::UndoChannelsFailed { game, error } =>
+                format!("Kunde inte återskapa kanalerna för **{}**: {}", game, error),
+            Self::Undid { action, actor, time, description } =>
+                format!("Ångrade \"{}\" av <@{}> {}.\n{}", action, actor, time, description),
+            Self::UndoFailed { action, error } => format!("Kunde inte ångra \"{}\": {}", action, error),
+            Self::TeamRestored { game, user } => format!("Återställde laget **{}** för <@{}>.", game, user),
+            Self::TeamNotRestored { game, user } => format!(
+                "<@{}> har skapat ett nytt lag sedan dess, så laget **{}** återställdes inte.",
+                user, game
+            ),
+            Self::TeamsRestored { count } =>
+                format!("Återställde {} kopplingar mellan användare och kanaler.", count),
+            Self::TeamsKept { count } =>
+                format!("{} användare har skapat nya kanaler sedan dess och behöll dem.", count),
+            Self::RoleRestored { role, emoji } =>
+                format!("Det går att be om **{}** med {} igen.", role, emoji),
+            Self::RoleNotRestored { role, emoji, used_by } =>
+                format!("{} används nu för **{}**, så **{}** återställdes inte.", emoji, used_by, role),
+
+            Self::LanguageName { language } => match language {
+                Language::En => "engelska",
+                Language::Sv => "svenska",
+            }.to_string(),
+            Self::UserLanguageSet { language } => format!(
+                "Jag svarar dig på {}.", Msg::LanguageName { language: *language }.sv()
+            ),
+            Self::UserLanguageReset { language } => format!(
+                "Jag svarar dig på serverns språk, {}.",
+                Msg::LanguageName { language: *language }.sv()
+            ),
+            Self::ServerLanguageSet { language } => format!(
+                "Jag svarar på {} på den här servern, om inte användare väljer ett annat språk \
+                med `{}language`.",
+                Msg::LanguageName { language: *language }.sv(), prefix
+            ),
+            Self::UnknownLanguage { code } => format!(
+                "Jag kan inte språket `{}`. Språken jag kan är {}.",
+                code, language_codes(", ")
+            ),
+
+            Self::UnknownCapability { name, names } =>
+                format!("Det finns ingen behörighet som heter `{}`. Behörigheterna är {}.", name, names),
+            Self::UnknownHolder { holder } =>
+                format!("Det finns ingen roll eller användare som heter **{}** på den här servern.", holder),
+            Self::PermissionGranted { capability, holder } =>
+                format!("{} har nu behörigheten **{}**.", holder, capability),
+            Self::PermissionAlreadyHeld { capability, holder } =>
+                format!("{} har redan behörigheten **{}**.", holder, capability),
+            Self::PermissionRevoked { capability, holder } =>
+                format!("{} har inte längre behörigheten **{}**.", holder, capability),
+            Self::PermissionNotHeld { capability, holder } =>
+                format!("{} har inte behörigheten **{}**.", holder, capability),
+            Self::Nobody => "ingen".to_string(),
+            Self::Permissions { organizer_role, table } => format!(
+                "Rollen **{}** har alla behörigheter. Utöver den:\n{}", organizer_role, table
+            ),
+        }
+    }
+}
+
+/// The names of all scripts theme ideas can be limited to
+fn script_names() -> String {
+    Script::ALL.iter()
+        .map(|script| script.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The names of all theme idea categories, separated by the separator
+fn category_names(separator: &str) -> String {
+    Category::ALL.iter()
+        .map(|category| category.name())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// The codes of all languages, separated by the separator
+fn language_codes(separator: &str) -> String {
+    Language::ALL.iter()
+        .map(|language| language.code())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+impl PersistentState {
+    /// The language to reply to the user in
+    pub fn language_for(&self, user_id: UserId) -> Language {
+        self.user_languages.get(&user_id).cloned().unwrap_or(self.language)
+    }
+
+    /// Sets the language replies are in for users that haven't chosen one
+    fn set_language(&mut self, actor: UserId, language: Language) -> Result<()> {
+        self.language = language;
+        self.save_change(Change::Settings)?;
+        self.record(actor, format!("Set the server language to {}", language.code()), None)
+    }
+
+    /// Sets the language of the user, or goes back to the server language with None
+    fn set_user_language(&mut self, user_id: UserId, language: Option<Language>) -> Result<()> {
+        match language {
+            Some(language) => self.user_languages.insert(user_id, language),
+            None => self.user_languages.remove(&user_id),
+        };
+        self.save_change(Change::Settings)?;
+        self.record(user_id,
+            format!("Set their language to {}", language.map_or("default", Language::code)),
+            None
+        )
+    }
+}
+
+/**
+  The language to reply to the user in on the server. The configured one is
+  used if the state of the server can't be loaded, so that the user can at
+  least be told that something went wrong
+*/
+pub async fn language(guild_id: GuildId, user_id: UserId) -> Language {
+    match PersistentState::for_guild(guild_id).await {
+        Ok(state) => state.lock().await.language_for(user_id),
+        Err(_) => Config::get().language,
+    }
+}
+
+/// Sends the message in the language of the user
+pub async fn reply(
+    http: &dyn DiscordApi,
+    channel_id: ChannelId,
+    guild_id: GuildId,
+    user_id: UserId,
+    msg: Msg<'_>,
+) -> Result<()> {
+    let language = language(guild_id, user_id).await;
+    send_message(http, channel_id, user_id, msg.text(language)).await
+}
+
+pub async fn handle_set_language<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let (language, msg) = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        let current = state.language_for(author.id);
+        match rest_command {
+            ["default"] => {
+                state.set_user_language(author.id, None)?;
+                (state.language, Msg::UserLanguageReset { language: state.language })
+            }
+            [code] => match Language::from_code(code) {
+                Some(language) => {
+                    state.set_user_language(author.id, Some(language))?;
+                    (language, Msg::UserLanguageSet { language })
+                }
+                None => (current, Msg::UnknownLanguage { code }),
+            },
+            _ => (current, Msg::UserLanguageSet { language: current }),
+        }
+    };
+    send_message(http, original_channel, author.id, msg.text(language)).await
+}
+
+pub async fn handle_set_server_language<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let (language, msg) = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        let current = state.language_for(author.id);
+        match rest_command {
+            [code] => match Language::from_code(code) {
+                Some(language) => {
+                    state.set_language(author.id, language)?;
+                    (state.language_for(author.id), Msg::ServerLanguageSet { language })
+                }
+                None => (current, Msg::UnknownLanguage { code }),
+            },
+            _ => (current, Msg::ServerLanguageSet { language: state.language }),
+        }
+    };
+    send_message(http, original_channel, author.id, msg.text(language)).await
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(SetLanguage);
+    registry.register(SetServerLanguage);
+}
+
+struct SetLanguage;
+
+#[async_trait]
+impl Command for SetLanguage {
+    fn name(&self) -> &'static str { "language" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageLanguage) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeLanguage }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_set_language(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct SetServerLanguage;
+
+#[async_trait]
+impl Command for SetServerLanguage {
+    fn name(&self) -> &'static str { "serverlanguage" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageServerLanguage) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeServerLanguage }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_set_server_language(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+      A message of every kind. The match makes leaving a kind out of the list
+      a compile error, like a missing translation is
+    */
+    macro_rules! every_msg {
+        ($($kind:ident $({ $($field:ident: $value:expr),* $(,)? })?,)*) => {{
+            #[allow(dead_code)]
+            fn listed(msg: &Msg<'_>) {
+                match msg {
+                    $(Msg::$kind { .. } => {})*
+                }
+            }
+            vec!($((stringify!($kind), Msg::$kind $({ $($field: $value),* })?)),*)
+        }};
+    }
+
+    /// Messages that are the same in every language, since they only show what to type
+    const SAME_IN_ALL_LANGUAGES: &[&str] = &["UsageLanguage", "UsageServerLanguage"];
+
+    #[test]
+    fn every_msg_is_translated() {
+        Config::init_default();
+        let messages = every_msg!(
+            // Commands and help
+            UnrecognisedCommand { command: "command" },
+            MissingCapability { capability: "capability", command: "command" },
+            CommandFailed { command: "command" },
+            HelpCommands { commands: "commands" },
+            HelpPrivilegedCommands { commands: "commands" },
+            ProperUsage { command: "command", usage: "usage" },
+            InvalidChannelReference { usage: "usage" },
+
+            DescribeHelp,
+            DescribeCreateChannels,
+            DescribeRenameChannels,
+            DescribeRemoveChannels,
+            DescribeClearAssociations,
+            DescribeGiveRole,
+            DescribeLeaveRole,
+            DescribeListRoles,
+            DescribeAddRole,
+            DescribeRemoveRole,
+            DescribeGenerateTheme,
+            DescribeShowAllThemes,
+            DescribeShowThemeCount,
+            DescribeSetRoleAssign,
+            DescribeSetLogChannel,
+            DescribeUndo,
+            DescribeLanguage,
+            DescribeServerLanguage,
+            DescribeGrant,
+            DescribeRevoke,
+            DescribePermissions,
+            DescribeThemeLimit,
+            DescribeThemePolicy,
+            DescribeStartVote,
+            DescribeThemeTemplates,
+            DescribeCategorize,
+            DescribeCloseVote,
+
+            UsageGameName,
+            UsageNewGameName,
+            UsageUserMention,
+            UsageRoleName,
+            UsageRoleAndEmoji,
+            UsageMessageReference,
+            UsageLogChannel,
+            UsageLanguage,
+            UsageServerLanguage,
+            UsagePermission,
+            UsageThemeLimit,
+            UsageThemePolicy,
+            UsageStartVote,
+            UsageThemeTemplates,
+            UsageCategorize,
+
+            // Team channels
+            ChannelsCreated { game: "game", channel: ChannelId(2) },
+            TeamAlreadyCreated { game: "game", channel: ChannelId(2) },
+            NoGameName,
+            InvalidGameName,
+            CategoryNotCreated,
+            TextNotCreated,
+            VoiceNotCreated,
+            CategoryCreationFailed,
+            TextCreationFailed,
+            VoiceCreationFailed,
+            TeamCreationFailed,
+            NothingKept,
+            ChannelsLeftBehind { channels: "channels" },
+            Category,
+            TextChannel,
+            VoiceChannel,
+            And,
+            RenamedCategory { name: "name" },
+            RenamedText { name: "name", channel: ChannelId(2) },
+            RenamedVoice { name: "name" },
+            Renamed { renamed: "renamed", game: "game" },
+            RenamedPartly { renamed: "renamed", game: "game", missing: "missing", count: 4 },
+            RenamedNone { game: "game" },
+            NoTeamYet,
+            InvalidUserReference,
+            InvalidUserId,
+            RemovedCategory { name: "name" },
+            RemovedText { name: "name" },
+            RemovedVoice { name: "name" },
+            Removed { removed: "removed", game: "game" },
+            RemovedPartly { removed: "removed", game: "game", missing: "missing", count: 4 },
+            RemovedNone { game: "game" },
+            NoTeamChannels,
+            MissingUserId,
+            AssociationsCleared { count: 4 },
+
+            // Roles
+            AvailableRoles { roles: "roles" },
+            RoleAssigned { role: "role" },
+            RoleAssignFailed { error: "error" },
+            RoleRemoved { role: "role" },
+            RoleRemoveFailed { error: "error" },
+            RoleCannotBeRequestable { role: "role" },
+            RoleAlreadyRequestable { role: "role" },
+            EmojiAlreadyUsed { emoji: "emoji", role: "role" },
+            OnlyStandardEmojis,
+            OfferCreateRole { role: "role", emoji: "emoji" },
+            RoleCreationFailed { role: "role", error: "error" },
+            RoleMadeRequestable { role: "role", emoji: "emoji" },
+            RoleNoLongerRequestable { role: "role" },
+            NoRequestableRoles,
+            RequestableRoles { roles: "roles" },
+            DiscordError { error: "error" },
+            InvalidRoleName { name: "name" },
+            RoleAlreadySet { role: "role" },
+            RoleNotSet { role: "role" },
+
+            // Themes
+            IdeaEmpty,
+            IdeaTooManyWords { max: 4 },
+            IdeaTooLong { max: 4 },
+            IdeaInvisible,
+            IdeaScript { character: "character" },
+            IdeaLink,
+            IdeaMention,
+            ThemeRegistered { idea: "idea", count: 4, limit: 4 },
+            ThemeReplaced { idea: "idea", previous: "previous" },
+            ThemeDeleted { idea: "idea" },
+            TooManyIdeas { limit: 4 },
+            ThemeIdeas { ideas: "ideas", count: 4, limit: 4 },
+            NoThemeIdeas { limit: 4 },
+            NoSuchIdea { number: "number", count: 4 },
+            ThemePmHelp { limit: 4 },
+            ThemeLimitSet { limit: 4 },
+            InvalidThemeLimit { limit: "limit" },
+            ThemePolicyShown {
+                words: 4, length: 4, scripts: "scripts", links: true, mentions: true,
+            },
+            UnknownPolicySetting { setting: "setting", settings: "settings" },
+            InvalidPolicyValue { setting: "setting", value: "value" },
+            Theme { theme: "theme" },
+            IdeaCategorySet { idea: "idea", category: "category" },
+            IdeasCategorized { idea: "idea", category: "category", count: 4 },
+            NoMatchingIdeas { idea: "idea" },
+            InvalidCategory { category: "category" },
+            ThemeTemplates { templates: "templates" },
+            TemplateAdded { template: "template" },
+            TemplateRemoved { template: "template" },
+            NoSuchTemplate { number: "number" },
+            LastTemplate,
+            TemplateNoSlots,
+            TemplateUnknownSlot { slot: "slot" },
+            TemplateUnclosed,
+            NotEnoughIdeas,
+            ThemeSendFailed,
+            AllThemesTitle,
+            AllThemesSendFailed,
+            NoIdeasSubmitted,
+            ThemeCount { count: 4 },
+            ThemeCountSendFailed,
+            NotAMember,
+            SeveralServers { guilds: "guilds" },
+
+            // Theme vote
+            VoteAnnouncement { round: 4, rounds: 4, candidates: "candidates", deadline: "deadline" },
+            VoteStarted { channel: ChannelId(2) },
+            VoteAlreadyOpen { channel: ChannelId(2) },
+            NoVoteOpen,
+            InvalidCandidateCount { max: 4 },
+            InvalidDuration { duration: "duration" },
+            TooManyRounds { max: 4 },
+            VoteCount { theme: "theme", votes: 4 },
+            VoteRoundEnded { round: 4, results: "results", dropped: "dropped" },
+            VoteWinner { theme: "theme", results: "results" },
+            VoteTie { themes: "themes", results: "results" },
+            VoteNoVotes,
+            VoteClosed { channel: ChannelId(2) },
+
+            // Reaction messages
+            RoleAssignMessage,
+            ReactionMessageSet {
+                kind: "kind", author: UserId(1), channel: ChannelId(2), content: "content",
+            },
+            ReactionMessageFailed { kind: "kind" },
+            MessageNotFound { message: 3, channel: 3 },
+            MessageIdNotNumber { usage: "usage" },
+
+            // Log channel
+            NotATextChannel,
+            LogChannelOff,
+            LogChannelSet { channel: ChannelId(2) },
+
+            // Undo
+            NothingToUndo,
+            UndoChannelsFailed { game: "game", error: "error" },
+            Undid { action: "action", actor: UserId(1), time: "time", description: "description" },
+            UndoFailed { action: "action", error: "error" },
+            TeamRestored { game: "game", user: UserId(1) },
+            TeamNotRestored { game: "game", user: UserId(1) },
+            TeamsRestored { count: 4 },
+            TeamsKept { count: 4 },
+            RoleRestored { role: "role", emoji: "emoji" },
+            RoleNotRestored { role: "role", emoji: "emoji", used_by: "used_by" },
+
+            // Languages
+            LanguageName { language: Language::Sv },
+            UserLanguageSet { language: Language::Sv },
+            UserLanguageReset { language: Language::Sv },
+            ServerLanguageSet { language: Language::Sv },
+            UnknownLanguage { code: "code" },
+
+            // Permissions
+            UnknownCapability { name: "name", names: "names" },
+            UnknownHolder { holder: "holder" },
+            PermissionGranted { capability: "capability", holder: "holder" },
+            PermissionAlreadyHeld { capability: "capability", holder: "holder" },
+            PermissionRevoked { capability: "capability", holder: "holder" },
+            PermissionNotHeld { capability: "capability", holder: "holder" },
+            Nobody,
+            Permissions { organizer_role: "organizer_role", table: "table" },
+        );
+
+        let untranslated = messages.into_iter()
+            .filter(|(kind, msg)| {
+                !SAME_IN_ALL_LANGUAGES.contains(kind) && msg.text(Language::Sv) == msg.text(Language::En)
+            })
+            .map(|(kind, _)| kind)
+            .collect::<Vec<_>>();
+        assert!(untranslated.is_empty(), "Not translated: {:?}", untranslated);
+    }
+}
@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing::warn;
+use twilight::model::{
+    channel::{
+        Channel, GuildChannel,
+        embed::{Embed, EmbedField},
+    },
+    id::{ChannelId, GuildId, UserId},
+    user::User,
+};
+
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::discord::DiscordApi;
+use crate::locale::{language, Msg};
+use crate::permission::Capability;
+use crate::state::PersistentState;
+use crate::utils::{EMBED_DESCRIPTION_LIMIT, Result, embed, send_message};
+
+const DONE_COLOR: u32 = 0x43b581;
+const FAILED_COLOR: u32 = 0xf04747;
+
+/// How an action posted in the log channel went
+pub enum Outcome {
+    Done(String),
+    Failed(String),
+}
+
+/**
+  Posts an entry about an action in the organizer log channel of the guild,
+  if it has one. The entry says who did what, when and how it went.
+
+  Failing to post is only logged, since it shouldn't affect the action itself
+*/
+pub async fn log_action(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    actor: UserId,
+    action: impl Into<String>,
+    outcome: Outcome,
+) {
+    let channel_id = match PersistentState::for_guild(guild_id).await {
+        Ok(state) => state.lock().await.get_log_channel(),
+        Err(e) => {
+            warn!(guild = %guild_id, error = ?e, "Failed to look up the log channel");
+            return
+        }
+    };
+    if channel_id.0 == 0 {
+        return
+    }
+
+    let (color, description) = match outcome {
+        Outcome::Done(description) => (DONE_COLOR, description),
+        Outcome::Failed(description) => (FAILED_COLOR, description),
+    };
+    let description = if description.chars().count() > EMBED_DESCRIPTION_LIMIT {
+        let mut shortened = description.chars()
+            .take(EMBED_DESCRIPTION_LIMIT - 1)
+            .collect::<String>();
+        shortened.push('…');
+        shortened
+    }
+    else {
+        description
+    };
+
+    let embed = Embed {
+        color: Some(color),
+        fields: vec!(EmbedField {
+            inline: true,
+            name: "By".to_string(),
+            value: format!("<@{}>", actor),
+        }),
+        timestamp: Some(Utc::now().to_rfc3339()),
+        ..embed(action, description)
+    };
+    if let Err(e) = http.create_embed(channel_id, None, embed).await {
+        warn!(guild = %guild_id, channel = %channel_id, error = ?e,
+            "Failed to post in the log channel");
+    }
+}
+
+pub async fn handle_set_log_channel<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    lazy_static! {
+        static ref CHANNEL_MENTION_REGEX: Regex =
+            Regex::new(r"^<#(\d+)>$").unwrap();
+    }
+    let language = language(guild_id, author.id).await;
+    let usage = Msg::ProperUsage {
+        command: SetLogChannel.name(),
+        usage: &Msg::UsageLogChannel.text(language),
+    }.text(language);
+
+    let channel_id = match rest_command {
+        ["off"] => ChannelId(0),
+        [mention] => {
+            let id = CHANNEL_MENTION_REGEX.captures(mention)
+                .and_then(|captures| captures[1].parse::<u64>().ok());
+            match id {
+                Some(id) => ChannelId(id),
+                None => {
+                    send_message(http, original_channel, author.id,
+                        Msg::InvalidChannelReference { usage: &usage }.text(language)
+                    ).await?;
+                    return Ok(())
+                }
+            }
+        }
+        _ => {
+            send_message(http, original_channel, author.id, usage).await?;
+            return Ok(())
+        }
+    };
+
+    if channel_id.0 != 0 {
+        let channel_guild = match http.channel(channel_id).await {
+            Ok(Some(Channel::Guild(GuildChannel::Category(c)))) => c.guild_id,
+            Ok(Some(Channel::Guild(GuildChannel::Text(c)))) => c.guild_id,
+            _ => None,
+        };
+        if channel_guild != Some(guild_id) {
+            send_message(http, original_channel, author.id,
+                Msg::NotATextChannel.text(language)
+            ).await?;
+            return Ok(())
+        }
+    }
+
+    PersistentState::for_guild(guild_id).await?.lock().await.set_log_channel(author.id, channel_id)?;
+
+    if channel_id.0 == 0 {
+        send_message(http, original_channel, author.id,
+            Msg::LogChannelOff.text(language)
+        ).await?;
+    }
+    else {
+        send_message(http, original_channel, author.id,
+            Msg::LogChannelSet { channel: channel_id }.text(language)
+        ).await?;
+        log_action(http, guild_id, author.id, "Log channel set",
+            Outcome::Done(format!("Entries will be posted in <#{}>.", channel_id))
+        ).await;
+    }
+    Ok(())
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(SetLogChannel);
+}
+
+struct SetLogChannel;
+
+#[async_trait]
+impl Command for SetLogChannel {
+    fn name(&self) -> &'static str { "setlogchannel" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageLogChannel) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeSetLogChannel }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_set_log_channel(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http)
+            .await
+    }
+}
@@ -0,0 +1,50 @@
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter, Registry};
+
+use crate::config::Config;
+use crate::utils::Result;
+
+const LOG_FILE_PREFIX: &'static str = "gamejambot.log";
+
+/**
+  Sends logs to stderr in a readable format, and to a daily rotated file
+  as one json object per line so that it can be searched by guild, user
+  or command after the jam.
+
+  The returned guard flushes the file when dropped, so keep it alive until
+  the bot exits
+*/
+pub fn init() -> Result<WorkerGuard> {
+    let config = &Config::get().log;
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.level)?,
+    };
+
+    let file = tracing_appender::rolling::daily(&config.directory, LOG_FILE_PREFIX);
+    let (file_writer, guard) = tracing_appender::non_blocking(file);
+
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(fmt::layer().json().with_writer(file_writer));
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    Ok(guard)
+}
+
+/**
+  Sends warnings and errors to stderr only, for the command line tools,
+  which print their own output to stdout
+*/
+pub fn init_console() -> Result<()> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new("warn")?,
+    };
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr));
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use std::env;
+use std::sync::Arc;
 
 use tokio::stream::StreamExt;
+use tracing::{error, info};
 use twilight::{
     cache::{
         twilight_cache_inmemory::config::{EventType, InMemoryConfigBuilder},
@@ -10,31 +12,63 @@ use twilight::{
     gateway::shard::Event,
     http::Client as HttpClient,
     model::{
-        channel::{Channel, Message},
+        channel::Message,
         gateway::GatewayIntents,
-        id::{ChannelId, GuildId, UserId},
+        id::{GuildId, UserId},
         user::CurrentUser,
     },
 };
 
 mod channel;
+mod cli;
+mod command;
+mod config;
+mod discord;
+mod journal;
+mod locale;
+mod log_channel;
+mod logging;
+mod migration;
+mod permission;
+mod queue;
 mod reaction;
+mod retry;
 mod role;
-mod roles;
 mod state;
+mod storage;
 mod theme;
+mod theme_policy;
+mod theme_template;
 mod utils;
+mod vote;
 
-use channel::{handle_create_channels, handle_remove_channels, handle_clear_channel_associations, handle_rename_channels};
-use reaction::{handle_reaction_add, handle_reaction_remove, handle_set_reaction_message, ReactionMessageType};
-use role::{handle_give_role, handle_remove_role, has_role};
-use roles::ORGANIZER;
-use theme::{handle_add_theme, handle_generate_theme, handle_show_all_themes, handle_show_theme_count};
-use utils::{Result, send_message};
+use command::{CommandRegistry, Help};
+use config::Config;
+use discord::{CachedClient, DiscordApi};
+use locale::Msg;
+use queue::UserQueues;
+use reaction::{handle_reaction_add, handle_reaction_remove};
+use state::PersistentState;
+use theme::handle_theme_pm;
+use utils::Result;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv::dotenv().ok();
+    Config::init()?;
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        logging::init_console()?;
+        storage::init()?;
+        return cli::run(&args).await;
+    }
+
+    let _log_guard = logging::init()?;
+    storage::init()?;
+    if !std::path::Path::new(config::FILENAME).exists() {
+        info!("No {} found, using the default config", config::FILENAME);
+    }
     let token = env::var("DISCORD_TOKEN")?;
 
     // This is also the default.
@@ -42,9 +76,12 @@ async fn main() -> Result<()> {
 
     let config = ClusterConfig::builder(&token)
         .shard_scheme(scheme)
-        // Use intents to only listen to GUILD_MESSAGES events
+        // Use intents to only listen to messages, reactions and the
+        // guild, role and member events that keep the cache up to date
         .intents(Some(
-            GatewayIntents::GUILD_MESSAGES
+            GatewayIntents::GUILDS
+                | GatewayIntents::GUILD_MEMBERS
+                | GatewayIntents::GUILD_MESSAGES
                 | GatewayIntents::DIRECT_MESSAGES
                 | GatewayIntents::GUILD_MESSAGE_REACTIONS,
         ))
@@ -54,78 +91,138 @@ async fn main() -> Result<()> {
     let cluster = Cluster::new(config);
     cluster.up().await?;
 
-    // The http client is seperate from the gateway,
-    // so startup a new one
-    let http = HttpClient::new(&token);
-
-    // Since we only care about messages and reactions, make
-    // the cache only cache message and reaction related events
+    // Permission checks and role changes look at the roles and members of
+    // the guild and the channels, so make the cache keep track of those
     let cache_config = InMemoryConfigBuilder::new()
         .event_types(
-            EventType::MESSAGE_CREATE
-                | EventType::MESSAGE_DELETE
-                | EventType::MESSAGE_DELETE_BULK
-                | EventType::MESSAGE_UPDATE
-                | EventType::REACTION_ADD
-                | EventType::REACTION_REMOVE,
+            EventType::GUILD_CREATE
+                | EventType::GUILD_DELETE
+                | EventType::GUILD_UPDATE
+                | EventType::ROLE_CREATE
+                | EventType::ROLE_DELETE
+                | EventType::ROLE_UPDATE
+                | EventType::MEMBER_ADD
+                | EventType::MEMBER_CHUNK
+                | EventType::MEMBER_REMOVE
+                | EventType::MEMBER_UPDATE
+                | EventType::CHANNEL_CREATE
+                | EventType::CHANNEL_DELETE
+                | EventType::CHANNEL_UPDATE,
         )
         .build();
     let cache = InMemoryCache::from(cache_config);
 
+    // The http client is seperate from the gateway,
+    // so startup a new one
+    let client = HttpClient::new(&token);
+    // Roles, members and channels are read from the cache when it has them
+    let http: Arc<dyn DiscordApi> = Arc::new(CachedClient {
+        http: client.clone(),
+        cache: cache.clone(),
+    });
+
+    state::migrate_legacy_state(&*http).await?;
+
+
+    let mut commands = CommandRegistry::new();
+    commands.register(Help);
+    role::register_commands(&mut commands);
+    channel::register_commands(&mut commands);
+    theme::register_commands(&mut commands);
+    theme_policy::register_commands(&mut commands);
+    theme_template::register_commands(&mut commands);
+    vote::register_commands(&mut commands);
+    reaction::register_commands(&mut commands);
+    log_channel::register_commands(&mut commands);
+    journal::register_commands(&mut commands);
+    locale::register_commands(&mut commands);
+    permission::register_commands(&mut commands);
+
+    let commands = Arc::new(commands);
+    let queues = UserQueues::new();
 
     let mut events = cluster.events().await;
 
-    let current_user = http.current_user().await?;
+    let current_user = Arc::new(client.current_user().await?);
+    // Theme votes move on to their next round without anyone asking, see vote.rs
+    tokio::spawn(vote::watch_deadlines(http.clone(), current_user.id));
     // Startup an event loop for each event in the event stream
     while let Some(event) = events.next().await {
         // Update the cache
         cache.update(&event.1).await.expect("Cache failed, OhNoe!");
 
-        // Spawn a new task to handle the event
-        handle_event(event, http.clone(), &current_user).await?;
+        let user_id = event_user(&event.1);
+        let http = http.clone();
+        let current_user = current_user.clone();
+        let commands = commands.clone();
+        let task = async move {
+            if let Err(e) = handle_event(event, &*http, &current_user, &commands).await {
+                error!(user = ?user_id, error = ?e, "Failed to handle event");
+            }
+        };
+
+        // Handle the event in a new task, after earlier events of the same user
+        match user_id {
+            Some(user_id) => queues.push(user_id, task),
+            None => {
+                tokio::spawn(task);
+            }
+        }
     }
 
     Ok(())
 }
 
-/// Checks if the specified channel is a private message channel
-async fn is_pm(http: &HttpClient, channel_id: ChannelId) -> Result<bool> {
-    match http.channel(channel_id).await?.unwrap() {
-        Channel::Private(_) => Ok(true),
-        _ => Ok(false)
+/// The user that caused the event, if its handling depends on earlier events of the user
+fn event_user(event: &Event) -> Option<UserId> {
+    match event {
+        Event::MessageCreate(msg) => Some(msg.author.id),
+        Event::ReactionAdd(reaction) => Some(reaction.user_id),
+        Event::ReactionRemove(reaction) => Some(reaction.user_id),
+        _ => None,
     }
 }
 
 async fn handle_event(
     event: (u64, Event),
-    http: HttpClient,
-    current_user: &CurrentUser
+    http: &dyn DiscordApi,
+    current_user: &CurrentUser,
+    commands: &CommandRegistry,
 ) -> Result<()> {
     match event {
         (_, Event::MessageCreate(msg)) => {
             // Don't send replies to yourself
             if msg.author.id != current_user.id {
-                if is_pm(&http, msg.channel_id).await? {
-                    handle_pm(&msg, &http).await?;
+                // Only private messages come without a guild
+                if msg.guild_id.is_none() {
+                    handle_pm(&msg, http).await?;
                 }
                 else {
-                    handle_potential_command(&msg, http, current_user)
+                    commands.handle_message(&msg, http, current_user)
                         .await?;
                 }
             }
         }
         (_, Event::ReactionAdd(reaction)) => {
-            if !is_pm(&http, reaction.channel_id).await? {
+            if reaction.guild_id.is_some() {
                 handle_reaction_add(&reaction, http, &current_user).await?;
             }
         }
         (_, Event::ReactionRemove(reaction)) => {
-            if !is_pm(&http, reaction.channel_id).await? {
-                handle_reaction_remove(&reaction, http).await?;
+            if reaction.guild_id.is_some() {
+                handle_reaction_remove(&reaction, http, &current_user).await?;
             }
         }
+        (_, Event::GuildCreate(guild)) => {
+            // Load the state up front so that PMs can be matched to the guild
+            let state = PersistentState::for_guild(guild.id).await?;
+            // Roles that were given capabilities in the config can only be
+            // looked up now that the roles of the guild are known
+            let roles = guild.roles.values().cloned().collect::<Vec<_>>();
+            state.lock().await.resolve_role_names(current_user.id, &roles)?;
+        }
         (id, Event::ShardConnected(_)) => {
-            println!("Connected on shard {}", id);
+            info!(shard = id, "Connected");
         }
         _ => {}
     }
@@ -136,175 +233,58 @@ async fn handle_event(
 
 async fn handle_pm(
     msg: &Message,
-    http: &HttpClient,
+    http: &dyn DiscordApi,
 ) -> Result<()> {
-    handle_add_theme(http, msg).await?;
+    if let Some((guild_id, content)) = pm_guild(msg, http).await? {
+        handle_theme_pm(http, msg, guild_id, content).await?;
+    }
     Ok(())
 }
 
-async fn handle_potential_command(
-    msg: &Message,
-    http: HttpClient,
-    current_user: &CurrentUser
-) -> Result<()> {
-    let mut words = msg.content.split_ascii_whitespace();
-    match words.next() {
-        Some("!help") => {
-            send_help_message(
-                http,
-                msg.channel_id,
-                msg.author.id,
-                msg.guild_id.expect("Tried to call for help in non-guild"),
-            ).await?;
-        }
-        Some("!createchannels") => {
-            handle_create_channels(
-                &words.collect::<Vec<_>>(),
-                msg.channel_id,
-                msg.guild_id.expect("Tried to create channels in non-guild"),
-                msg.author.id,
-                current_user.id,
-                http
-            ).await?;
-        },
-        Some("!renamechannels") => {
-            handle_rename_channels(
-                &words.collect::<Vec<_>>(),
-                msg.channel_id,
-                msg.author.id,
-                current_user.id,
-                http
-            ).await?;
-        },
-        Some("!removechannels") => {
-            handle_remove_channels(
-                &words.collect::<Vec<_>>(),
-                msg.channel_id,
-                msg.guild_id.expect("Tried to remove channels in non-guild"),
-                msg.author.id,
-                http
-            ).await?;
-        },
-        Some("!clearassociations") => {
-            handle_clear_channel_associations(
-                msg.channel_id,
-                msg.guild_id.expect("Tried to clear channel associations in non-guild"),
-                msg.author.id,
-                http,
-            ).await?;
-        }
-        Some("!role") => {
-            handle_give_role(
-                &words.collect::<Vec<_>>(),
-                msg.channel_id,
-                msg.guild_id.expect("Tried to get role in non-guild"),
-                &msg.author,
-                http
-            ).await?;
-        },
-        Some("!leave") => {
-            handle_remove_role(
-                &words.collect::<Vec<_>>(),
-                msg.channel_id,
-                msg.guild_id.expect("Tried to leave role in non-guild"),
-                &msg.author,
-                http
-            ).await?;
-        },
-        Some("!generatetheme") => {
-            handle_generate_theme(
-                msg.channel_id,
-                msg.guild_id.expect("Tried to generate theme in non-guild"),
-                &msg.author,
-                http
-            ).await?;
-        }
-        Some("!showallthemes") => {
-            handle_show_all_themes(
-                msg.channel_id,
-                msg.guild_id.expect("Tried to show all themes in non-guild"),
-                &msg.author,
-                http
-            ).await?;
+/**
+  Works out which guild a PM is meant for. Users that share several guilds
+  with the bot have to start the message with the ID of the guild.
+
+  Returns the guild and the rest of the message, or None if the user has
+  been told why the guild couldn't be found
+*/
+async fn pm_guild<'a>(
+    msg: &'a Message,
+    http: &dyn DiscordApi,
+) -> Result<Option<(GuildId, &'a str)>> {
+    let content = msg.content.trim();
+    let guilds = PersistentState::loaded_guilds();
+    // The guild decides the language, so until it is known use the configured one
+    let language = Config::get().language;
+
+    let mut words = content.splitn(2, char::is_whitespace);
+    if let Some(Ok(id)) = words.next().map(str::parse::<u64>) {
+        if guilds.contains(&GuildId(id)) {
+            return Ok(Some((GuildId(id), words.next().unwrap_or("").trim())))
         }
-        Some("!showthemecount") => {
-            handle_show_theme_count(
-                msg.channel_id,
-                msg.guild_id.expect("Tried to show theme idea count in non-guild"),
-                &msg.author,
-                http
-            ).await?;
+    }
+
+    let mut shared_guilds = Vec::new();
+    for guild_id in guilds {
+        if let Ok(Some(_)) = http.guild_member_roles(guild_id, msg.author.id).await {
+            shared_guilds.push(guild_id);
         }
-        Some("!setroleassign") => {
-            handle_set_reaction_message(
-                &words.collect::<Vec<_>>(),
-                msg.channel_id,
-                msg.guild_id.expect("Tried to set role assignment message in non-guild"),
-                &msg.author,
-                http,
-                msg,
-                ReactionMessageType::RoleAssign,
-            ).await?;
+    }
+    match shared_guilds.as_slice() {
+        [guild_id] => Ok(Some((*guild_id, content))),
+        [] => {
+            http.create_message(msg.channel_id, Msg::NotAMember.text(language)).await?;
+            Ok(None)
         }
-        Some(s) if s.chars().next() == Some('!') => {
-            send_message(&http, msg.channel_id, msg.author.id,
-                format!("Unrecognised command `{}`.", s)
+        _ => {
+            let guilds = shared_guilds.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            http.create_message(msg.channel_id,
+                Msg::SeveralServers { guilds: &guilds }.text(language)
             ).await?;
-            send_help_message(
-                http,
-                msg.channel_id,
-                msg.author.id,
-                msg.guild_id.expect("Tried to issue a command in non-guild"),
-            ).await?;
-        }
-        // Not a command and probably not for us
-        Some(_) => {
-            // Check if we were mentioned
-            if msg.mentions.contains_key(&current_user.id) {
-                send_help_message(
-                    http,
-                    msg.channel_id,
-                    msg.author.id,
-                    msg.guild_id.expect("Tried to mention us in non-guild"),
-                ).await?;
-            }
+            Ok(None)
         }
-        None => {}
     }
-    Ok(())
-}
-
-async fn send_help_message(
-    http: HttpClient,
-    channel_id: ChannelId,
-    user_id: UserId,
-    guild_id: GuildId,
-) -> Result<()> {
-    let standard_message =
-        //"Send me a PM to submit theme ideas.\n\n\
-        "Get a role to signify one of your skill sets with the command `!role <role name>`\n\
-        and leave a role with `!leave <role name>`.\n\n\
-        You can also ask for text and voice channels for your game \
-        with the command `!createchannels <game name>`\n\
-        and rename them with `!renamechannels <new game name>`.";
-    let organizer_message = format!(
-        "Since you have the **{}** role, you also have access to the \
-        following commands:\n\
-        - `!generatetheme` to generate a theme.\n\
-        - `!showallthemes` to view all the theme ideas that have been submitted.\n\
-        - `!showthemecount` to see the number of theme ideas that have been submitted.\n\
-        - `!removechannels <mention of user>` to remove a user's created channel.\n\
-        - `!clearassociations` to clear all user–channel associations.\n\
-        - `!setroleassign <mention of channel with the message> <message ID>` to \
-        set the server's role assignment message.", ORGANIZER
-    );
-    let help_message =
-    if has_role(&http, guild_id, user_id, ORGANIZER).await? {
-        format!("{}\n\n{}", standard_message, organizer_message)
-    }
-    else {
-        standard_message.to_string()
-    };
-    send_message(&http, channel_id, user_id, help_message).await?;
-    Ok(())
 }
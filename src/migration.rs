@@ -0,0 +1,337 @@
+use anyhow::anyhow;
+use serde_json::Value;
+use tracing::info;
+
+use crate::config::Config;
+use crate::utils::Result;
+
+/// Upgrades the json of a state file from one version to the next
+type Migration = fn(&mut Value) -> Result<()>;
+
+/**
+  The nth migration upgrades a state file from version n to version n + 1.
+
+  When the layout of `PersistentState` changes, add a migration at the end
+  instead of changing the existing ones, since old files can still be around
+*/
+const MIGRATIONS: &[Migration] = &[
+    add_version,
+    add_requestable_roles,
+    add_log_channel,
+    add_languages,
+    add_permissions,
+    list_theme_ideas,
+    add_theme_policy,
+    add_vote,
+    add_vote_rounds,
+    categorize_theme_ideas,
+    identify_permission_roles,
+];
+
+/// The version of files written by this build
+pub const CURRENT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// Version 0 files predate the version field but are otherwise the same as version 1
+fn add_version(_: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// Version 2 keeps the requestable roles per guild, starting out as the configured ones
+fn add_requestable_roles(state: &mut Value) -> Result<()> {
+    let roles = serde_json::to_value(&Config::get().roles)?;
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("requestable_roles".to_string(), roles);
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Version 3 has an organizer log channel, which starts out unset
+fn add_log_channel(state: &mut Value) -> Result<()> {
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("log_channel_id".to_string(), "0".into());
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Version 4 has a reply language, which starts out as the configured one, and languages per user
+fn add_languages(state: &mut Value) -> Result<()> {
+    let language = serde_json::to_value(&Config::get().language)?;
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("language".to_string(), language);
+            object.insert("user_languages".to_string(), serde_json::json!({}));
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Version 5 keeps who has each capability per guild, starting out as configured
+fn add_permissions(state: &mut Value) -> Result<()> {
+    let permissions = serde_json::to_value(&Config::get().permissions)?;
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("permissions".to_string(), permissions);
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/**
+  Version 6 has a list of theme ideas per user instead of a single one, and
+  a limit on their number which starts out as the configured one
+*/
+fn list_theme_ideas(state: &mut Value) -> Result<()> {
+    let max_ideas = Config::get().themes.max_ideas;
+    match state.as_object_mut() {
+        Some(object) => {
+            if let Some(Value::Object(ideas)) = object.get_mut("theme_ideas") {
+                for idea in ideas.values_mut() {
+                    *idea = Value::Array(vec!(idea.take()));
+                }
+            }
+            object.insert("max_theme_ideas".to_string(), max_ideas.into());
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Version 7 has a policy for theme ideas, which starts out as the configured one
+fn add_theme_policy(state: &mut Value) -> Result<()> {
+    let policy = serde_json::to_value(&Config::get().themes.policy)?;
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("theme_policy".to_string(), policy);
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Version 8 can have an open theme vote, which there is none of at first
+fn add_vote(state: &mut Value) -> Result<()> {
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("vote".to_string(), Value::Null);
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Version 9 votes can have several rounds, and open votes of older versions have only one
+fn add_vote_rounds(state: &mut Value) -> Result<()> {
+    match state.as_object_mut() {
+        Some(object) => {
+            if let Some(Value::Object(vote)) = object.get_mut("vote") {
+                vote.insert("round".to_string(), 1.into());
+                vote.insert("later_rounds".to_string(), serde_json::json!([]));
+            }
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/**
+  Version 10 theme ideas can have a category, which ideas of older versions
+  don't have, and generated themes follow templates which start out as the
+  configured ones
+*/
+fn categorize_theme_ideas(state: &mut Value) -> Result<()> {
+    let templates = serde_json::to_value(&Config::get().themes.templates)?;
+    match state.as_object_mut() {
+        Some(object) => {
+            if let Some(Value::Object(ideas)) = object.get_mut("theme_ideas") {
+                for idea in ideas.values_mut().filter_map(Value::as_array_mut).flatten() {
+                    *idea = serde_json::json!({ "text": idea.take(), "category": null });
+                }
+            }
+            object.insert("theme_templates".to_string(), templates);
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/**
+  Version 11 keeps the roles that have been given capabilities by id. The
+  names older versions have are kept apart until the roles of the guild
+  are known, see `PersistentState::resolve_role_names`
+*/
+fn identify_permission_roles(state: &mut Value) -> Result<()> {
+    match state.as_object_mut() {
+        Some(object) => {
+            if let Some(Value::Object(permissions)) = object.get_mut("permissions") {
+                for holders in permissions.values_mut().filter_map(Value::as_object_mut) {
+                    let names = holders.remove("roles").unwrap_or_else(|| serde_json::json!([]));
+                    holders.insert("role_names".to_string(), names);
+                    holders.insert("roles".to_string(), serde_json::json!([]));
+                }
+            }
+            Ok(())
+        }
+        None => Err(anyhow!("The state is not a json object")),
+    }
+}
+
+/// Upgrades the json of a state file of any known version to the current version
+pub fn migrate(mut state: Value) -> Result<Value> {
+    let version = match state.get("version") {
+        Some(version) => version.as_u64()
+            .ok_or_else(|| anyhow!("The state version {} is not a number", version))?,
+        None => 0,
+    };
+    if version > CURRENT_VERSION {
+        return Err(anyhow!(
+            "The state is of version {} but this bot only knows up to version {}",
+            version, CURRENT_VERSION
+        ))
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut state)?;
+        info!("Migrated state from version {} to {}", from, from + 1);
+    }
+
+    match state.as_object_mut() {
+        Some(object) => {
+            object.insert("version".to_string(), CURRENT_VERSION.into());
+        }
+        None => return Err(anyhow!("The state is not a json object")),
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use twilight::model::id::{ChannelId, GuildId, RoleId, UserId};
+
+    use super::*;
+    use crate::locale::Language;
+    use crate::state::PersistentState;
+    use crate::theme::ThemeIdea;
+    use crate::theme_template::Category;
+
+    /// A state file of each version, all describing the same jam as far as the version allows
+    const FIXTURES: &[&str] = &[
+        include_str!("../tests/fixtures/state_v0.json"),
+        include_str!("../tests/fixtures/state_v1.json"),
+        include_str!("../tests/fixtures/state_v2.json"),
+        include_str!("../tests/fixtures/state_v3.json"),
+        include_str!("../tests/fixtures/state_v4.json"),
+        include_str!("../tests/fixtures/state_v5.json"),
+        include_str!("../tests/fixtures/state_v6.json"),
+        include_str!("../tests/fixtures/state_v7.json"),
+        include_str!("../tests/fixtures/state_v8.json"),
+        include_str!("../tests/fixtures/state_v9.json"),
+        include_str!("../tests/fixtures/state_v10.json"),
+        include_str!("../tests/fixtures/state_v11.json"),
+    ];
+
+    const GUILD: GuildId = GuildId(1);
+    const USER: UserId = UserId(10);
+
+    fn load(version: usize) -> PersistentState {
+        Config::init_default();
+        PersistentState::parse(FIXTURES[version], GUILD)
+            .unwrap_or_else(|e| panic!("Version {} can't be loaded: {:#}", version, e))
+    }
+
+    #[test]
+    fn there_is_a_fixture_of_every_version() {
+        assert_eq!(FIXTURES.len() as u64, CURRENT_VERSION + 1);
+    }
+
+    #[test]
+    fn every_version_migrates_to_the_current_one() {
+        for version in 0..FIXTURES.len() {
+            let state = load(version);
+            let json = serde_json::to_value(&state).unwrap();
+            assert_eq!(json["version"], CURRENT_VERSION, "version {}", version);
+            assert_eq!(state.guild_id(), GUILD);
+
+            let team = &state.channel_creators[&USER];
+            assert_eq!(team.game_name(), "Frog Game", "version {}", version);
+            assert_eq!(team.voice_id(), ChannelId(302), "version {}", version);
+            assert_eq!(state.get_role_assign_channel(), ChannelId(200), "version {}", version);
+            assert_eq!(state.theme_ideas[&USER][0].text, "Space frogs", "version {}", version);
+        }
+    }
+
+    #[test]
+    fn added_settings_start_out_as_configured() {
+        let config = Config::get();
+        let state = load(0);
+        assert_eq!(state.requestable_roles.len(), config.roles.len());
+        assert_eq!(state.get_log_channel(), ChannelId(0));
+        assert_eq!(state.language, config.language);
+        assert!(state.user_languages.is_empty());
+        assert_eq!(state.permissions.len(), config.permissions.len());
+        assert_eq!(state.max_theme_ideas, config.themes.max_ideas);
+        assert_eq!(state.theme_policy.max_words, config.themes.policy.max_words);
+        assert_eq!(state.theme_templates, config.themes.templates);
+        assert!(state.vote.is_none());
+    }
+
+    #[test]
+    fn stored_settings_are_kept() {
+        let state = load(CURRENT_VERSION as usize - 1);
+        assert_eq!(state.requestable_roles[1].name, "Artist");
+        assert_eq!(state.get_log_channel(), ChannelId(400));
+        assert_eq!(state.language, Language::Sv);
+        assert_eq!(state.user_languages[&UserId(11)], Language::En);
+        assert_eq!(state.permissions["theme.view"].users, vec!(UserId(12)));
+        assert_eq!(state.max_theme_ideas, 3);
+        assert_eq!(state.theme_policy.max_words, 4);
+    }
+
+    #[test]
+    fn single_theme_ideas_become_uncategorized_lists() {
+        assert_eq!(load(5).theme_ideas[&USER], vec!(ThemeIdea {
+            text: "Space frogs".to_string(),
+            category: None,
+        }));
+        assert_eq!(load(9).theme_ideas[&USER].len(), 2);
+        assert_eq!(load(10).theme_ideas[&USER][0].category, Some(Category::Noun));
+    }
+
+    #[test]
+    fn votes_without_rounds_get_a_single_round() {
+        let vote = load(8).vote.unwrap();
+        assert_eq!(vote.round, 1);
+        assert!(vote.later_rounds.is_empty());
+        assert_eq!(vote.candidates[0].voters.len(), 1);
+
+        let vote = load(9).vote.unwrap();
+        assert_eq!(vote.round, 2);
+        assert_eq!(vote.later_rounds, vec!(3600));
+    }
+
+    #[test]
+    fn permission_roles_are_kept_by_name_until_looked_up() {
+        let holders = &load(10).permissions["theme.view"];
+        assert!(holders.roles.is_empty());
+        assert_eq!(holders.role_names, vec!("Judge".to_string()));
+        assert_eq!(holders.users, vec!(UserId(12)));
+
+        let holders = &load(11).permissions["theme.view"];
+        assert_eq!(holders.roles, vec!(RoleId(600)));
+        assert!(holders.role_names.is_empty());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        Config::init_default();
+        let newer = format!("{{\"version\": {}}}", CURRENT_VERSION + 1);
+        assert!(PersistentState::parse(&newer, GUILD).is_err());
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+use std::mem;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use tracing::warn;
+use twilight::model::{
+    guild::Role,
+    id::{ChannelId, GuildId, RoleId, UserId},
+    user::User,
+};
+
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::config::{Config, PermissionHolders};
+use crate::discord::DiscordApi;
+use crate::locale::{language, Msg};
+use crate::state::PersistentState;
+use crate::storage::Change;
+use crate::utils::{Result, send_message};
+
+/// Something privileged commands need. The organizer role has all of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// Seeing the submitted theme ideas and how many there are
+    ThemeView,
+    ThemeGenerate,
+    TeamsRemove,
+    TeamsClear,
+    /// Changing which roles can be requested and the role assignment message
+    RolesConfigure,
+    /// Changing the log channel, language and permissions, and undoing
+    ServerConfigure,
+}
+
+impl Capability {
+    pub const ALL: &'static [Capability] = &[
+        Capability::ThemeView,
+        Capability::ThemeGenerate,
+        Capability::TeamsRemove,
+        Capability::TeamsClear,
+        Capability::RolesConfigure,
+        Capability::ServerConfigure,
+    ];
+
+    /// The name used in the config and in commands, e.g. `theme.view`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ThemeView => "theme.view",
+            Self::ThemeGenerate => "theme.generate",
+            Self::TeamsRemove => "teams.remove",
+            Self::TeamsClear => "teams.clear",
+            Self::RolesConfigure => "roles.configure",
+            Self::ServerConfigure => "server.configure",
+        }
+    }
+
+    /// Finds the capability with the name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter()
+            .cloned()
+            .find(|capability| capability.name() == name.to_lowercase())
+    }
+}
+
+/// Roles and users that have been given a capability on the server
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Holders {
+    pub roles: Vec<RoleId>,
+    pub users: Vec<UserId>,
+    /**
+      Roles from the config, which only has their names. They are looked up
+      on the server when it becomes available, names of roles the server
+      doesn't have stay here until it does
+    */
+    pub role_names: Vec<String>,
+}
+
+impl From<&PermissionHolders> for Holders {
+    fn from(configured: &PermissionHolders) -> Self {
+        Self {
+            roles: Vec::new(),
+            users: configured.users.clone(),
+            role_names: configured.roles.clone(),
+        }
+    }
+}
+
+/// A role, with its name at the time, or a user that can be given a capability
+#[derive(Clone, Debug)]
+enum Holder {
+    Role(RoleId, String),
+    User(UserId),
+}
+
+impl Holder {
+    /// How the holder is shown in replies
+    fn mention(&self) -> String {
+        match self {
+            Self::Role(_, name) => format!("**{}**", name),
+            Self::User(user_id) => format!("<@{}>", user_id),
+        }
+    }
+}
+
+impl PersistentState {
+    /// The roles and users that have been given the capability
+    pub fn holders(&self, capability: Capability) -> Option<&Holders> {
+        self.permissions.get(capability.name())
+    }
+
+    /**
+      Replaces the role names that came from the config with the ids of the
+      roles of the server that have those names. The actor is whoever the
+      roles were looked up for, usually the bot itself
+    */
+    pub fn resolve_role_names(&mut self, actor: UserId, roles: &[Role]) -> Result<()> {
+        let guild_id = self.guild_id();
+        let mut resolved = Vec::new();
+        for (capability, holders) in self.permissions.iter_mut() {
+            for name in mem::take(&mut holders.role_names) {
+                match roles.iter().find(|role| role.name.to_lowercase() == name.to_lowercase()) {
+                    Some(role) => {
+                        if !holders.roles.contains(&role.id) {
+                            holders.roles.push(role.id);
+                        }
+                        resolved.push(format!("{} to **{}**", capability, role.name));
+                    }
+                    None => {
+                        warn!(guild = %guild_id, role = %name,
+                            "There is no role to give {} to", capability);
+                        holders.role_names.push(name);
+                    }
+                }
+            }
+        }
+        if !resolved.is_empty() {
+            resolved.sort();
+            self.save_change(Change::Settings)?;
+            self.record(actor,
+                format!("Gave the configured roles their capabilities: {}", resolved.join(", ")),
+                None
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Gives the capability to the holder. Returns false if it already had it
+    fn grant(&mut self, actor: UserId, capability: Capability, holder: &Holder) -> Result<bool> {
+        let holders = self.permissions.entry(capability.name().to_string()).or_default();
+        let added = match holder {
+            Holder::Role(role_id, _) => {
+                if holders.roles.contains(role_id) {
+                    false
+                }
+                else {
+                    holders.roles.push(*role_id);
+                    true
+                }
+            }
+            Holder::User(user_id) => {
+                if holders.users.contains(user_id) {
+                    false
+                }
+                else {
+                    holders.users.push(*user_id);
+                    true
+                }
+            }
+        };
+        if added {
+            self.save_change(Change::Settings)?;
+            self.record(actor,
+                format!("Gave {} to {}", capability.name(), holder.mention()),
+                None
+            )?;
+        }
+        Ok(added)
+    }
+
+    /// Takes the capability from the holder. Returns false if it didn't have it
+    fn revoke(&mut self, actor: UserId, capability: Capability, holder: &Holder) -> Result<bool> {
+        let holders = match self.permissions.get_mut(capability.name()) {
+            Some(holders) => holders,
+            None => return Ok(false),
+        };
+        let count = holders.roles.len() + holders.users.len() + holders.role_names.len();
+        match holder {
+            Holder::Role(role_id, name) => {
+                holders.roles.retain(|role| role != role_id);
+                holders.role_names.retain(|role| role.to_lowercase() != name.to_lowercase());
+            }
+            Holder::User(user_id) => holders.users.retain(|user| user != user_id),
+        }
+        let removed = holders.roles.len() + holders.users.len() + holders.role_names.len() < count;
+        if removed {
+            self.save_change(Change::Settings)?;
+            self.record(actor,
+                format!("Took {} from {}", capability.name(), holder.mention()),
+                None
+            )?;
+        }
+        Ok(removed)
+    }
+}
+
+/**
+  The capabilities the user has on the server, through the organizer role,
+  one of their other roles or being given them directly
+*/
+pub async fn capabilities(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<Vec<Capability>> {
+    let member_roles = match http.guild_member_roles(guild_id, user_id).await? {
+        Some(roles) => roles,
+        None => return Ok(Vec::new()),
+    };
+    let organizer_role = Config::get().organizer_role.to_lowercase();
+    let is_organizer = http.roles(guild_id).await?
+        .into_iter()
+        .any(|role| member_roles.contains(&role.id) && role.name.to_lowercase() == organizer_role);
+    if is_organizer {
+        return Ok(Capability::ALL.to_vec())
+    }
+
+    let state = PersistentState::for_guild(guild_id).await?;
+    let state = state.lock().await;
+    Ok(Capability::ALL.iter()
+        .cloned()
+        .filter(|capability| match state.holders(*capability) {
+            Some(holders) => holders.users.contains(&user_id)
+                || holders.roles.iter().any(|role| member_roles.contains(role)),
+            None => false,
+        })
+        .collect())
+}
+
+/// Whether the user has the capability on the server
+pub async fn has_capability(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    user_id: UserId,
+    capability: Capability,
+) -> Result<bool> {
+    Ok(capabilities(http, guild_id, user_id).await?.contains(&capability))
+}
+
+/**
+  Reads a role mention, user mention or role name. Roles are looked up on
+  the server, unless `must_exist` is false and there is no such role. Then
+  mentions are taken to be of a deleted role and names of a role that was
+  never found
+*/
+async fn parse_holder(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    text: &str,
+    must_exist: bool,
+) -> Result<Option<Holder>> {
+    lazy_static! {
+        static ref ROLE_MENTION_REGEX: Regex = Regex::new(r"^<@&(\d+)>$").unwrap();
+        static ref USER_MENTION_REGEX: Regex = Regex::new(r"^<@!?(\d+)>$").unwrap();
+    }
+    if let Some(id) = USER_MENTION_REGEX.captures(text).and_then(|c| c[1].parse::<u64>().ok()) {
+        return Ok(Some(Holder::User(UserId(id))))
+    }
+
+    let roles = http.roles(guild_id).await?;
+    let role = match ROLE_MENTION_REGEX.captures(text).and_then(|c| c[1].parse::<u64>().ok()) {
+        Some(id) => roles.iter().find(|role| role.id == RoleId(id)),
+        None => roles.iter().find(|role| role.name.to_lowercase() == text.to_lowercase()),
+    };
+    Ok(match role {
+        Some(role) => Some(Holder::Role(role.id, role.name.clone())),
+        None if must_exist => None,
+        None => match ROLE_MENTION_REGEX.captures(text).and_then(|c| c[1].parse::<u64>().ok()) {
+            Some(id) => Some(Holder::Role(RoleId(id), id.to_string())),
+            None => Some(Holder::Role(RoleId(0), text.to_string())),
+        },
+    })
+}
+
+/// Reads the capability and holder of a grant or revoke command, replying if they are invalid
+async fn parse_arguments<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+    command: &str,
+    must_exist: bool,
+) -> Result<Option<(Capability, Holder)>> {
+    let language = language(guild_id, author.id).await;
+    if rest_command.len() < 2 {
+        send_message(http, original_channel, author.id, Msg::ProperUsage {
+            command,
+            usage: &Msg::UsagePermission.text(language),
+        }.text(language)).await?;
+        return Ok(None)
+    }
+    let capability = match Capability::from_name(rest_command[0]) {
+        Some(capability) => capability,
+        None => {
+            send_message(http, original_channel, author.id,
+                Msg::UnknownCapability { name: rest_command[0], names: &capability_names() }
+                    .text(language)
+            ).await?;
+            return Ok(None)
+        }
+    };
+    let holder_text = rest_command[1..].join(" ");
+    match parse_holder(http, guild_id, &holder_text, must_exist).await? {
+        Some(holder) => Ok(Some((capability, holder))),
+        None => {
+            send_message(http, original_channel, author.id,
+                Msg::UnknownHolder { holder: &holder_text }.text(language)
+            ).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// The names of all capabilities, to show when an unknown one was given
+fn capability_names() -> String {
+    Capability::ALL.iter()
+        .map(|capability| format!("`{}`", capability.name()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub async fn handle_grant<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let arguments = parse_arguments(
+        rest_command, original_channel, guild_id, author, http, Grant.name(), true
+    ).await?;
+    if let Some((capability, holder)) = arguments {
+        let (added, language) = {
+            let state = PersistentState::for_guild(guild_id).await?;
+            let mut state = state.lock().await;
+            (state.grant(author.id, capability, &holder)?, state.language_for(author.id))
+        };
+        let (capability, holder) = (capability.name(), holder.mention());
+        let holder = &holder;
+        let msg = if added {
+            Msg::PermissionGranted { capability, holder }
+        }
+        else {
+            Msg::PermissionAlreadyHeld { capability, holder }
+        };
+        send_message(http, original_channel, author.id, msg.text(language)).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_revoke<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let arguments = parse_arguments(
+        rest_command, original_channel, guild_id, author, http, Revoke.name(), false
+    ).await?;
+    if let Some((capability, holder)) = arguments {
+        let (removed, language) = {
+            let state = PersistentState::for_guild(guild_id).await?;
+            let mut state = state.lock().await;
+            (state.revoke(author.id, capability, &holder)?, state.language_for(author.id))
+        };
+        let (capability, holder) = (capability.name(), holder.mention());
+        let holder = &holder;
+        let msg = if removed {
+            Msg::PermissionRevoked { capability, holder }
+        }
+        else {
+            Msg::PermissionNotHeld { capability, holder }
+        };
+        send_message(http, original_channel, author.id, msg.text(language)).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_list_permissions(
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let role_names = http.roles(guild_id).await?
+        .into_iter()
+        .map(|role| (role.id, role.name))
+        .collect::<HashMap<_, _>>();
+    let (table, language) = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let state = state.lock().await;
+        let language = state.language_for(author.id);
+        let table = Capability::ALL.iter()
+            .map(|capability| {
+                let holders = state.holders(*capability)
+                    .map(|holders| holders_text(holders, &role_names))
+                    .filter(|holders| !holders.is_empty())
+                    .unwrap_or_else(|| Msg::Nobody.text(language));
+                format!("- `{}` {}", capability.name(), holders)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        (table, language)
+    };
+    send_message(http, original_channel, author.id, Msg::Permissions {
+        organizer_role: &Config::get().organizer_role,
+        table: &table,
+    }.text(language)).await
+}
+
+/**
+  The holders as a comma separated list of mentions. Roles that have been
+  deleted from the server are shown by id
+*/
+fn holders_text(holders: &Holders, role_names: &HashMap<RoleId, String>) -> String {
+    holders.roles.iter()
+        .map(|role_id| Holder::Role(*role_id,
+            role_names.get(role_id).cloned().unwrap_or_else(|| role_id.to_string())
+        ))
+        .chain(holders.role_names.iter().map(|name| Holder::Role(RoleId(0), name.clone())))
+        .chain(holders.users.iter().map(|user_id| Holder::User(*user_id)))
+        .map(|holder| holder.mention())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(Grant);
+    registry.register(Revoke);
+    registry.register(ListPermissions);
+}
+
+struct Grant;
+
+#[async_trait]
+impl Command for Grant {
+    fn name(&self) -> &'static str { "grant" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsagePermission) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeGrant }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_grant(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct Revoke;
+
+#[async_trait]
+impl Command for Revoke {
+    fn name(&self) -> &'static str { "revoke" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsagePermission) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeRevoke }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_revoke(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct ListPermissions;
+
+#[async_trait]
+impl Command for ListPermissions {
+    fn name(&self) -> &'static str { "permissions" }
+    fn description(&self) -> Msg<'static> { Msg::DescribePermissions }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_list_permissions(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::fake::{test_guild, test_user, FakeDiscord};
+    use crate::storage::storage;
+
+    const USER: UserId = UserId(10);
+    const ORGANIZER: UserId = UserId(11);
+    const COMMANDS: ChannelId = ChannelId(20);
+
+    #[tokio::test]
+    async fn configured_roles_are_looked_up_by_name() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let judge = discord.add_role(guild, "Judge");
+        discord.add_member(guild, USER, &[judge]);
+        let state = PersistentState::for_guild(guild).await.unwrap();
+        state.lock().await.permissions.insert("theme.view".to_string(), Holders {
+            role_names: vec!("judge".to_string(), "Jury".to_string()),
+            ..Holders::default()
+        });
+
+        let roles = discord.roles(guild).await.unwrap();
+        state.lock().await.resolve_role_names(ORGANIZER, &roles).unwrap();
+
+        let holders = state.lock().await.holders(Capability::ThemeView).unwrap().clone();
+        assert_eq!(holders.roles, vec!(judge));
+        assert_eq!(holders.role_names, vec!("Jury".to_string()));
+        let journal = storage().load_journal(guild).unwrap();
+        assert_eq!(journal.last().unwrap().action,
+            "Gave the configured roles their capabilities: theme.view to **Judge**");
+        assert!(has_capability(&discord, guild, USER, Capability::ThemeView).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn granted_roles_keep_the_capability_when_renamed() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let organizer = discord.add_role(guild, &Config::get().organizer_role);
+        let judge = discord.add_role(guild, "Judge");
+        discord.add_member(guild, ORGANIZER, &[organizer]);
+        discord.add_member(guild, USER, &[judge]);
+
+        handle_grant(&["theme.view", "Judge"], COMMANDS, guild, &test_user(ORGANIZER), &discord)
+            .await.unwrap();
+        discord.rename_role(guild, judge, "Jury");
+
+        assert!(has_capability(&discord, guild, USER, Capability::ThemeView).await.unwrap());
+        handle_revoke(&["theme.view", "Jury"], COMMANDS, guild, &test_user(ORGANIZER), &discord)
+            .await.unwrap();
+        assert!(!has_capability(&discord, guild, USER, Capability::ThemeView).await.unwrap());
+    }
+}
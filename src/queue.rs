@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use tokio::sync::mpsc::{self, UnboundedSender};
+use twilight::model::id::UserId;
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/**
+  Runs tasks concurrently, except that the tasks of a single user run one at
+  a time in the order they were queued. That way commands that depend on
+  each other, like creating channels and then renaming them, are handled in
+  the order the user sent them.
+
+  Each user with queued tasks gets a worker task that lives as long as the
+  queue does
+*/
+pub struct UserQueues {
+    queues: Mutex<HashMap<UserId, UnboundedSender<Task>>>,
+}
+
+impl UserQueues {
+    pub fn new() -> Self {
+        Self {
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queues a task to run after all previously queued tasks of the user
+    pub fn push(&self, user_id: UserId, task: impl Future<Output = ()> + Send + 'static) {
+        let mut queues = self.queues.lock().unwrap();
+        let task: Task = Box::pin(task);
+
+        let task = match queues.get(&user_id) {
+            Some(sender) => match sender.send(task) {
+                Ok(()) => return,
+                // The worker is gone, which only happens if a task panicked
+                Err(e) => e.0,
+            },
+            None => task,
+        };
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Task>();
+        tokio::spawn(async move {
+            while let Some(task) = receiver.recv().await {
+                task.await;
+            }
+        });
+        // The receiver was just created, so this can't fail
+        sender.send(task).ok();
+        queues.insert(user_id, sender);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use twilight::model::id::ChannelId;
+
+    use super::*;
+    use crate::channel::{handle_create_channels, handle_rename_channels};
+    use crate::discord::fake::{test_guild, FakeDiscord};
+    use crate::state::PersistentState;
+
+    const BOT: UserId = UserId(1);
+    const COMMANDS: ChannelId = ChannelId(20);
+    const USERS: u64 = 50;
+
+    #[tokio::test(threaded_scheduler)]
+    async fn tasks_of_each_user_run_in_order() {
+        let guild = test_guild();
+        let discord = Arc::new(FakeDiscord::new());
+        let queues = UserQueues::new();
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let (done, mut finished) = mpsc::unbounded_channel();
+
+        // Every user creates a team and then renames it, with the commands
+        // of different users interleaved
+        for step in 0..2 {
+            for i in 0..USERS {
+                let user_id = UserId(100 + i);
+                let discord = discord.clone();
+                let steps = steps.clone();
+                let done = done.clone();
+                queues.push(user_id, async move {
+                    let result = if step == 0 {
+                        let name = format!("Game {}", i);
+                        handle_create_channels(&[&name], COMMANDS, guild, user_id, BOT, &*discord).await
+                    }
+                    else {
+                        let name = format!("Renamed {}", i);
+                        handle_rename_channels(&[&name], COMMANDS, guild, user_id, BOT, &*discord).await
+                    };
+                    result.unwrap();
+                    steps.lock().unwrap().push((user_id, step));
+                    done.send(()).unwrap();
+                });
+            }
+        }
+        for _ in 0..USERS * 2 {
+            finished.recv().await.unwrap();
+        }
+
+        let steps = steps.lock().unwrap();
+        let state = PersistentState::for_guild(guild).await.unwrap();
+        let state = state.lock().await;
+        for i in 0..USERS {
+            let user_id = UserId(100 + i);
+            let order = steps.iter()
+                .filter(|(user, _)| *user == user_id)
+                .map(|(_, step)| *step)
+                .collect::<Vec<_>>();
+            assert_eq!(order, vec!(0, 1), "user {}", user_id);
+            let team = &state.channel_creators[&user_id];
+            assert_eq!(team.game_name(), format!("Renamed {}", i));
+            assert_eq!(discord.channel_name(team.text_id()).unwrap(), format!("Renamed {}", i));
+        }
+    }
+}
@@ -1,106 +1,117 @@
+use async_trait::async_trait;
 use regex::Regex;
 use lazy_static::lazy_static;
-use twilight::{
-    http::Client as HttpClient,
-    model::{
-        channel::{Message, Reaction, ReactionType},
-        id::{ChannelId, GuildId, MessageId},
-        user::{User, CurrentUser},
-    },
+use tracing::{debug, error, warn};
+use twilight::model::{
+    channel::{Message, Reaction, ReactionType},
+    id::{ChannelId, GuildId, MessageId, UserId},
+    user::{User, CurrentUser},
 };
 
-use crate::role::{has_role, remove_role, set_role};
-use crate::roles::*;
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::discord::DiscordApi;
+use crate::locale::{language, Msg};
+use crate::log_channel::{log_action, Outcome};
+use crate::permission::Capability;
+use crate::retry::{retry, Retry};
+use crate::role::{handle_role_offer_reaction, remove_role, set_role};
 use crate::state::PersistentState;
 use crate::utils::{Result, send_message};
+use crate::vote::handle_vote_reaction;
 
 
 pub async fn handle_reaction_add(
     reaction: &Reaction,
-    http: HttpClient,
+    http: &dyn DiscordApi,
     current_user: &CurrentUser,
 ) -> Result<()> {
-    handle_add_role(&http, reaction, &current_user).await?;
+    handle_role_offer_reaction(http, reaction).await?;
+    handle_add_role(http, reaction, &current_user).await?;
+    handle_vote_reaction(reaction, current_user.id, true).await?;
     Ok(())
 }
 
 pub async fn handle_reaction_remove(
     reaction: &Reaction,
-    http: HttpClient,
+    http: &dyn DiscordApi,
+    current_user: &CurrentUser,
 ) -> Result<()> {
-    handle_remove_role(&http, reaction).await?;
+    handle_remove_role(http, reaction).await?;
+    handle_vote_reaction(reaction, current_user.id, false).await?;
     Ok(())
 }
 
-fn emoji_to_role(emoji: &String) -> Option<&str> {
-    if      emoji == "💻" { Some(PROGRAMMER) }
-    else if emoji == "🎨" { Some(ARTIST_2D) }
-    else if emoji == "🗿" { Some(ARTIST_3D) }
-    else if emoji == "🔊" { Some(SOUND_DESIGNER) }
-    else if emoji == "🎵" { Some(MUSICIAN) }
-    else if emoji == "💡" { Some(IDEA_GUY) }
-    else if emoji == "🎲" { Some(BOARD_GAMES) }
-    else if emoji == "🎮" { Some(PLAY_TESTER) }
-    else { None }
+/**
+  The name of the role that the reaction requests, if it was made on the
+  role assignment message. The state is only locked while looking it up so
+  that it isn't held while talking to discord
+*/
+async fn requested_role(reaction: &Reaction) -> Result<Option<String>> {
+    let guild_id = match reaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(None),
+    };
+    let state = PersistentState::for_guild(guild_id).await?;
+    let ps = state.lock().await;
+    if reaction.channel_id == ps.get_role_assign_channel() &&
+        reaction.message_id == ps.get_role_assign_message() {
+
+        match &reaction.emoji {
+            ReactionType::Unicode {name} => {
+                Ok(ps.role_for_emoji(name).map(|role| role.name.clone()))
+            }
+            _ => Ok(None)
+        }
+    }
+    else {
+        Ok(None)
+    }
 }
 
 async fn handle_add_role(
-    http: &HttpClient,
+    http: &dyn DiscordApi,
     reaction: &Reaction,
     current_user: &CurrentUser,
 ) -> Result<()> {
-    let mut ps = PersistentState::instance().lock().unwrap();
-    if reaction.channel_id == ps.get_role_assign_channel() &&
-        reaction.message_id == ps.get_role_assign_message() {
+    let guild_id = reaction.guild_id.unwrap();
+    let user_id = reaction.user_id;
 
-        let guild_id = reaction.guild_id.unwrap();
-        let user_id = reaction.user_id;
-
-        if user_id != current_user.id {
-            match &reaction.emoji {
-                ReactionType::Unicode {name} => {
-                    let maybe_role = emoji_to_role(name);
-                    match maybe_role {
-                        Some(role_name) => {
-                            match set_role(http, guild_id, user_id, role_name).await {
-                                Err(e) => println!("Failed setting role from reaction {}: {}", name, e),
-                                _ => {}
-                            }
-                        }
-                        None => {}
-                    }
+    if user_id != current_user.id {
+        if let Some(role_name) = requested_role(reaction).await? {
+            match set_role(http, guild_id, user_id, &role_name).await {
+                Err(e) => {
+                    warn!(guild = %guild_id, user = %user_id, role = %role_name, error = %e,
+                        "Failed setting role from reaction");
+                    log_action(http, guild_id, user_id, "Role grant by reaction failed",
+                        Outcome::Failed(format!("Could not give **{}**: {}", role_name, e))
+                    ).await;
+                }
+                Ok(role) => {
+                    log_action(http, guild_id, user_id, "Role granted by reaction",
+                        Outcome::Done(format!("Gave **{}**.", role))
+                    ).await;
                 }
-                _ => {}
             }
         }
-        else {}
     }
     Ok(())
 }
 
 async fn handle_remove_role(
-    http: &HttpClient,
+    http: &dyn DiscordApi,
     reaction: &Reaction,
 ) -> Result<()> {
-    let mut ps = PersistentState::instance().lock().unwrap();
-    if reaction.channel_id == ps.get_role_assign_channel() &&
-        reaction.message_id == ps.get_role_assign_message() {
-
-        let guild_id = reaction.guild_id.unwrap();
-        let user_id = reaction.user_id;
+    let guild_id = reaction.guild_id.unwrap();
+    let user_id = reaction.user_id;
 
-        match &reaction.emoji {
-            ReactionType::Unicode {name} => {
-                let maybe_role = emoji_to_role(name);
-                match maybe_role {
-                    Some(role_name) => {
-                        match remove_role(http, guild_id, user_id, role_name).await {
-                            Err(e) => println!("Failed to remove role from reaction {}: {}", name, e),
-                            _ => {}
-                        }
-                    }
-                    None => {}
-                }
+    if let Some(role_name) = requested_role(reaction).await? {
+        match remove_role(http, guild_id, user_id, &role_name).await {
+            Err(e) => {
+                warn!(guild = %guild_id, user = %user_id, role = %role_name, error = %e,
+                    "Failed to remove role from reaction");
+                log_action(http, guild_id, user_id, "Role removal by reaction failed",
+                    Outcome::Failed(format!("Could not remove **{}**: {}", role_name, e))
+                ).await;
             }
             _ => {}
         }
@@ -112,12 +123,33 @@ pub enum ReactionMessageType {
     RoleAssign,
 }
 
+/**
+  Reacts to the message with the emoji of every requestable role and makes
+  it the role assignment message. Stops at the first reaction that fails
+*/
+async fn set_role_assign_message(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    author_id: UserId,
+    reaction_msg: &Message,
+) -> Result<()> {
+    let roles = PersistentState::for_guild(guild_id).await?.lock().await
+        .requestable_roles.clone();
+    for role in roles {
+        retry(Retry::Idempotent, "add reaction",
+            || http.create_reaction(reaction_msg.channel_id, reaction_msg.id, role.emoji.clone())
+        ).await?;
+    }
+    PersistentState::for_guild(guild_id).await?.lock().await
+        .set_role_assign(author_id, reaction_msg.channel_id, reaction_msg.id)
+}
+
 pub async fn handle_set_reaction_message<'a>(
     rest_command: &[&'a str],
     original_channel: ChannelId,
-    guild: GuildId,
+    guild_id: GuildId,
     author: &User,
-    http: HttpClient,
+    http: &dyn DiscordApi,
     msg: &Message,
     msg_type: ReactionMessageType,
 ) -> Result<()> {
@@ -125,127 +157,185 @@ pub async fn handle_set_reaction_message<'a>(
         static ref CHANNEL_MENTION_REGEX: Regex =
             Regex::new(r"<#(\d+)>").unwrap();
     }
+    let language = language(guild_id, author.id).await;
+    // The english name is for the logs
     let msg_type_name = match msg_type {
         ReactionMessageType::RoleAssign => "role assignment message",
     };
+    let msg_kind = match msg_type {
+        ReactionMessageType::RoleAssign => Msg::RoleAssignMessage,
+    }.text(language);
 
-    println!("Got set {} request \"{}\"", msg_type_name, &msg.content);
-
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
-
-        // Parse arguments
-        let command = match msg_type {
-            ReactionMessageType::RoleAssign => "setroleassign",
-        };
-        let arg_guide_msg = format!(
-            "Proper usage: `!{} <mention of channel with the message> <message ID>`", command
-        );
-        if rest_command.len() < 2 {
-            send_message(&http, original_channel, author.id, arg_guide_msg).await?;
-        }
-        else {
-            match CHANNEL_MENTION_REGEX.captures(rest_command[0]) {
-                Some(channel_ids) => {
-                    if channel_ids.len() != 2 {
-                        send_message(&http, original_channel, author.id,
-                            format!("Invalid channel reference.\n{}", arg_guide_msg)
-                        ).await?;
-                    }
-                    else {
-                        match channel_ids[1].parse::<u64>() {
-                            Ok(channel_id_num) => {
-                                match rest_command[1].parse::<u64>() {
-                                    Ok(messege_id_num) => {
-
-                                        // Fetch specified message
-                                        match http.message(
-                                            ChannelId(channel_id_num),
-                                            MessageId(messege_id_num)
-                                        ).await {
-                                            Ok(response) => {
-                                                let reaction_msg = response.unwrap();
-                                                let mut ps = PersistentState::instance().lock().unwrap();
-                                                let result = match msg_type {
-                                                    ReactionMessageType::RoleAssign => {
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "💻").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎨").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🗿").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🔊").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎵").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "💡").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎲").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎮").await?;
-                                                        ps.set_role_assign(reaction_msg.channel_id, reaction_msg.id)
-                                                    }
-                                                };
-
-                                                match result {
-                                                    Ok(_) => {
-                                                        send_message(&http, original_channel, author.id,
-                                                            format!(
-                                                                "Server {} set to the following messege by <@{}> in <#{}>:\n>>> {}",
-                                                                msg_type_name, reaction_msg.author.id,
-                                                                reaction_msg.channel_id, reaction_msg.content
-                                                            )
-                                                        ).await?;
-                                                    }
-                                                    Err(ref e) => {
-                                                        send_message(&http, original_channel, author.id,
-                                                            format!("Could not set server {}. Check the logs for details.", msg_type_name)
-                                                        ).await?;
-                                                        println!("Failed setting {}: {:?}", msg_type_name, e);
-                                                    }
+    debug!(guild = %guild_id, user = %author.id, content = %msg.content,
+        "Got set {} request", msg_type_name);
+
+    // Parse arguments
+    let command = match msg_type {
+        ReactionMessageType::RoleAssign => "setroleassign",
+    };
+    let arg_guide_msg = Msg::ProperUsage {
+        command,
+        usage: &Msg::UsageMessageReference.text(language),
+    }.text(language);
+    if rest_command.len() < 2 {
+        send_message(http, original_channel, author.id, arg_guide_msg).await?;
+    }
+    else {
+        match CHANNEL_MENTION_REGEX.captures(rest_command[0]) {
+            Some(channel_ids) => {
+                if channel_ids.len() != 2 {
+                    send_message(http, original_channel, author.id,
+                        Msg::InvalidChannelReference { usage: &arg_guide_msg }.text(language)
+                    ).await?;
+                }
+                else {
+                    match channel_ids[1].parse::<u64>() {
+                        Ok(channel_id_num) => {
+                            match rest_command[1].parse::<u64>() {
+                                Ok(messege_id_num) => {
+
+                                    // Fetch specified message
+                                    match http.message(
+                                        ChannelId(channel_id_num),
+                                        MessageId(messege_id_num)
+                                    ).await {
+                                        Ok(response) => {
+                                            let reaction_msg = response.unwrap();
+                                            let result = match msg_type {
+                                                ReactionMessageType::RoleAssign => {
+                                                    set_role_assign_message(http, guild_id, author.id, &reaction_msg).await
+                                                }
+                                            };
+
+                                            match result {
+                                                Ok(_) => {
+                                                    send_message(http, original_channel, author.id,
+                                                        Msg::ReactionMessageSet {
+                                                            kind: &msg_kind,
+                                                            author: reaction_msg.author.id,
+                                                            channel: reaction_msg.channel_id,
+                                                            content: &reaction_msg.content,
+                                                        }.text(language)
+                                                    ).await?;
+                                                    log_action(http, guild_id, author.id,
+                                                        format!("Server {} set", msg_type_name),
+                                                        Outcome::Done(format!(
+                                                            "https://discordapp.com/channels/{}/{}/{}",
+                                                            guild_id, reaction_msg.channel_id, reaction_msg.id
+                                                        ))
+                                                    ).await;
+                                                }
+                                                Err(ref e) => {
+                                                    send_message(http, original_channel, author.id,
+                                                        Msg::ReactionMessageFailed { kind: &msg_kind }.text(language)
+                                                    ).await?;
+                                                    error!(guild = %guild_id, user = %author.id, error = ?e,
+                                                        "Failed setting {}", msg_type_name);
+                                                    log_action(http, guild_id, author.id,
+                                                        format!("Setting server {} failed", msg_type_name),
+                                                        Outcome::Failed(format!("{:?}", e))
+                                                    ).await;
                                                 }
-                                            }
-                                            Err(_) => {
-                                                send_message(&http, original_channel, author.id,
-                                                    format!(
-                                                        "No message with ID {} was found in <#{}>",
-                                                        messege_id_num, channel_id_num
-                                                    )
-                                                ).await?;
-                                                println!("No message with ID {} was found in <#{}>",
-                                                    messege_id_num, channel_id_num
-                                                );
                                             }
                                         }
-                                    }
-                                    Err(_) => {
-                                        send_message(&http, original_channel, author.id,
-                                            format!("Message ID must be a number.\n{}", arg_guide_msg)
-                                        ).await?;
+                                        Err(_) => {
+                                            send_message(http, original_channel, author.id,
+                                                Msg::MessageNotFound {
+                                                    message: messege_id_num,
+                                                    channel: channel_id_num,
+                                                }.text(language)
+                                            ).await?;
+                                            debug!(guild = %guild_id, user = %author.id,
+                                                "No message with ID {} was found in <#{}>",
+                                                messege_id_num, channel_id_num
+                                            );
+                                        }
                                     }
                                 }
+                                Err(_) => {
+                                    send_message(http, original_channel, author.id,
+                                        Msg::MessageIdNotNumber { usage: &arg_guide_msg }.text(language)
+                                    ).await?;
+                                }
                             }
-                            Err(_) => {
-                                send_message(&http, original_channel, author.id,
-                                    format!("Invalid channel reference.\n{}", arg_guide_msg)
-                                ).await?;
-                            }
+                        }
+                        Err(_) => {
+                            send_message(http, original_channel, author.id,
+                                Msg::InvalidChannelReference { usage: &arg_guide_msg }.text(language)
+                            ).await?;
                         }
                     }
                 }
-                _ => {
-                    send_message(&http, original_channel, author.id,
-                        format!("Invalid channel reference.\n{}", arg_guide_msg)
-                    ).await?;
-                }
+            }
+            _ => {
+                send_message(http, original_channel, author.id,
+                    Msg::InvalidChannelReference { usage: &arg_guide_msg }.text(language)
+                ).await?;
             }
         }
     }
-    else {
-        send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to set the server {}.", ORGANIZER, msg_type_name)
-        ).await?;
-        println!("Tried to set {} without required role \"{}\"", msg_type_name, ORGANIZER);
-    }
 
     Ok(())
 }
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(SetRoleAssign);
+}
+
+struct SetRoleAssign;
+
+#[async_trait]
+impl Command for SetRoleAssign {
+    fn name(&self) -> &'static str { "setroleassign" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageMessageReference) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeSetRoleAssign }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::RolesConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_set_reaction_message(
+            args,
+            ctx.msg.channel_id,
+            ctx.guild_id,
+            &ctx.msg.author,
+            ctx.http,
+            ctx.msg,
+            ReactionMessageType::RoleAssign,
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::fake::{test_guild, test_message, test_user, Call, FakeDiscord};
+    use crate::locale::Language;
+
+    const ORGANIZER: UserId = UserId(11);
+    const COMMANDS: ChannelId = ChannelId(20);
+    const LOG: ChannelId = ChannelId(30);
+    const ROLES: ChannelId = ChannelId(40);
+
+    #[tokio::test]
+    async fn failed_reactions_are_reported() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        PersistentState::for_guild(guild).await.unwrap().lock().await
+            .set_log_channel(ORGANIZER, LOG).unwrap();
+        discord.fail_on(|call| matches!(call, Call::CreateReaction(..)));
+        let mention = format!("<#{}>", ROLES);
+        let msg = test_message(COMMANDS, ORGANIZER, "!setroleassign");
+
+        handle_set_reaction_message(&[&mention, "500"], COMMANDS, guild, &test_user(ORGANIZER),
+            &discord, &msg, ReactionMessageType::RoleAssign
+        ).await.unwrap();
+
+        let failed = Msg::ReactionMessageFailed { kind: &Msg::RoleAssignMessage.text(Language::En) }
+            .text(Language::En);
+        assert_eq!(discord.messages(COMMANDS), vec!(format!("<@{}> {}", ORGANIZER, failed)));
+        assert!(discord.messages(LOG)[0].contains("CreateReaction"));
+        assert_eq!(PersistentState::for_guild(guild).await.unwrap().lock().await
+            .get_role_assign_message(), MessageId(0));
+    }
+}
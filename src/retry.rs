@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::delay_for;
+use tracing::{info, warn};
+
+use crate::config::{Config, RetryConfig};
+use crate::discord::{is_server_error, is_transient};
+use crate::utils::Result;
+
+/// Which failures of a call are safe to repeat
+#[derive(Clone, Copy, Debug)]
+pub enum Retry {
+    /// Calls that have the same effect however many times they are made,
+    /// like renaming a channel or giving a role. Retried on any transient
+    /// failure
+    Idempotent,
+    /// Calls that create something. A request that timed out may still have
+    /// gone through, so these are only retried when discord answered with a
+    /// server side error
+    Creating,
+}
+
+impl Retry {
+    fn applies_to(self, e: &anyhow::Error) -> bool {
+        match self {
+            Retry::Idempotent => is_transient(e),
+            Retry::Creating => is_server_error(e),
+        }
+    }
+}
+
+/**
+  Makes the call, and makes it again with exponential backoff while it fails
+  in a way that `kind` allows retrying, until the attempts configured in the
+  `[retry]` section run out.
+
+  `what` names the call in the logs
+*/
+pub async fn retry<T, F, Fut>(kind: Retry, what: &str, mut call: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let budget = &Config::get().retry;
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Ok(value) => {
+                if attempt > 1 {
+                    info!(call = what, attempts = attempt, "Succeeded after retrying");
+                }
+                return Ok(value)
+            }
+            Err(e) if attempt < budget.attempts && kind.applies_to(&e) => {
+                let delay = backoff(budget, attempt);
+                warn!(
+                    call = what, attempt, delay_ms = delay.as_millis() as u64, error = %e,
+                    "Request failed, retrying"
+                );
+                delay_for(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                if attempt > 1 {
+                    warn!(call = what, attempts = attempt, error = %e, "Giving up after retrying");
+                }
+                return Err(e)
+            }
+        }
+    }
+}
+
+/**
+  How long to wait after the given attempt. The delay doubles with every
+  attempt up to the maximum, and a random part of up to half of it is
+  taken off so that requests that failed together aren't retried together
+*/
+fn backoff(budget: &RetryConfig, attempt: u32) -> Duration {
+    let exponential = budget.base_delay_ms.saturating_mul(1 << (attempt - 1).min(16));
+    let delay = exponential.min(budget.max_delay_ms);
+    let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
+    Duration::from_millis(delay - jitter)
+}
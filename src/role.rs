@@ -1,53 +1,89 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Mutex;
 
+use async_trait::async_trait;
 use lazy_static::lazy_static;
-use twilight::{
-    http::Client as HttpClient,
-    http::error::Error as DiscordError,
-    model::{
-        id::{ChannelId, UserId, GuildId},
-        user::User,
-    },
+use tracing::{info, warn};
+use twilight::model::{
+    channel::{Reaction, ReactionType},
+    id::{ChannelId, MessageId, UserId, GuildId},
+    user::User,
 };
 
-use crate::roles::*;
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::config::{Config, RoleConfig};
+use crate::discord::DiscordApi;
+use crate::journal::Undo;
+use crate::locale::{language, Language, Msg};
+use crate::permission::Capability;
+use crate::retry::{retry, Retry};
+use crate::state::PersistentState;
 use crate::utils::{Result, send_message};
 
+/// Reaction used to accept the offer to create a missing role
+const CONFIRM_EMOJI: &'static str = "✅";
+
+/// A role that an organizer tried to make requestable before it existed
+struct RoleOffer {
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    organizer_id: UserId,
+    role: RoleConfig,
+}
 
 lazy_static! {
-    static ref REQUESTABLE_ROLES : HashSet<String> = {
-        let mut set = HashSet::new();
-        set.insert(PROGRAMMER.to_lowercase());
-        set.insert(ARTIST_2D.to_lowercase());
-        set.insert(ARTIST_3D.to_lowercase());
-        set.insert(SOUND_DESIGNER.to_lowercase());
-        set.insert(MUSICIAN.to_lowercase());
-        set.insert(IDEA_GUY.to_lowercase());
-        set.insert(BOARD_GAMES.to_lowercase());
-        set.insert(PLAY_TESTER.to_lowercase());
-        set
-    };
+    /// Offers to create roles, keyed by the message containing the offer
+    static ref ROLE_OFFERS: Mutex<HashMap<MessageId, RoleOffer>> = Mutex::new(HashMap::new());
 }
 
-pub async fn has_role(
-    http: &HttpClient,
-    guild_id: GuildId,
-    user_id: UserId,
-    role_name: impl ToString,
-) -> Result<bool> {
-    let guild_roles = http.roles(guild_id).await?;
-    let user_roles = http.guild_member(guild_id, user_id).await?.unwrap().roles;
-    let role_to_check = role_name.to_string().to_lowercase();
+impl PersistentState {
+    /// Finds a requestable role by name, ignoring case
+    pub fn requestable_role(&self, name: &str) -> Option<&RoleConfig> {
+        self.requestable_roles.iter()
+            .find(|role| role.name.to_lowercase() == name.to_lowercase())
+    }
 
-    for role in guild_roles {
-        if role.name.to_lowercase() == role_to_check
-            && user_roles.contains(&role.id)
-        {
-            return Ok(true)
+    /// Finds the requestable role given by reacting with the emoji
+    pub fn role_for_emoji(&self, emoji: &str) -> Option<&RoleConfig> {
+        self.requestable_roles.iter().find(|role| role.emoji == emoji)
+    }
+
+    /// Makes a role requestable
+    fn add_requestable_role(&mut self, actor: UserId, role: RoleConfig) -> Result<()> {
+        let action = format!("Made {} requestable with {}", role.name, role.emoji);
+        self.requestable_roles.push(role);
+        self.save()?;
+        self.record(actor, action, None)
+    }
+
+    /// Stops a role from being requestable. Returns the role if it was
+    fn remove_requestable_role(&mut self, actor: UserId, name: &str) -> Result<Option<RoleConfig>> {
+        let position = self.requestable_roles.iter()
+            .position(|role| role.name.to_lowercase() == name.to_lowercase());
+        match position {
+            Some(position) => {
+                let role = self.requestable_roles.remove(position);
+                self.save()?;
+                self.record(actor,
+                    format!("Stopped {} from being requestable", role.name),
+                    Some(Undo::RequestableRole(role.clone()))
+                )?;
+                Ok(Some(role))
+            }
+            None => Ok(None),
         }
     }
-    Ok(false)
+}
+
+/// Lists the requestable roles, for when the user asked for one that isn't
+async fn available_roles_message(guild_id: GuildId, language: Language) -> Result<String> {
+    let roles = PersistentState::for_guild(guild_id).await?.lock().await
+        .requestable_roles.iter()
+        .map(|role| role.name.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(Msg::AvailableRoles { roles: &roles }.text(language))
 }
 
 impl std::error::Error for RoleError {
@@ -56,25 +92,27 @@ impl std::error::Error for RoleError {
             Self::InvalidName(_)
                 | Self::AlreadySet(_)
                 | Self::NotSet(_) => None,
-            Self::RequestFailed(e) => Some(e)
+            Self::RequestFailed(e) => Some(e.as_ref())
         }
     }
 }
 
 pub async fn set_role(
-    http: &HttpClient,
+    http: &dyn DiscordApi,
     guild: GuildId,
     user_id: UserId,
     role_name: impl ToString,
 ) -> std::result::Result<String, RoleError<>> {
     let requested_role = role_name.to_string().to_lowercase();
     let guild_roles = http.roles(guild).await?;
-    let author_roles = http.guild_member(guild, user_id).await?.unwrap().roles;
+    let author_roles = http.guild_member_roles(guild, user_id).await?.unwrap();
 
     for role in guild_roles {
         if role.name.to_lowercase() == requested_role {
             return if !author_roles.contains(&role.id) {
-                let request = http.add_guild_member_role(guild, user_id, role.id);
+                let request = retry(Retry::Idempotent, "add role",
+                    || http.add_guild_member_role(guild, user_id, role.id)
+                );
 
                 match request.await {
                     Err(e) => {
@@ -94,19 +132,21 @@ pub async fn set_role(
 }
 
 pub async fn remove_role(
-    http: &HttpClient,
+    http: &dyn DiscordApi,
     guild: GuildId,
     user_id: UserId,
     role_name: impl ToString,
 ) -> std::result::Result<String, RoleError<>> {
     let requested_role = role_name.to_string().to_lowercase();
     let guild_roles = http.roles(guild).await?;
-    let author_roles = http.guild_member(guild, user_id).await?.unwrap().roles;
+    let author_roles = http.guild_member_roles(guild, user_id).await?.unwrap();
 
     for role in guild_roles {
         if role.name.to_lowercase() == requested_role {
             return if author_roles.contains(&role.id) {
-                let request = http.remove_guild_member_role(guild, user_id, role.id);
+                let request = retry(Retry::Idempotent, "remove role",
+                    || http.remove_guild_member_role(guild, user_id, role.id)
+                );
 
                 match request.await {
                     Err(e) => {
@@ -130,31 +170,35 @@ pub async fn handle_give_role<'a>(
     original_channel: ChannelId,
     guild: GuildId,
     author: &User,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    let mut message = "You need to to specify a valid role.\nAvailable roles are:```\nProgrammer\n2D Artist\n3D Artist\nSound Designer\nMusician\nIdea Guy\nBoard Games\nPlay Tester```".to_string();
+    let language = language(guild, author.id).await;
+    let mut message = available_roles_message(guild, language).await?;
 
     let reply : String = if rest_command.len() == 0 {
         message.into()
     }
     else {
         let requested_role = rest_command.join(" ");
-        if REQUESTABLE_ROLES.contains(&requested_role.to_lowercase()) {
-            match set_role(&http, guild, author.id, &requested_role).await {
+        if PersistentState::for_guild(guild).await?.lock().await
+            .requestable_role(&requested_role).is_some()
+        {
+            match set_role(http, guild, author.id, &requested_role).await {
                 Err(e) => {
-                    message = format!("Couldn't assign role to you: {}", e);
-                    println!("Couldn't assign role to {}: {}", author.name, e);
+                    message = Msg::RoleAssignFailed { error: &e.message(language) }.text(language);
+                    warn!(guild = %guild, user = %author.id, role = %requested_role, error = %e,
+                        "Couldn't assign role");
                 }
                 Ok(role) => {
-                    message = format!("You have been assigned the role **{}**.", role);
-                    println!("New role {} assigned to {}", role, author.name);
+                    message = Msg::RoleAssigned { role: &role }.text(language);
+                    info!(guild = %guild, user = %author.id, role = %role, "Assigned role");
                 }
             }
         }
         message.into()
     };
 
-    send_message(&http, original_channel, author.id, reply).await?;
+    send_message(http, original_channel, author.id, reply).await?;
 
     Ok(())
 }
@@ -164,61 +208,405 @@ pub async fn handle_remove_role<'a>(
     original_channel: ChannelId,
     guild: GuildId,
     author: &User,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    let mut message = "You need to to specify a valid role.\nAvailable roles are:```\nProgrammer\n2D Artist\n3D Artist\nSound Designer\nMusician\nIdea Guy\nBoard Games\nPlay Tester```".to_string();
+    let language = language(guild, author.id).await;
+    let mut message = available_roles_message(guild, language).await?;
 
     let reply : String = if rest_command.len() == 0 {
         message.into()
     }
     else {
         let requested_role = rest_command.join(" ");
-        if REQUESTABLE_ROLES.contains(&requested_role.to_lowercase()) {
-            match remove_role(&http, guild, author.id, &requested_role).await {
+        if PersistentState::for_guild(guild).await?.lock().await
+            .requestable_role(&requested_role).is_some()
+        {
+            match remove_role(http, guild, author.id, &requested_role).await {
                 Err(e) => {
-                    message = format!("Couldn't strip you of role: {}", e);
-                    println!("Couldn't strip {} of role: {}", author.name, e);
+                    message = Msg::RoleRemoveFailed { error: &e.message(language) }.text(language);
+                    warn!(guild = %guild, user = %author.id, role = %requested_role, error = %e,
+                        "Couldn't remove role");
                 }
                 Ok(role) => {
-                    message = format!("You have been stripped of the role **{}**.", role);
-                    println!("{} left the role {}", author.name, role);
+                    message = Msg::RoleRemoved { role: &role }.text(language);
+                    info!(guild = %guild, user = %author.id, role = %role, "Removed role");
                 }
             }
         }
         message.into()
     };
 
-    send_message(&http, original_channel, author.id, reply).await?;
+    send_message(http, original_channel, author.id, reply).await?;
 
     Ok(())
 }
 
+pub async fn handle_add_requestable_role<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: &dyn DiscordApi
+) -> Result<()> {
+    let language = language(guild, author.id).await;
+    if rest_command.len() < 2 {
+        send_message(http, original_channel, author.id, Msg::ProperUsage {
+            command: AddRole.name(),
+            usage: &Msg::UsageRoleAndEmoji.text(language),
+        }.text(language)).await?;
+        return Ok(())
+    }
+    let (emoji, name) = rest_command.split_last().unwrap();
+    let role = RoleConfig {
+        name: name.join(" "),
+        emoji: emoji.to_string(),
+    };
+
+    let problem = {
+        let state = PersistentState::for_guild(guild).await?;
+        let state = state.lock().await;
+        let problem = if role.name.to_lowercase() == Config::get().organizer_role.to_lowercase() {
+            Some(Msg::RoleCannotBeRequestable { role: &role.name })
+        }
+        else if let Some(existing) = state.requestable_role(&role.name) {
+            Some(Msg::RoleAlreadyRequestable { role: &existing.name })
+        }
+        else if let Some(existing) = state.role_for_emoji(&role.emoji) {
+            Some(Msg::EmojiAlreadyUsed { emoji: &role.emoji, role: &existing.name })
+        }
+        else if role.emoji.starts_with('<') {
+            Some(Msg::OnlyStandardEmojis)
+        }
+        else {
+            None
+        };
+        problem.map(|problem| problem.text(language))
+    };
+    if let Some(problem) = problem {
+        send_message(http, original_channel, author.id, problem).await?;
+        return Ok(())
+    }
+
+    let exists = http.roles(guild).await?
+        .iter()
+        .any(|r| r.name.to_lowercase() == role.name.to_lowercase());
+    if exists {
+        make_requestable(http, guild, original_channel, author.id, role).await?;
+    }
+    else {
+        let offer = http.create_message(original_channel, format!("<@{}> {}",
+            author.id,
+            Msg::OfferCreateRole { role: &role.name, emoji: CONFIRM_EMOJI }.text(language)
+        )).await?;
+        http.create_reaction(offer.channel_id, offer.id, CONFIRM_EMOJI.to_string()).await?;
+        ROLE_OFFERS.lock().unwrap().insert(offer.id, RoleOffer {
+            guild_id: guild,
+            channel_id: original_channel,
+            organizer_id: author.id,
+            role,
+        });
+    }
+    Ok(())
+}
+
+/// Creates the offered role if the organizer who asked for it accepted the offer
+pub async fn handle_role_offer_reaction(
+    http: &dyn DiscordApi,
+    reaction: &Reaction,
+) -> Result<()> {
+    let accepted = match &reaction.emoji {
+        ReactionType::Unicode {name} if name == CONFIRM_EMOJI => {
+            let mut offers = ROLE_OFFERS.lock().unwrap();
+            match offers.get(&reaction.message_id) {
+                Some(offer) if offer.organizer_id == reaction.user_id => {
+                    offers.remove(&reaction.message_id)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(offer) = accepted {
+        match http.create_role(offer.guild_id, offer.role.name.clone()).await {
+            Ok(_) => {
+                make_requestable(http, offer.guild_id, offer.channel_id, offer.organizer_id, offer.role)
+                    .await?;
+            }
+            Err(e) => {
+                let language = language(offer.guild_id, offer.organizer_id).await;
+                send_message(http, offer.channel_id, offer.organizer_id, Msg::RoleCreationFailed {
+                    role: &offer.role.name,
+                    error: &e.to_string(),
+                }.text(language)).await?;
+                warn!(guild = %offer.guild_id, user = %offer.organizer_id,
+                    role = %offer.role.name, error = ?e, "Failed to create role");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds an existing role to the requestable ones, and to the role assignment message
+async fn make_requestable(
+    http: &dyn DiscordApi,
+    guild: GuildId,
+    original_channel: ChannelId,
+    author_id: UserId,
+    role: RoleConfig,
+) -> Result<()> {
+    let (name, emoji) = (role.name.clone(), role.emoji.clone());
+    let (assign_channel, assign_message) = {
+        let state = PersistentState::for_guild(guild).await?;
+        let mut state = state.lock().await;
+        state.add_requestable_role(author_id, role)?;
+        (state.get_role_assign_channel(), state.get_role_assign_message())
+    };
+    if assign_message.0 != 0 {
+        let reaction = retry(Retry::Idempotent, "add reaction",
+            || http.create_reaction(assign_channel, assign_message, emoji.clone())
+        );
+        if let Err(e) = reaction.await {
+            warn!(guild = %guild, emoji = %emoji, error = ?e,
+                "Failed to add the emoji to the role assignment message");
+        }
+    }
+    send_message(http, original_channel, author_id,
+        Msg::RoleMadeRequestable { role: &name, emoji: &emoji }
+            .text(language(guild, author_id).await)
+    ).await?;
+    info!(guild = %guild, user = %author_id, role = %name, "Made role requestable");
+    Ok(())
+}
+
+pub async fn handle_remove_requestable_role<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: &dyn DiscordApi
+) -> Result<()> {
+    let name = rest_command.join(" ");
+    let language = language(guild, author.id).await;
+    let removed = PersistentState::for_guild(guild).await?.lock().await
+        .remove_requestable_role(author.id, &name)?;
+    let message = match removed {
+        Some(role) => Msg::RoleNoLongerRequestable { role: &role.name }.text(language),
+        None => available_roles_message(guild, language).await?,
+    };
+    send_message(http, original_channel, author.id, message).await
+}
+
+pub async fn handle_list_roles(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: &dyn DiscordApi
+) -> Result<()> {
+    let roles = PersistentState::for_guild(guild).await?.lock().await
+        .requestable_roles.iter()
+        .map(|role| format!("{} {}", role.emoji, role.name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let language = language(guild, author.id).await;
+    let message = if roles.is_empty() {
+        Msg::NoRequestableRoles.text(language)
+    }
+    else {
+        Msg::RequestableRoles { roles: &roles }.text(language)
+    };
+    send_message(http, original_channel, author.id, message).await
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(GiveRole);
+    registry.register(LeaveRole);
+    registry.register(ListRoles);
+    registry.register(AddRole);
+    registry.register(RemoveRole);
+}
+
+struct GiveRole;
+
+#[async_trait]
+impl Command for GiveRole {
+    fn name(&self) -> &'static str { "role" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageRoleName) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeGiveRole }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_give_role(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct LeaveRole;
+
+#[async_trait]
+impl Command for LeaveRole {
+    fn name(&self) -> &'static str { "leave" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageRoleName) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeLeaveRole }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_remove_role(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct ListRoles;
+
+#[async_trait]
+impl Command for ListRoles {
+    fn name(&self) -> &'static str { "roles" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeListRoles }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_list_roles(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct AddRole;
+
+#[async_trait]
+impl Command for AddRole {
+    fn name(&self) -> &'static str { "addrole" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageRoleAndEmoji) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeAddRole }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::RolesConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_add_requestable_role(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct RemoveRole;
+
+#[async_trait]
+impl Command for RemoveRole {
+    fn name(&self) -> &'static str { "removerole" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageRoleName) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeRemoveRole }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::RolesConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_remove_requestable_role(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
 #[derive(Debug)]
 pub enum RoleError {
-    RequestFailed(DiscordError),
+    RequestFailed(anyhow::Error),
     InvalidName(String),
     AlreadySet(String),
     NotSet(String),
 }
 
-impl From<DiscordError> for RoleError {
-    fn from(e: DiscordError) -> Self {
+impl From<anyhow::Error> for RoleError {
+    fn from(e: anyhow::Error) -> Self {
         Self::RequestFailed(e)
     }
 }
 
+impl RoleError {
+    /// The error in the language, to send back to the user
+    pub fn message(&self, language: Language) -> String {
+        match self {
+            Self::RequestFailed(e) => Msg::DiscordError { error: &e.to_string() }.text(language),
+            Self::InvalidName(invalid_name) => Msg::InvalidRoleName { name: invalid_name }.text(language),
+            Self::AlreadySet(role) => Msg::RoleAlreadySet { role }.text(language),
+            Self::NotSet(role) => Msg::RoleNotSet { role }.text(language),
+        }
+    }
+}
+
 impl Display for RoleError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let msg = match self {
-            Self::RequestFailed(e) =>
-                format!("Discord error: {}", e),
-            Self::InvalidName(invalid_name) =>
-                format!("Invalid role name \"{}\"", invalid_name),
-            Self::AlreadySet(role) =>
-                format!("Role **{}** already set", role),
-            Self::NotSet(role) =>
-                format!("Role **{}** not set", role),
-        };
-        write!(f, "{}", msg)
+        write!(f, "{}", self.message(Language::En))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::fake::{test_guild, Call, FakeDiscord};
+
+    const USER: UserId = UserId(10);
+
+    #[tokio::test]
+    async fn set_role_gives_the_role() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        discord.add_role(guild, "Musician");
+        let role = discord.add_role(guild, "Programmer");
+        discord.add_member(guild, USER, &[]);
+
+        assert_eq!(set_role(&discord, guild, USER, "programmer").await.unwrap(), "Programmer");
+        assert_eq!(discord.member_roles(guild, USER).unwrap(), vec!(role));
+    }
+
+    #[tokio::test]
+    async fn set_role_keeps_a_role_the_user_has() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        let role = discord.add_role(guild, "Programmer");
+        discord.add_member(guild, USER, &[role]);
+
+        match set_role(&discord, guild, USER, "Programmer").await {
+            Err(RoleError::AlreadySet(name)) => assert_eq!(name, "Programmer"),
+            other => panic!("Expected AlreadySet, got {:?}", other),
+        }
+        assert!(!discord.calls().iter().any(|call| match call {
+            Call::AddGuildMemberRole(..) => true,
+            _ => false,
+        }));
+    }
+
+    #[tokio::test]
+    async fn set_role_rejects_unknown_roles() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        discord.add_role(guild, "Programmer");
+        discord.add_member(guild, USER, &[]);
+
+        match set_role(&discord, guild, USER, "Juggler").await {
+            Err(RoleError::InvalidName(name)) => assert_eq!(name, "Juggler"),
+            other => panic!("Expected InvalidName, got {:?}", other),
+        }
+        assert_eq!(discord.member_roles(guild, USER).unwrap(), vec!());
+    }
+
+    #[tokio::test]
+    async fn set_role_reports_failed_requests() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        discord.add_role(guild, "Programmer");
+        discord.add_member(guild, USER, &[]);
+        discord.fail_on(|call| match call {
+            Call::AddGuildMemberRole(..) => true,
+            _ => false,
+        });
+
+        let result = set_role(&discord, guild, USER, "Programmer").await;
+
+        assert!(matches!(result, Err(RoleError::RequestFailed(_))));
+        assert_eq!(discord.member_roles(guild, USER).unwrap(), vec!());
+    }
+
+    #[tokio::test]
+    async fn set_role_reports_when_the_roles_cant_be_fetched() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        discord.add_member(guild, USER, &[]);
+        discord.fail_on(|call| match call {
+            Call::Roles(_) => true,
+            _ => false,
+        });
+
+        let result = set_role(&discord, guild, USER, "Programmer").await;
+
+        assert!(matches!(result, Err(RoleError::RequestFailed(_))));
     }
 }
@@ -1,71 +1,164 @@
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::prelude::*;
+use std::fs;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use lazy_static::lazy_static;
 use serde_derive::{Serialize, Deserialize};
 use serde_json;
-use twilight::model::id::{ChannelId, MessageId, UserId};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task;
+use tracing::info;
+use twilight::model::{
+    channel::{Channel, GuildChannel},
+    id::{ChannelId, GuildId, MessageId, UserId},
+};
 
 use crate::channel::Team;
+use crate::config::{Config, RoleConfig};
+use crate::discord::DiscordApi;
+use crate::journal::Undo;
+use crate::locale::Language;
+use crate::migration;
+use crate::permission::Holders;
+use crate::storage::{storage, Change};
+use crate::theme::ThemeIdea;
+use crate::theme_policy::ThemePolicy;
 use crate::utils::Result;
+use crate::vote::Vote;
 
-const FILENAME: &'static str = "state.json";
+/// The file all state was stored in before it was split up by guild
+const LEGACY_FILENAME: &'static str = "state.json";
+/// Environment variable naming the guild of the legacy file, in case it
+/// can't be worked out from the channels it refers to
+const LEGACY_GUILD_VAR: &'static str = "LEGACY_STATE_GUILD";
+
+lazy_static! {
+    /// The registry itself is only locked briefly, while each guild's state
+    /// uses an async mutex so that waiting for it doesn't block other tasks
+    static ref GUILDS: Mutex<HashMap<GuildId, Arc<AsyncMutex<PersistentState>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Placeholder used while deserializing, the real id comes from the file name
+fn unknown_guild() -> GuildId {
+    GuildId(0)
+}
 
 /**
   Stores state that should persist between bot restarts.
 
-  The data is stored as json and is loaded lazily on the first use
-  of the struct.
+  There is one instance per guild so that several jams can run on the same
+  bot. The data is kept in the storage chosen with `backend` under
+  `[storage]` in `gamejambot.toml`, see `storage.rs`, and is loaded lazily
+  on the first use of the guild.
 
   Data is not automatically reloaded on file changes
 */
 #[derive(Serialize, Deserialize)]
 pub struct PersistentState {
-    pub theme_ideas: HashMap<UserId, String>,
+    /// Layout version of the stored data, see `migration.rs`
+    version: u64,
+    #[serde(skip, default = "unknown_guild")]
+    guild_id: GuildId,
+    /// Theme ideas of each user, in the order they were submitted, see `theme.rs`
+    #[serde(default)]
+    pub theme_ideas: HashMap<UserId, Vec<ThemeIdea>>,
+    /// Number of ideas each user can have at once
+    pub max_theme_ideas: usize,
+    /// What ideas have to look like, see `theme_policy.rs`
+    pub theme_policy: ThemePolicy,
+    /// What generated themes look like, see `theme_template.rs`
+    pub theme_templates: Vec<String>,
+    #[serde(default)]
     pub channel_creators: HashMap<UserId, Team>,
+    /// Roles users can give themselves, see `role.rs`
+    pub requestable_roles: Vec<RoleConfig>,
     role_assign_channel_id: ChannelId,
     role_assign_message_id: MessageId,
+    /// Channel where organizer actions and errors are posted, see `log_channel.rs`
+    log_channel_id: ChannelId,
+    /// Language of replies to users that haven't chosen one, see `locale.rs`
+    pub language: Language,
+    /// Languages users have chosen for themselves
+    pub user_languages: HashMap<UserId, Language>,
+    /// Who has each capability besides organizers, see `permission.rs`
+    pub permissions: HashMap<String, Holders>,
+    /// The theme vote that is going on, see `vote.rs`
+    #[serde(default)]
+    pub vote: Option<Vote>,
 }
 
 impl PersistentState {
-    /// Load the data from disk, or default initialise it if the file doesn't exist
-    fn load() -> Result<Self> {
-        if PathBuf::from(FILENAME).exists() {
-            let mut file = File::open(FILENAME)?;
-            let mut content = String::new();
-            file.read_to_string(&mut content)?;
-            Ok(serde_json::from_str(&content)?)
-        }
-        else {
-            Ok(Self {
-                theme_ideas: HashMap::new(),
-                channel_creators: HashMap::new(),
-                role_assign_channel_id: ChannelId(0),
-                role_assign_message_id: MessageId(0),
-            })
+    fn new(guild_id: GuildId) -> Self {
+        Self {
+            version: migration::CURRENT_VERSION,
+            guild_id,
+            theme_ideas: HashMap::new(),
+            max_theme_ideas: Config::get().themes.max_ideas,
+            theme_policy: Config::get().themes.policy.clone(),
+            theme_templates: Config::get().themes.templates.clone(),
+            channel_creators: HashMap::new(),
+            requestable_roles: Config::get().roles.clone(),
+            role_assign_channel_id: ChannelId(0),
+            role_assign_message_id: MessageId(0),
+            log_channel_id: ChannelId(0),
+            language: Config::get().language,
+            user_languages: HashMap::new(),
+            permissions: Config::get().permissions.iter()
+                .map(|(capability, holders)| (capability.clone(), holders.into()))
+                .collect(),
+            vote: None,
         }
     }
 
+    /// Parses stored state of any version, upgrading it to the current version
+    pub fn parse(content: &str, guild_id: GuildId) -> Result<Self> {
+        let json = migration::migrate(serde_json::from_str(content)?)?;
+        let mut state: Self = serde_json::from_value(json)?;
+        state.guild_id = guild_id;
+        Ok(state)
+    }
+
+    /// Load the data from storage, or default initialise it if nothing is stored
+    fn load(guild_id: GuildId) -> Result<Self> {
+        Ok(storage().load(guild_id)?
+            .map(|loaded| loaded.state)
+            .unwrap_or_else(|| Self::new(guild_id)))
+    }
+
     /**
-      Return a global instance of the struct. The instance is global to
-      avoid race conditions, especially with data stored on disk
+      Return the instance for the specified guild. The instances are global
+      to avoid race conditions, especially with data stored on disk.
+
+      Events are handled concurrently, so don't keep the state locked while
+      waiting for discord. Copy what is needed out of it instead.
+
+      The state is loaded on first use, without locking the registry so that
+      other guilds can be used meanwhile
     */
-    pub fn instance() -> &'static Mutex<Self> {
-        lazy_static! {
-            static ref INSTANCE: Mutex<PersistentState> = Mutex::new(
-                PersistentState::load().unwrap()
-            );
+    pub async fn for_guild(guild_id: GuildId) -> Result<Arc<AsyncMutex<Self>>> {
+        if let Some(state) = GUILDS.lock().unwrap().get(&guild_id) {
+            return Ok(state.clone())
         }
-        &INSTANCE
+        let state = task::spawn_blocking(move || PersistentState::load(guild_id)).await?
+            .with_context(|| format!("Failed to load the state of guild {}", guild_id))?;
+        // Another task can have loaded the guild meanwhile, and its copy may be in use already
+        Ok(GUILDS.lock().unwrap()
+            .entry(guild_id)
+            .or_insert_with(|| Arc::new(AsyncMutex::new(state)))
+            .clone())
     }
 
-    /// Checks if the user is allowed to create a channel
-    pub fn has_created_channel(&mut self, id: UserId) -> bool {
-        self.channel_creators.contains_key(&id)
+    /// All guilds that have been looked up so far
+    pub fn loaded_guilds() -> Vec<GuildId> {
+        GUILDS.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The guild this state belongs to
+    pub fn guild_id(&self) -> GuildId {
+        self.guild_id
     }
 
     /// Gets the user's current channel
@@ -73,16 +166,34 @@ impl PersistentState {
         self.channel_creators.get(&id)
     }
 
-    /// Registers that the user has created a channel
-    pub fn register_channel_creation(&mut self, user_id: UserId, team: &Team) -> Result<()> {
+    /// Registers that the user has created or renamed a channel
+    pub fn register_channel_creation(
+        &mut self,
+        actor: UserId,
+        user_id: UserId,
+        team: &Team
+    ) -> Result<()> {
         self.channel_creators.insert(user_id, team.clone());
-        self.save()
+        self.save_change(Change::Team(user_id))?;
+        self.record(actor,
+            format!("Registered the team {} of <@{}>", team.game_name(), user_id),
+            None
+        )
     }
 
-    /// Remove a registered channel
-    pub fn remove_channel(&mut self, user_id: UserId) -> Result<()> {
-        self.channel_creators.remove(&user_id);
-        self.save()
+    /// Remove a registered channel. The team is kept if the removal can't be saved
+    pub fn remove_channel(&mut self, actor: UserId, user_id: UserId) -> Result<()> {
+        if let Some(team) = self.channel_creators.remove(&user_id) {
+            if let Err(e) = self.save_change(Change::Team(user_id)) {
+                self.channel_creators.insert(user_id, team);
+                return Err(e)
+            }
+            self.record(actor,
+                format!("Removed the team {} of <@{}>", team.game_name(), user_id),
+                Some(Undo::Team(user_id, team))
+            )?;
+        }
+        Ok(())
     }
 
     /// Gets the number of created channels
@@ -90,34 +201,126 @@ impl PersistentState {
         self.channel_creators.len()
     }
 
-    /// Remove all registered channels
-    pub fn remove_all_channels(&mut self) -> Result<()> {
-        self.channel_creators.clear();
-        self.save()
+    /// Remove all registered channels. The teams are kept if the removal can't be saved
+    pub fn remove_all_channels(&mut self, actor: UserId) -> Result<()> {
+        let teams = std::mem::replace(&mut self.channel_creators, HashMap::new());
+        if let Err(e) = self.save_change(Change::AllTeams) {
+            self.channel_creators = teams;
+            return Err(e)
+        }
+        self.record(actor,
+            format!("Cleared all {} user–channel associations", teams.len()),
+            Some(Undo::Teams(teams))
+        )
     }
 
     /// Sets the role assignment message
-    pub fn set_role_assign(&mut self, channel_id: ChannelId, message_id: MessageId) -> Result<()> {
+    pub fn set_role_assign(
+        &mut self,
+        actor: UserId,
+        channel_id: ChannelId,
+        message_id: MessageId
+    ) -> Result<()> {
         self.role_assign_channel_id = channel_id;
         self.role_assign_message_id = message_id;
-        self.save()
+        self.save_change(Change::ReactionMessage)?;
+        self.record(actor,
+            format!("Set the role assignment message to {} in <#{}>", message_id, channel_id),
+            None
+        )
     }
+
+    /// Sets the role assignment message without saving, used when loading
+    pub fn restore_role_assign(&mut self, channel_id: ChannelId, message_id: MessageId) {
+        self.role_assign_channel_id = channel_id;
+        self.role_assign_message_id = message_id;
+    }
+
     /// Gets the channel containing the role assignment message
-    pub fn get_role_assign_channel(&mut self) -> ChannelId {
+    pub fn get_role_assign_channel(&self) -> ChannelId {
         self.role_assign_channel_id
     }
 
     /// Gets the role assignment message
-    pub fn get_role_assign_message(&mut self) -> MessageId {
+    pub fn get_role_assign_message(&self) -> MessageId {
         self.role_assign_message_id
     }
 
-    /// Save the state to disk. Should be called after all modifications
+    /// Sets the organizer log channel, or turns it off with a channel id of 0
+    pub fn set_log_channel(&mut self, actor: UserId, channel_id: ChannelId) -> Result<()> {
+        self.log_channel_id = channel_id;
+        self.save_change(Change::Settings)?;
+        self.record(actor, format!("Set the log channel to <#{}>", channel_id), None)
+    }
+
+    /// Gets the organizer log channel
+    pub fn get_log_channel(&self) -> ChannelId {
+        self.log_channel_id
+    }
+
+    /// Save the whole state. Should be called after all modifications
     pub fn save(&self) -> Result<()> {
-        let mut file = File::create(FILENAME)
-            .with_context(|| format!("Failed to open {} for writing", FILENAME))?;
-        file.write_all(serde_json::to_string(&self)?.as_bytes())
-            .with_context(|| format!("Failed to write to {}", FILENAME))?;
-        Ok(())
+        self.save_change(Change::All)
     }
+
+    /// Save the state after a modification that only changed the specified part
+    pub fn save_change(&self, change: Change) -> Result<()> {
+        storage().save(self, change)
+    }
+}
+
+/**
+  Moves the state from before it was split by guild into the file of the
+  guild it belongs to. The guild is found by asking discord about the
+  channels the state refers to, falling back to the guild id in the
+  `LEGACY_STATE_GUILD` environment variable.
+*/
+pub async fn migrate_legacy_state(http: &dyn DiscordApi) -> Result<()> {
+    if !PathBuf::from(LEGACY_FILENAME).exists() {
+        return Ok(())
+    }
+    let content = fs::read_to_string(LEGACY_FILENAME)
+        .with_context(|| format!("Failed to read {}", LEGACY_FILENAME))?;
+    let mut state = PersistentState::parse(&content, unknown_guild())
+        .with_context(|| format!("Failed to parse {}", LEGACY_FILENAME))?;
+
+    let mut channels = vec!(state.role_assign_channel_id);
+    channels.extend(state.channel_creators.values().map(|team| team.category_id()));
+
+    let mut guild_id = None;
+    for channel_id in channels.into_iter().filter(|id| id.0 != 0) {
+        guild_id = match http.channel(channel_id).await {
+            Ok(Some(Channel::Guild(GuildChannel::Category(c)))) => c.guild_id,
+            Ok(Some(Channel::Guild(GuildChannel::Text(c)))) => c.guild_id,
+            _ => None,
+        };
+        if guild_id.is_some() {
+            break;
+        }
+    }
+    if guild_id.is_none() {
+        if let Ok(id) = std::env::var(LEGACY_GUILD_VAR) {
+            guild_id = Some(GuildId(id.parse()
+                .with_context(|| format!("{} is not a guild id", LEGACY_GUILD_VAR))?
+            ));
+        }
+    }
+    let guild_id = guild_id.ok_or_else(|| anyhow!(
+        "Could not work out which guild {} belongs to. Set {} to its guild id",
+        LEGACY_FILENAME, LEGACY_GUILD_VAR
+    ))?;
+
+    if storage().load(guild_id)?.is_some() {
+        return Err(anyhow!(
+            "Both {} and the state of guild {} exist, refusing to overwrite the latter",
+            LEGACY_FILENAME, guild_id
+        ));
+    }
+
+    state.guild_id = guild_id;
+    state.save()?;
+    fs::rename(LEGACY_FILENAME, format!("{}.migrated", LEGACY_FILENAME))
+        .with_context(|| format!("Failed to move {} out of the way", LEGACY_FILENAME))?;
+    info!(guild = %guild_id, "Migrated {} into the state of the guild", LEGACY_FILENAME);
+    Ok(())
 }
@@ -0,0 +1,1031 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context};
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use tracing::{error, info};
+use twilight::model::id::{ChannelId, GuildId, MessageId, UserId};
+
+use crate::channel::Team;
+use crate::config::{Backend, Config};
+use crate::journal::JournalEntry;
+use crate::migration;
+use crate::state::PersistentState;
+use crate::theme::ThemeIdea;
+use crate::theme_template::Category;
+use crate::utils::Result;
+use crate::vote::{Candidate, Vote};
+
+/// Directory containing one json file per guild
+const DIRECTORY: &'static str = "state";
+/// Number of previous versions of each json file to keep
+const BACKUP_COUNT: usize = 5;
+
+static STORAGE: OnceCell<Box<dyn Storage>> = OnceCell::new();
+#[cfg(test)]
+static TEST_STORAGE: OnceCell<TestStorage> = OnceCell::new();
+
+/// Opens the storage backend chosen in the config. Must be called once at startup
+pub fn init() -> Result<()> {
+    let config = &Config::get().storage;
+    let storage: Box<dyn Storage> = match config.backend {
+        Backend::Json => Box::new(JsonStorage::new(DIRECTORY)),
+        Backend::Sqlite => Box::new(SqliteStorage::open(&config.database, JsonStorage::new(DIRECTORY))?),
+    };
+    STORAGE.set(storage).map_err(|_| anyhow!("The storage was opened twice"))?;
+    Ok(())
+}
+
+/// The storage backend opened at startup
+pub fn storage() -> &'static dyn Storage {
+    // Tests keep everything in memory so that they never touch the state directory
+    #[cfg(test)]
+    let storage = TEST_STORAGE.get_or_init(TestStorage::new);
+    #[cfg(not(test))]
+    let storage = &**STORAGE.get().expect("The storage was used before it was opened");
+    storage
+}
+
+/// Makes every save of the state of the guild fail from now on
+#[cfg(test)]
+pub fn fail_saves(guild_id: GuildId) {
+    TEST_STORAGE.get_or_init(TestStorage::new).failing.lock().unwrap().insert(guild_id);
+}
+
+/**
+  The storage of tests, which is kept in memory and can be made to fail.
+  Json files are looked for in a temporary directory that stays empty
+*/
+#[cfg(test)]
+struct TestStorage {
+    sqlite: SqliteStorage,
+    /// Guilds whose state can't be saved
+    failing: Mutex<HashSet<GuildId>>,
+    _directory: tempfile::TempDir,
+}
+
+#[cfg(test)]
+impl TestStorage {
+    fn new() -> Self {
+        let directory = tempfile::tempdir().unwrap();
+        Self {
+            sqlite: SqliteStorage::open(":memory:", JsonStorage::new(directory.path())).unwrap(),
+            failing: Mutex::new(HashSet::new()),
+            _directory: directory,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Storage for TestStorage {
+    fn load(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        self.sqlite.load(guild_id)
+    }
+
+    fn inspect(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        self.sqlite.inspect(guild_id)
+    }
+
+    fn save(&self, state: &PersistentState, change: Change) -> Result<()> {
+        if self.failing.lock().unwrap().contains(&state.guild_id()) {
+            return Err(anyhow!("Saving the state of guild {} fails in this test", state.guild_id()))
+        }
+        self.sqlite.save(state, change)
+    }
+
+    fn guilds(&self) -> Result<Vec<GuildId>> {
+        self.sqlite.guilds()
+    }
+
+    fn append_journal(&self, guild_id: GuildId, entry: &JournalEntry) -> Result<()> {
+        self.sqlite.append_journal(guild_id, entry)
+    }
+
+    fn load_journal(&self, guild_id: GuildId) -> Result<Vec<JournalEntry>> {
+        self.sqlite.load_journal(guild_id)
+    }
+}
+
+/**
+  The part of the state that changed since it was last saved. Backends that
+  can update parts of the state use it to avoid rewriting everything
+*/
+#[derive(Clone, Copy, Debug)]
+pub enum Change {
+    /// The theme ideas of a user were added, replaced or removed
+    ThemeIdeas(UserId),
+    /// The team of a user was added, changed or removed
+    Team(UserId),
+    /// Any number of teams were changed
+    AllTeams,
+    /// The message used for reaction roles was changed
+    ReactionMessage,
+    /// Settings without a table of their own, like the log channel, were changed
+    Settings,
+    /// The theme vote was started, moved on to another round or closed
+    Vote,
+    /// A user voted or took back a vote in the open theme vote
+    Ballot(UserId),
+    /// Anything could have changed
+    All,
+}
+
+/// State read from storage
+pub struct Loaded {
+    pub state: PersistentState,
+    /// The backup that was read instead because the newest state couldn't be
+    pub backup: Option<PathBuf>,
+    /// The json file the state was read from, if it hasn't been imported yet
+    pub unimported: Option<PathBuf>,
+}
+
+impl Loaded {
+    fn new(state: PersistentState) -> Self {
+        Self { state, backup: None, unimported: None }
+    }
+}
+
+/// Somewhere to keep the persistent state of each guild
+pub trait Storage: Send + Sync {
+    /// Loads the state of the guild, or None if nothing has been stored yet
+    fn load(&self, guild_id: GuildId) -> Result<Option<Loaded>>;
+
+    /**
+      Reads the state of the guild like `load` but without changing anything,
+      so that it can be checked while the bot is stopped. `load` may import
+      the state from elsewhere on the way
+    */
+    fn inspect(&self, guild_id: GuildId) -> Result<Option<Loaded>>;
+
+    /// Stores the state after the specified change
+    fn save(&self, state: &PersistentState, change: Change) -> Result<()>;
+
+    /// All guilds with stored state
+    fn guilds(&self) -> Result<Vec<GuildId>>;
+
+    /// Adds an entry to the end of the journal of the guild, see `journal.rs`
+    fn append_journal(&self, guild_id: GuildId, entry: &JournalEntry) -> Result<()>;
+
+    /// All journal entries of the guild, oldest first
+    fn load_journal(&self, guild_id: GuildId) -> Result<Vec<JournalEntry>>;
+}
+
+/**
+  Stores the state of each guild in `<guild id>.json` in its directory,
+  which is `state` outside of tests.
+
+  The whole file is rewritten on every change. It is written to a temporary
+  file which replaces the old one once it is safely on disk, so a crash never
+  leaves a half written file behind. The previous versions are kept as
+  numbered backups, except after ballots which would otherwise push every
+  useful backup out during a vote.
+
+  The journal is appended to `<guild id>.journal`, one json entry per line
+*/
+pub struct JsonStorage {
+    directory: PathBuf,
+}
+
+impl JsonStorage {
+    /// Keeps the files in the directory, which is created when first written to
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into() }
+    }
+
+    pub fn path(&self, guild_id: GuildId) -> PathBuf {
+        self.directory.join(format!("{}.json", guild_id))
+    }
+
+    fn journal_path(&self, guild_id: GuildId) -> PathBuf {
+        self.directory.join(format!("{}.journal", guild_id))
+    }
+
+    /// Path of the nth newest backup, starting at 1
+    fn backup_path(&self, guild_id: GuildId, n: usize) -> PathBuf {
+        self.directory.join(format!("{}.json.{}", guild_id, n))
+    }
+
+    fn create_directory(&self) -> Result<()> {
+        fs::create_dir_all(&self.directory)
+            .with_context(|| format!("Failed to create {}", self.directory.display()))
+    }
+
+    fn read(path: &Path, guild_id: GuildId) -> Result<PersistentState> {
+        let mut file = File::open(path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        PersistentState::parse(&content, guild_id)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+impl Storage for JsonStorage {
+    /// If the file can't be read, the newest readable backup is used instead
+    fn load(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        let path = self.path(guild_id);
+        let error = if path.exists() {
+            match Self::read(&path, guild_id) {
+                Ok(state) => return Ok(Some(Loaded::new(state))),
+                Err(e) => e,
+            }
+        }
+        else if self.backup_path(guild_id, 1).exists() {
+            anyhow!("{} is missing", path.display())
+        }
+        else {
+            return Ok(None)
+        };
+
+        for n in 1..=BACKUP_COUNT {
+            let backup = self.backup_path(guild_id, n);
+            if let Ok(state) = Self::read(&backup, guild_id) {
+                error!(
+                    guild = %guild_id, error = ?error,
+                    "Could not load {}. Using the backup {} instead. Changes \
+                    made after it was saved are lost",
+                    path.display(), backup.display()
+                );
+                return Ok(Some(Loaded { backup: Some(backup), ..Loaded::new(state) }))
+            }
+        }
+        Err(error.context(format!("No readable backup of {} found", path.display())))
+    }
+
+    /// Loading only reads files, so it is the same as `load`
+    fn inspect(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        self.load(guild_id)
+    }
+
+    fn save(&self, state: &PersistentState, change: Change) -> Result<()> {
+        self.create_directory()?;
+        let guild_id = state.guild_id();
+        let path = self.path(guild_id);
+        let tmp_path = path.with_extension("json.tmp");
+
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("Failed to open {} for writing", tmp_path.display()))?;
+        file.write_all(serde_json::to_string(state)?.as_bytes())
+            .with_context(|| format!("Failed to write to {}", tmp_path.display()))?;
+        file.sync_all()
+            .with_context(|| format!("Failed to sync {}", tmp_path.display()))?;
+
+        let ballot = matches!(change, Change::Ballot(_));
+        if path.exists() && !ballot {
+            for n in (1..BACKUP_COUNT).rev() {
+                let backup = self.backup_path(guild_id, n);
+                if backup.exists() {
+                    fs::rename(&backup, self.backup_path(guild_id, n + 1))
+                        .with_context(|| format!("Failed to rotate {}", backup.display()))?;
+                }
+            }
+            // Copy rather than move so that there is always a complete file at `path`
+            fs::copy(&path, self.backup_path(guild_id, 1))
+                .with_context(|| format!("Failed to back up {}", path.display()))?;
+        }
+
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        // Make sure the rename itself is on disk. Not possible on all platforms
+        if let Ok(directory) = File::open(&self.directory) {
+            directory.sync_all().ok();
+        }
+        Ok(())
+    }
+
+    fn guilds(&self) -> Result<Vec<GuildId>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new())
+        }
+        let mut guilds = Vec::new();
+        for entry in fs::read_dir(&self.directory)
+            .with_context(|| format!("Failed to list {}", self.directory.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                if let Some(Ok(id)) = path.file_stem().and_then(|s| s.to_str()).map(str::parse) {
+                    guilds.push(GuildId(id));
+                }
+            }
+        }
+        guilds.sort();
+        Ok(guilds)
+    }
+
+    fn append_journal(&self, guild_id: GuildId, entry: &JournalEntry) -> Result<()> {
+        self.create_directory()?;
+        let path = self.journal_path(guild_id);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {} for appending", path.display()))?;
+        // A single write so that a crash can at most leave the last line incomplete
+        file.write_all(format!("{}\n", serde_json::to_string(entry)?).as_bytes())
+            .with_context(|| format!("Failed to write to {}", path.display()))?;
+        file.sync_data()
+            .with_context(|| format!("Failed to sync {}", path.display()))?;
+        Ok(())
+    }
+
+    fn load_journal(&self, guild_id: GuildId) -> Result<Vec<JournalEntry>> {
+        let path = self.journal_path(guild_id);
+        if !path.exists() {
+            return Ok(Vec::new())
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut entries = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => error!(guild = %guild_id, error = ?e,
+                    "Skipping unreadable line {} of {}", number + 1, path.display()),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+const REACTION_ROLE_ASSIGN: &'static str = "role_assign";
+
+/**
+  The nth statement upgrades the database schema from version n to version
+  n + 1. The version is kept in sqlite's `user_version`.
+
+  The tables are first created as they were in version 0, so add a statement
+  here instead of changing them
+*/
+const SCHEMA_MIGRATIONS: &[&str] = &[
+    // Version 1 has several theme ideas per user, kept in submission order
+    "ALTER TABLE theme_ideas RENAME TO theme_ideas_v0;
+    CREATE TABLE theme_ideas (
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        idea TEXT NOT NULL,
+        PRIMARY KEY (guild_id, user_id, position)
+    );
+    INSERT INTO theme_ideas (guild_id, user_id, position, idea)
+        SELECT guild_id, user_id, 0, idea FROM theme_ideas_v0;
+    DROP TABLE theme_ideas_v0;",
+    // Version 2 theme ideas can have a category
+    "ALTER TABLE theme_ideas ADD COLUMN category TEXT;",
+    // Version 3 keeps theme votes out of the json of the guild. The votes that
+    // are open are moved over by `move_votes`
+    "CREATE TABLE votes (
+        guild_id INTEGER PRIMARY KEY,
+        channel_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        round INTEGER NOT NULL,
+        later_rounds TEXT NOT NULL,
+        deadline INTEGER NOT NULL
+    );
+    CREATE TABLE vote_candidates (
+        guild_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        theme TEXT NOT NULL,
+        emoji TEXT NOT NULL,
+        PRIMARY KEY (guild_id, position)
+    );
+    CREATE TABLE ballots (
+        guild_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, position, user_id)
+    );",
+];
+
+/// The schema version that moved votes into tables of their own
+const VOTE_TABLES_VERSION: usize = 3;
+
+/**
+  Fields of the state with tables of their own. They are left out of the
+  json in the `guilds` table so that changing the settings doesn't rewrite them
+*/
+const SEPARATELY_STORED: &[&str] = &["theme_ideas", "channel_creators", "vote"];
+
+/**
+  Stores the state in an sqlite database.
+
+  Theme ideas, teams, reaction messages, votes and journal entries have
+  tables of their own and are updated row by row. The rest of the state is
+  stored as json in the `guilds` table and is only rewritten when the
+  settings change.
+
+  Guilds that are not in the database yet are imported from their json file
+  the first time they are loaded
+*/
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+    /// Where the json files to import are
+    json: JsonStorage,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str, json: JsonStorage) -> Result<Self> {
+        let mut connection = Connection::open(path)
+            .with_context(|| format!("Failed to open database {}", path))?;
+        connection.execute_batch("
+            CREATE TABLE IF NOT EXISTS guilds (
+                guild_id INTEGER PRIMARY KEY,
+                state TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS theme_ideas (
+                guild_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                idea TEXT NOT NULL,
+                PRIMARY KEY (guild_id, user_id)
+            );
+            CREATE TABLE IF NOT EXISTS teams (
+                guild_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                team TEXT NOT NULL,
+                PRIMARY KEY (guild_id, user_id)
+            );
+            CREATE TABLE IF NOT EXISTS reaction_messages (
+                guild_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                channel_id INTEGER NOT NULL,
+                message_id INTEGER NOT NULL,
+                PRIMARY KEY (guild_id, kind)
+            );
+            CREATE TABLE IF NOT EXISTS journal (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                guild_id INTEGER NOT NULL,
+                entry TEXT NOT NULL
+            );
+        ").context("Failed to create database tables")?;
+
+        let version: i64 = connection.query_row("PRAGMA user_version", params![], |row| row.get(0))?;
+        for (from, migration) in SCHEMA_MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            if from + 1 == VOTE_TABLES_VERSION {
+                move_votes(&transaction)?;
+            }
+            transaction.execute_batch(&format!("PRAGMA user_version = {}", from + 1))?;
+            transaction.commit()
+                .with_context(|| format!("Failed to upgrade the database to version {}", from + 1))?;
+            info!("Upgraded database from version {} to {}", from, from + 1);
+        }
+        Ok(Self { connection: Mutex::new(connection), json })
+    }
+
+    /**
+      Copies the json state of the guild into the database, if there is one.
+      The json file is renamed afterwards so that it isn't imported again
+    */
+    fn import_json(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        let loaded = match self.json.load(guild_id)? {
+            Some(loaded) => loaded,
+            None => return Ok(None),
+        };
+        self.save(&loaded.state, Change::All)?;
+        for entry in self.json.load_journal(guild_id)? {
+            self.append_journal(guild_id, &entry)?;
+        }
+
+        let journal_path = self.json.journal_path(guild_id);
+        if journal_path.exists() {
+            fs::rename(&journal_path, journal_path.with_extension("journal.imported"))
+                .with_context(|| format!("Failed to move {} out of the way", journal_path.display()))?;
+        }
+        let path = self.json.path(guild_id);
+        fs::rename(&path, path.with_extension("json.imported"))
+            .with_context(|| format!("Failed to move {} out of the way", path.display()))?;
+        info!(guild = %guild_id, "Imported {} into the database", path.display());
+        Ok(Some(loaded))
+    }
+}
+
+/// The json of the state kept in the `guilds` table
+fn settings_json(state: &PersistentState) -> Result<String> {
+    let mut json = serde_json::to_value(state)?;
+    if let Some(object) = json.as_object_mut() {
+        for field in SEPARATELY_STORED {
+            object.remove(*field);
+        }
+    }
+    Ok(serde_json::to_string(&json)?)
+}
+
+/**
+  Moves the votes that were kept in the json of each guild into their
+  tables, leaving only the settings in the json. The json is brought up to
+  the current version first, since older votes look different
+*/
+fn move_votes(connection: &Connection) -> Result<()> {
+    let rows = {
+        let mut statement = connection.prepare("SELECT guild_id, state FROM guilds")?;
+        let rows = statement.query_map(params![], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (guild, json) in rows {
+        let mut json = serde_json::from_str(&json).map_err(anyhow::Error::from)
+            .and_then(migration::migrate)
+            .with_context(|| format!("Failed to parse the state of guild {}", guild))?;
+        let mut vote = Value::Null;
+        if let Some(object) = json.as_object_mut() {
+            vote = object.remove("vote").unwrap_or(Value::Null);
+            for field in SEPARATELY_STORED {
+                object.remove(*field);
+            }
+        }
+        let vote = serde_json::from_value::<Option<Vote>>(vote)
+            .with_context(|| format!("Failed to parse the vote of guild {}", guild))?;
+        save_vote(connection, GuildId(guild as u64), vote.as_ref())?;
+        connection.execute(
+            "UPDATE guilds SET state = ?2 WHERE guild_id = ?1",
+            params![guild, serde_json::to_string(&json)?],
+        )?;
+    }
+    Ok(())
+}
+
+fn save_theme_ideas(
+    connection: &Connection,
+    state: &PersistentState,
+    user_id: UserId,
+) -> Result<()> {
+    let guild = state.guild_id().0 as i64;
+    connection.execute(
+        "DELETE FROM theme_ideas WHERE guild_id = ?1 AND user_id = ?2",
+        params![guild, user_id.0 as i64],
+    )?;
+    for (position, idea) in state.theme_ideas.get(&user_id).into_iter().flatten().enumerate() {
+        connection.execute(
+            "INSERT INTO theme_ideas (guild_id, user_id, position, idea, category)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                guild, user_id.0 as i64, position as i64, idea.text,
+                idea.category.map(Category::name),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn save_team(
+    connection: &Connection,
+    state: &PersistentState,
+    user_id: UserId,
+) -> Result<()> {
+    let guild = state.guild_id().0 as i64;
+    match state.channel_creators.get(&user_id) {
+        Some(team) => connection.execute(
+            "INSERT OR REPLACE INTO teams (guild_id, user_id, team) VALUES (?1, ?2, ?3)",
+            params![guild, user_id.0 as i64, serde_json::to_string(team)?],
+        )?,
+        None => connection.execute(
+            "DELETE FROM teams WHERE guild_id = ?1 AND user_id = ?2",
+            params![guild, user_id.0 as i64],
+        )?,
+    };
+    Ok(())
+}
+
+fn save_all_teams(connection: &Connection, state: &PersistentState) -> Result<()> {
+    connection.execute(
+        "DELETE FROM teams WHERE guild_id = ?1",
+        params![state.guild_id().0 as i64],
+    )?;
+    for user_id in state.channel_creators.keys() {
+        save_team(connection, state, *user_id)?;
+    }
+    Ok(())
+}
+
+fn save_reaction_message(connection: &Connection, state: &PersistentState) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO reaction_messages (guild_id, kind, channel_id, message_id)
+            VALUES (?1, ?2, ?3, ?4)",
+        params![
+            state.guild_id().0 as i64,
+            REACTION_ROLE_ASSIGN,
+            state.get_role_assign_channel().0 as i64,
+            state.get_role_assign_message().0 as i64
+        ],
+    )?;
+    Ok(())
+}
+
+fn save_vote(connection: &Connection, guild_id: GuildId, vote: Option<&Vote>) -> Result<()> {
+    let guild = guild_id.0 as i64;
+    for table in &["votes", "vote_candidates", "ballots"] {
+        connection.execute(
+            &format!("DELETE FROM {} WHERE guild_id = ?1", table),
+            params![guild],
+        )?;
+    }
+    let vote = match vote {
+        Some(vote) => vote,
+        None => return Ok(()),
+    };
+    connection.execute(
+        "INSERT INTO votes (guild_id, channel_id, message_id, round, later_rounds, deadline)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            guild, vote.channel_id.0 as i64, vote.message_id.0 as i64, vote.round as i64,
+            serde_json::to_string(&vote.later_rounds)?, vote.deadline,
+        ],
+    )?;
+    for (position, candidate) in vote.candidates.iter().enumerate() {
+        connection.execute(
+            "INSERT INTO vote_candidates (guild_id, position, theme, emoji) VALUES (?1, ?2, ?3, ?4)",
+            params![guild, position as i64, candidate.theme, candidate.emoji],
+        )?;
+        for user_id in &candidate.voters {
+            connection.execute(
+                "INSERT INTO ballots (guild_id, position, user_id) VALUES (?1, ?2, ?3)",
+                params![guild, position as i64, user_id.0 as i64],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the votes of the user in the open vote
+fn save_ballot(
+    connection: &Connection,
+    state: &PersistentState,
+    user_id: UserId,
+) -> Result<()> {
+    let guild = state.guild_id().0 as i64;
+    connection.execute(
+        "DELETE FROM ballots WHERE guild_id = ?1 AND user_id = ?2",
+        params![guild, user_id.0 as i64],
+    )?;
+    let candidates = state.vote.iter().flat_map(|vote| vote.candidates.iter());
+    for (position, candidate) in candidates.enumerate() {
+        if candidate.voters.contains(&user_id) {
+            connection.execute(
+                "INSERT INTO ballots (guild_id, position, user_id) VALUES (?1, ?2, ?3)",
+                params![guild, position as i64, user_id.0 as i64],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn load_vote(connection: &Connection, guild_id: GuildId) -> Result<Option<Vote>> {
+    let guild = guild_id.0 as i64;
+    let vote = connection.query_row(
+        "SELECT channel_id, message_id, round, later_rounds, deadline FROM votes WHERE guild_id = ?1",
+        params![guild],
+        |row| Ok((
+            row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?, row.get::<_, i64>(4)?,
+        )),
+    ).optional()?;
+    let (channel_id, message_id, round, later_rounds, deadline) = match vote {
+        Some(vote) => vote,
+        None => return Ok(None),
+    };
+
+    let mut statement = connection.prepare(
+        "SELECT theme, emoji FROM vote_candidates WHERE guild_id = ?1 ORDER BY position"
+    )?;
+    let candidates = statement.query_map(params![guild], |row| Ok(Candidate {
+        theme: row.get(0)?,
+        emoji: row.get(1)?,
+        voters: HashSet::new(),
+    }))?;
+    let mut candidates = candidates.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut statement = connection.prepare(
+        "SELECT position, user_id FROM ballots WHERE guild_id = ?1"
+    )?;
+    let ballots = statement.query_map(params![guild], |row| {
+        Ok((row.get::<_, i64>(0)? as usize, UserId(row.get::<_, i64>(1)? as u64)))
+    })?;
+    for row in ballots {
+        let (position, user_id) = row?;
+        if let Some(candidate) = candidates.get_mut(position) {
+            candidate.voters.insert(user_id);
+        }
+    }
+
+    Ok(Some(Vote {
+        channel_id: ChannelId(channel_id as u64),
+        message_id: MessageId(message_id as u64),
+        round: round as usize,
+        later_rounds: serde_json::from_str(&later_rounds)?,
+        deadline,
+        candidates,
+    }))
+}
+
+impl SqliteStorage {
+    /// The state of the guild in the database, without importing anything
+    fn read(&self, guild_id: GuildId) -> Result<Option<PersistentState>> {
+        let guild = guild_id.0 as i64;
+        let connection = self.connection.lock().unwrap();
+        let json: Option<String> = connection.query_row(
+            "SELECT state FROM guilds WHERE guild_id = ?1",
+            params![guild],
+            |row| row.get(0),
+        ).optional()?;
+        let json = match json {
+            Some(json) => json,
+            None => return Ok(None),
+        };
+        let mut state = PersistentState::parse(&json, guild_id)
+            .with_context(|| format!("Failed to parse the state of guild {}", guild_id))?;
+
+        let mut statement = connection.prepare(
+            "SELECT user_id, idea, category FROM theme_ideas WHERE guild_id = ?1 ORDER BY position"
+        )?;
+        let ideas = statement.query_map(params![guild], |row| {
+            let category = row.get::<_, Option<String>>(2)?;
+            Ok((UserId(row.get::<_, i64>(0)? as u64), ThemeIdea {
+                text: row.get(1)?,
+                category: category.as_deref().and_then(Category::from_name),
+            }))
+        })?;
+        state.theme_ideas = HashMap::new();
+        for row in ideas {
+            let (user_id, idea) = row?;
+            state.theme_ideas.entry(user_id).or_insert_with(Vec::new).push(idea);
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT user_id, team FROM teams WHERE guild_id = ?1"
+        )?;
+        let teams = statement.query_map(params![guild], |row| {
+            Ok((UserId(row.get::<_, i64>(0)? as u64), row.get::<_, String>(1)?))
+        })?;
+        state.channel_creators = HashMap::new();
+        for row in teams {
+            let (user_id, team) = row?;
+            let team: Team = serde_json::from_str(&team)?;
+            state.channel_creators.insert(user_id, team);
+        }
+
+        let role_assign: Option<(i64, i64)> = connection.query_row(
+            "SELECT channel_id, message_id FROM reaction_messages
+                WHERE guild_id = ?1 AND kind = ?2",
+            params![guild, REACTION_ROLE_ASSIGN],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?;
+        if let Some((channel_id, message_id)) = role_assign {
+            state.restore_role_assign(
+                ChannelId(channel_id as u64),
+                MessageId(message_id as u64)
+            );
+        }
+
+        state.vote = load_vote(&connection, guild_id)?;
+        Ok(Some(state))
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        match self.read(guild_id)? {
+            Some(state) => Ok(Some(Loaded::new(state))),
+            None => self.import_json(guild_id),
+        }
+    }
+
+    /// Guilds that are only in a json file are read from it and reported as not imported
+    fn inspect(&self, guild_id: GuildId) -> Result<Option<Loaded>> {
+        match self.read(guild_id)? {
+            Some(state) => Ok(Some(Loaded::new(state))),
+            None => Ok(self.json.inspect(guild_id)?.map(|loaded| Loaded {
+                unimported: Some(self.json.path(guild_id)),
+                ..loaded
+            })),
+        }
+    }
+
+    fn save(&self, state: &PersistentState, change: Change) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        // The other tables are only read for guilds that have a row here
+        transaction.execute(
+            "INSERT OR IGNORE INTO guilds (guild_id, state) VALUES (?1, ?2)",
+            params![state.guild_id().0 as i64, settings_json(state)?],
+        )?;
+        match change {
+            Change::ThemeIdeas(user_id) => save_theme_ideas(&transaction, state, user_id)?,
+            Change::Team(user_id) => save_team(&transaction, state, user_id)?,
+            Change::AllTeams => save_all_teams(&transaction, state)?,
+            Change::ReactionMessage => save_reaction_message(&transaction, state)?,
+            Change::Vote => save_vote(&transaction, state.guild_id(), state.vote.as_ref())?,
+            Change::Ballot(user_id) => save_ballot(&transaction, state, user_id)?,
+            Change::Settings => {
+                transaction.execute(
+                    "UPDATE guilds SET state = ?2 WHERE guild_id = ?1",
+                    params![state.guild_id().0 as i64, settings_json(state)?],
+                )?;
+            }
+            Change::All => {
+                let guild = state.guild_id().0 as i64;
+                transaction.execute(
+                    "INSERT OR REPLACE INTO guilds (guild_id, state) VALUES (?1, ?2)",
+                    params![guild, settings_json(state)?],
+                )?;
+                transaction.execute(
+                    "DELETE FROM theme_ideas WHERE guild_id = ?1",
+                    params![guild],
+                )?;
+                for user_id in state.theme_ideas.keys() {
+                    save_theme_ideas(&transaction, state, *user_id)?;
+                }
+                save_all_teams(&transaction, state)?;
+                save_reaction_message(&transaction, state)?;
+                save_vote(&transaction, state.guild_id(), state.vote.as_ref())?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn guilds(&self) -> Result<Vec<GuildId>> {
+        let mut guilds = {
+            let connection = self.connection.lock().unwrap();
+            let mut statement = connection.prepare("SELECT guild_id FROM guilds")?;
+            let rows = statement.query_map(params![], |row| row.get::<_, i64>(0))?;
+            rows.map(|id| id.map(|id| GuildId(id as u64)))
+                .collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        // Json files that haven't been imported yet are imported on load
+        for guild_id in self.json.guilds()? {
+            if !guilds.contains(&guild_id) {
+                guilds.push(guild_id);
+            }
+        }
+        guilds.sort();
+        Ok(guilds)
+    }
+
+    fn append_journal(&self, guild_id: GuildId, entry: &JournalEntry) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO journal (guild_id, entry) VALUES (?1, ?2)",
+            params![guild_id.0 as i64, serde_json::to_string(entry)?],
+        )?;
+        Ok(())
+    }
+
+    fn load_journal(&self, guild_id: GuildId) -> Result<Vec<JournalEntry>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT entry FROM journal WHERE guild_id = ?1 ORDER BY id"
+        )?;
+        let rows = statement.query_map(params![guild_id.0 as i64], |row| row.get::<_, String>(0))?;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(serde_json::from_str(&row?)?);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const GUILD: GuildId = GuildId(1);
+
+    /// A state with theme ideas, a team and a vote
+    fn state() -> PersistentState {
+        Config::init_default();
+        PersistentState::parse(include_str!("../tests/fixtures/state_v10.json"), GUILD).unwrap()
+    }
+
+    /// An in memory database, with json files in a temporary directory of its own
+    fn open() -> (SqliteStorage, tempfile::TempDir) {
+        let directory = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::open(":memory:", JsonStorage::new(directory.path())).unwrap();
+        (storage, directory)
+    }
+
+    fn stored_json(storage: &SqliteStorage) -> Value {
+        let json: String = storage.connection.lock().unwrap().query_row(
+            "SELECT state FROM guilds WHERE guild_id = ?1",
+            params![GUILD.0 as i64],
+            |row| row.get(0),
+        ).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn voters(state: &PersistentState) -> Vec<Vec<UserId>> {
+        state.vote.as_ref().unwrap().candidates.iter()
+            .map(|c| {
+                let mut voters = c.voters.iter().cloned().collect::<Vec<_>>();
+                voters.sort();
+                voters
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settings_are_stored_without_the_rest() {
+        let (storage, _directory) = open();
+        let mut state = state();
+        storage.save(&state, Change::All).unwrap();
+        state.max_theme_ideas = 5;
+        storage.save(&state, Change::Settings).unwrap();
+
+        let json = stored_json(&storage);
+        assert_eq!(json["max_theme_ideas"], 5);
+        for field in SEPARATELY_STORED {
+            assert!(json.get(field).is_none(), "{} is in the settings", field);
+        }
+        let loaded = storage.load(GUILD).unwrap().unwrap().state;
+        assert_eq!(loaded.max_theme_ideas, 5);
+        assert_eq!(loaded.theme_ideas, state.theme_ideas);
+        assert_eq!(loaded.channel_creators.len(), 1);
+    }
+
+    #[test]
+    fn votes_and_ballots_are_kept() {
+        let (storage, _directory) = open();
+        let mut state = state();
+        storage.save(&state, Change::All).unwrap();
+        let loaded = storage.load(GUILD).unwrap().unwrap().state;
+        assert_eq!(voters(&loaded), voters(&state));
+        assert_eq!(loaded.vote.as_ref().unwrap().later_rounds, vec!(3600));
+
+        let candidates = &mut state.vote.as_mut().unwrap().candidates;
+        candidates[0].voters.remove(&UserId(10));
+        candidates[1].voters.insert(UserId(10));
+        candidates[1].voters.insert(UserId(11));
+        storage.save(&state, Change::Ballot(UserId(10))).unwrap();
+        let loaded = storage.load(GUILD).unwrap().unwrap().state;
+        // Only the ballot of the user that changed is saved
+        assert_eq!(voters(&loaded), vec!(vec!(), vec!(UserId(10))));
+
+        state.vote = None;
+        storage.save(&state, Change::Vote).unwrap();
+        assert!(storage.load(GUILD).unwrap().unwrap().state.vote.is_none());
+    }
+
+    #[test]
+    fn votes_are_moved_out_of_the_json() {
+        let (storage, _directory) = open();
+        let state = state();
+        let old_json = serde_json::to_string(&state).unwrap();
+        {
+            let connection = storage.connection.lock().unwrap();
+            connection.execute(
+                "INSERT INTO guilds (guild_id, state) VALUES (?1, ?2)",
+                params![GUILD.0 as i64, old_json],
+            ).unwrap();
+            move_votes(&connection).unwrap();
+        }
+
+        assert!(stored_json(&storage).get("vote").is_none());
+        let loaded = storage.load(GUILD).unwrap().unwrap().state;
+        assert_eq!(voters(&loaded), voters(&state));
+        assert_eq!(loaded.vote.unwrap().round, 2);
+    }
+
+    #[test]
+    fn ballots_dont_rotate_the_backups() {
+        let directory = tempfile::tempdir().unwrap();
+        let json = JsonStorage::new(directory.path());
+        let mut state = state();
+        state.max_theme_ideas = 1;
+        json.save(&state, Change::All).unwrap();
+        state.max_theme_ideas = 2;
+        json.save(&state, Change::Settings).unwrap();
+        for voter in 20..30 {
+            state.vote.as_mut().unwrap().candidates[0].voters.insert(UserId(voter));
+            json.save(&state, Change::Ballot(UserId(voter))).unwrap();
+        }
+
+        let backup = JsonStorage::read(&json.backup_path(GUILD, 1), GUILD).unwrap();
+        assert_eq!(backup.max_theme_ideas, 1);
+        assert!(!json.backup_path(GUILD, 2).exists());
+        assert_eq!(voters(&json.load(GUILD).unwrap().unwrap().state)[0].len(), 11);
+    }
+
+    #[test]
+    fn inspecting_doesnt_import() {
+        let (storage, directory) = open();
+        let state = state();
+        let json = JsonStorage::new(directory.path());
+        json.save(&state, Change::All).unwrap();
+        let path = json.path(GUILD);
+
+        let inspected = storage.inspect(GUILD).unwrap().unwrap();
+        assert_eq!(inspected.unimported, Some(path.clone()));
+        assert_eq!(inspected.state.theme_ideas, state.theme_ideas);
+        assert!(path.exists());
+        assert!(storage.read(GUILD).unwrap().is_none());
+
+        storage.load(GUILD).unwrap().unwrap();
+        assert!(!path.exists());
+        assert!(path.with_extension("json.imported").exists());
+        assert_eq!(storage.inspect(GUILD).unwrap().unwrap().unimported, None);
+    }
+}
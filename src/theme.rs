@@ -1,241 +1,659 @@
+use std::fmt::Display;
+
 use anyhow::Context;
-use rand::seq::{IteratorRandom, SliceRandom};
-use twilight::{
-    http::Client as HttpClient,
-    model::{
-        channel::Message,
-        id::{ChannelId, GuildId, UserId},
-        user::User,
-    },
+use async_trait::async_trait;
+use serde_derive::{Serialize, Deserialize};
+use tracing::error;
+use twilight::model::{
+    channel::Message,
+    id::{ChannelId, GuildId, UserId},
+    user::User,
 };
 
-use crate::role::has_role;
-use crate::roles::ORGANIZER;
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::config::Config;
+use crate::discord::DiscordApi;
+use crate::locale::{language, Language, Msg};
+use crate::log_channel::{log_action, Outcome};
+use crate::permission::Capability;
 use crate::state::PersistentState;
-use crate::utils::{Result, send_message};
+use crate::storage::Change;
+use crate::theme_template::{generate_theme, Category};
+use crate::utils::{Result, send_embeds, send_message, send_plain};
+
+/// A theme idea and the kind of word it is, if the submitter or an organizer said
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThemeIdea {
+    pub text: String,
+    pub category: Option<Category>,
+}
+
+impl Display for ThemeIdea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.category {
+            Some(category) => write!(f, "{} ({})", self.text, category.name()),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
 
 enum SubmissionResult {
-    Done,
-    AlreadySubmitted{previous_submission: String},
+    Done { count: usize },
+    TooMany,
 }
 
 impl PersistentState {
+    /// The text of the theme ideas of all users, in no particular order
+    pub fn all_theme_ideas(&self) -> impl Iterator<Item = &String> {
+        self.theme_ideas.values().flatten().map(|idea| &idea.text)
+    }
+
     /**
-      Tries to add a theme submission by the user. Replaces the previous theme
-      if the user had one previously. If file saving fails, returns Err
+      The theme ideas of all users in the order of the users, so that a seeded
+      rng picks the same ideas every time
+    */
+    pub fn sorted_theme_ideas(&self) -> Vec<&ThemeIdea> {
+        let mut ideas = self.theme_ideas.iter().collect::<Vec<_>>();
+        ideas.sort_by_key(|(user_id, _)| **user_id);
+        ideas.into_iter().flat_map(|(_, ideas)| ideas).collect()
+    }
+
+    /**
+      Tries to add a theme submission by the user, unless they already have
+      as many ideas as they are allowed. If file saving fails, returns Err
     */
     fn try_add_theme(
         &mut self,
         user: UserId,
         idea: &str
     ) -> Result<SubmissionResult> {
-        if self.theme_ideas.contains_key(&user) {
-            let previous_submission = self.theme_ideas.get(&user).unwrap().to_string();
-            self.theme_ideas.insert(user, idea.into());
-            self.save().context("Failed to write current themes")?;
-            Ok(SubmissionResult::AlreadySubmitted{previous_submission})
+        let count = self.theme_ideas.get(&user).map_or(0, Vec::len);
+        if count >= self.max_theme_ideas {
+            return Ok(SubmissionResult::TooMany)
+        }
+        self.theme_ideas.entry(user).or_insert_with(Vec::new).push(ThemeIdea {
+            text: idea.into(),
+            category: None,
+        });
+        self.save_change(Change::ThemeIdeas(user)).context("Failed to write current themes")?;
+        self.record(user, "Submitted a theme idea", None)?;
+        Ok(SubmissionResult::Done { count: count + 1 })
+    }
+
+    /**
+      Replaces the idea at the index in the user's list, returning the old one
+      if there was one. The new idea has no category until it is given one
+    */
+    fn replace_theme(&mut self, user: UserId, index: usize, idea: &str) -> Result<Option<String>> {
+        let previous = match self.theme_ideas.get_mut(&user).and_then(|ideas| ideas.get_mut(index)) {
+            Some(previous) => std::mem::replace(previous, ThemeIdea {
+                text: idea.into(),
+                category: None,
+            }).text,
+            None => return Ok(None),
+        };
+        self.save_change(Change::ThemeIdeas(user)).context("Failed to write current themes")?;
+        self.record(user, "Replaced a theme idea", None)?;
+        Ok(Some(previous))
+    }
+
+    /// Removes the idea at the index in the user's list, returning it if there was one
+    fn delete_theme(&mut self, user: UserId, index: usize) -> Result<Option<String>> {
+        let ideas = match self.theme_ideas.get_mut(&user) {
+            Some(ideas) if index < ideas.len() => ideas,
+            _ => return Ok(None),
+        };
+        let deleted = ideas.remove(index).text;
+        if ideas.is_empty() {
+            self.theme_ideas.remove(&user);
         }
-        else {
-            self.theme_ideas.insert(user, idea.into());
-            self.save().context("Failed to write current themes")?;
-            Ok(SubmissionResult::Done)
+        self.save_change(Change::ThemeIdeas(user)).context("Failed to write current themes")?;
+        self.record(user, "Deleted a theme idea", None)?;
+        Ok(Some(deleted))
+    }
+
+    /// Sets the category of the idea at the index in the user's list, returning the idea if there was one
+    fn set_theme_category(
+        &mut self,
+        user: UserId,
+        index: usize,
+        category: Option<Category>,
+    ) -> Result<Option<String>> {
+        let idea = match self.theme_ideas.get_mut(&user).and_then(|ideas| ideas.get_mut(index)) {
+            Some(idea) => {
+                idea.category = category;
+                idea.text.clone()
+            }
+            None => return Ok(None),
+        };
+        self.save_change(Change::ThemeIdeas(user)).context("Failed to write current themes")?;
+        self.record(user, format!("Set the category of a theme idea to {}", category_name(category)), None)?;
+        Ok(Some(idea))
+    }
+
+    /// Sets the category of every idea with the text, whoever submitted it, returning how many there were
+    fn categorize_theme_ideas(
+        &mut self,
+        actor: UserId,
+        text: &str,
+        category: Option<Category>,
+    ) -> Result<usize> {
+        let mut users = Vec::new();
+        for (user_id, ideas) in self.theme_ideas.iter_mut() {
+            for idea in ideas.iter_mut().filter(|idea| idea.text.eq_ignore_ascii_case(text)) {
+                idea.category = category;
+                users.push(*user_id);
+            }
+        }
+        for user_id in &users {
+            self.save_change(Change::ThemeIdeas(*user_id)).context("Failed to write current themes")?;
         }
+        if !users.is_empty() {
+            self.record(actor, format!(
+                "Set the category of {} theme ideas \"{}\" to {}",
+                users.len(), text, category_name(category)
+            ), None)?;
+        }
+        Ok(users.len())
+    }
+
+    /// Sets how many ideas each user can have. Users that have more keep them
+    fn set_max_theme_ideas(&mut self, actor: UserId, max_ideas: usize) -> Result<()> {
+        self.max_theme_ideas = max_ideas;
+        self.save_change(Change::Settings)?;
+        self.record(actor, format!("Set the theme idea limit to {}", max_ideas), None)
     }
 }
 
-pub async fn handle_add_theme(
-    http: &HttpClient,
+/**
+  Handles a PM meant for the guild. Commands manage the user's theme ideas,
+  anything else is submitted as a new idea
+*/
+pub async fn handle_theme_pm(
+    http: &dyn DiscordApi,
     msg: &Message,
+    guild_id: GuildId,
+    content: &str,
 ) -> Result<()> {
-    // Check if the message is a single word
-    if msg.content.split_ascii_whitespace().count() != 1 {
-        http.create_message(msg.channel_id)
-            .content("Themes ideas should only be a single word.")
-            .await?;
+    let prefix = &Config::get().prefix;
+    if !content.starts_with(prefix.as_str()) {
+        return handle_add_theme(http, msg, guild_id, content).await
     }
-    else {
-        let had_old_theme = PersistentState::instance().lock()
-            .unwrap()
-            .try_add_theme(msg.author.id, &msg.content)
-            .context("Failed to save theme")?;
-
-        match had_old_theme {
-            SubmissionResult::Done => {
-                // Check if the message is a PM
-                http.create_message(msg.channel_id)
-                    .content(format!(
-                        "Theme idea \"{}\" registered, thanks!",
-                        &msg.content
-                    ))
-                    .await?;
+
+    let language = language(guild_id, msg.author.id).await;
+    let words = content[prefix.len()..].split_ascii_whitespace().collect::<Vec<_>>();
+    let state = PersistentState::for_guild(guild_id).await?;
+    let mut state = state.lock().await;
+    let count = state.theme_ideas.get(&msg.author.id).map_or(0, Vec::len);
+    let limit = state.max_theme_ideas;
+    let reply = match words.as_slice() {
+        [command] if command.eq_ignore_ascii_case("ideas") => {
+            match state.theme_ideas.get(&msg.author.id) {
+                Some(ideas) => {
+                    let ideas = ideas.iter()
+                        .enumerate()
+                        .map(|(i, idea)| format!("{}. {}", i + 1, idea))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    Msg::ThemeIdeas { ideas: &ideas, count, limit }.text(language)
+                }
+                None => Msg::NoThemeIdeas { limit }.text(language),
             }
-            SubmissionResult::AlreadySubmitted{previous_submission} => {
-                // Check if the message is a PM
-                http.create_message(msg.channel_id)
-                    .content(format!(
-                        "You can only submit one idea.\n\
-                        Theme idea \"{}\" registered, \
-                        replacing your previous submission \"{}\".",
-                        &msg.content, previous_submission
-                    ))
-                    .await?;
+        }
+        [command, number, idea @ ..] if command.eq_ignore_ascii_case("replace") && !idea.is_empty() => {
+            match state.theme_policy.check(&idea.join(" ")) {
+                Ok(idea) => {
+                    let replaced = match idea_index(number) {
+                        Some(index) => state.replace_theme(msg.author.id, index, &idea)
+                            .context("Failed to save theme")?,
+                        None => None,
+                    };
+                    match replaced {
+                        Some(previous) =>
+                            Msg::ThemeReplaced { idea: &idea, previous: &previous }.text(language),
+                        None => Msg::NoSuchIdea { number, count }.text(language),
+                    }
+                }
+                Err(rejection) => rejection.message(language),
+            }
+        }
+        [command, number, category] if command.eq_ignore_ascii_case("category") => {
+            match parse_category(category) {
+                Some(category) => {
+                    let idea = match idea_index(number) {
+                        Some(index) => state.set_theme_category(msg.author.id, index, category)
+                            .context("Failed to save theme")?,
+                        None => None,
+                    };
+                    match idea {
+                        Some(idea) => Msg::IdeaCategorySet {
+                            idea: &idea,
+                            category: category_name(category),
+                        }.text(language),
+                        None => Msg::NoSuchIdea { number, count }.text(language),
+                    }
+                }
+                None => Msg::InvalidCategory { category }.text(language),
             }
         }
+        [command, number] if command.eq_ignore_ascii_case("delete") => {
+            let deleted = match idea_index(number) {
+                Some(index) => state.delete_theme(msg.author.id, index)
+                    .context("Failed to save theme")?,
+                None => None,
+            };
+            match deleted {
+                Some(idea) => Msg::ThemeDeleted { idea: &idea }.text(language),
+                None => Msg::NoSuchIdea { number, count }.text(language),
+            }
+        }
+        _ => Msg::ThemePmHelp { limit }.text(language),
+    };
+    drop(state);
+    send_plain(http, msg.channel_id, &reply).await
+}
+
+/// A category as it is written in commands, where none takes the category away
+fn parse_category(name: &str) -> Option<Option<Category>> {
+    if name.eq_ignore_ascii_case("none") {
+        Some(None)
+    }
+    else {
+        Category::from_name(name).map(Some)
+    }
+}
+
+/// A category as it is shown to users
+fn category_name(category: Option<Category>) -> &'static str {
+    category.map_or("none", Category::name)
+}
+
+/// The index of an idea from the number shown in the list, which starts at 1
+fn idea_index(number: &str) -> Option<usize> {
+    match number.parse::<usize>() {
+        Ok(number) if number > 0 => Some(number - 1),
+        _ => None,
     }
-    Ok(())
+}
+
+async fn handle_add_theme(
+    http: &dyn DiscordApi,
+    msg: &Message,
+    guild_id: GuildId,
+    idea: &str,
+) -> Result<()> {
+    let language = language(guild_id, msg.author.id).await;
+    let reply = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        match state.theme_policy.check(idea) {
+            Ok(idea) => {
+                let result = state.try_add_theme(msg.author.id, &idea)
+                    .context("Failed to save theme")?;
+                let limit = state.max_theme_ideas;
+                match result {
+                    SubmissionResult::Done { count } =>
+                        Msg::ThemeRegistered { idea: &idea, count, limit }.text(language),
+                    SubmissionResult::TooMany => Msg::TooManyIdeas { limit }.text(language),
+                }
+            }
+            Err(rejection) => rejection.message(language),
+        }
+    };
+    send_plain(http, msg.channel_id, &reply).await
 }
 
 pub async fn handle_generate_theme(
     original_channel: ChannelId,
-    guild: GuildId,
+    guild_id: GuildId,
     author: &User,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
-        let theme = do_theme_generation();
-        let send_result = send_message(&http, original_channel, author.id,
-            &theme
-        )
-        .await
-        .context("Failed to send theme");
-        match send_result {
-            Ok(_) => {},
-            Err(e) => {
-                send_message(&http, original_channel, author.id,
-                    "Failed to send theme. Has someone been naughty? 🤔"
-                ).await?;
-                println!("Failed to send theme message {:?}", e);
-                println!("Message should have been: {:?}", theme);
-            }
+    let language = language(guild_id, author.id).await;
+    let theme = do_theme_generation(guild_id, language).await?;
+    let send_result = send_message(http, original_channel, author.id,
+        &theme
+    )
+    .await
+    .context("Failed to send theme");
+    match send_result {
+        Ok(_) => {
+            log_action(http, guild_id, author.id, "Theme generated",
+                Outcome::Done(theme.clone())
+            ).await;
+        },
+        Err(e) => {
+            // Logged first, since telling the user can fail in the same way
+            error!(guild = %guild_id, user = %author.id, error = ?e, theme = %theme,
+                "Failed to send theme message");
+            log_action(http, guild_id, author.id, "Theme generation failed",
+                Outcome::Failed(format!("{}\n{:?}", theme, e))
+            ).await;
+            send_message(http, original_channel, author.id,
+                Msg::ThemeSendFailed.text(language)
+            ).await?;
         }
     }
-    else {
-        send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to generate themes.", ORGANIZER)
-        ).await?;
-        println!("Tried to generate theme without required role \"{}\"", ORGANIZER);
-    }
 
     Ok(())
 }
 
 pub async fn handle_show_all_themes(
     original_channel: ChannelId,
-    guild: GuildId,
+    guild_id: GuildId,
     author: &User,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
-        let all_ideas = format_all_ideas();
-        let send_result = send_message(&http, original_channel, author.id,
-            format!("The theme ideas submitted are ```{}```", all_ideas)
-        )
-        .await
-        .context("Failed to send all themes");
-
-        match send_result {
-            Ok(_) => {},
-            Err(e) => {
-                send_message(&http, original_channel, author.id,
-                    "Failed to send all themes. I don't know how this happened."
-                )
-                .await?;
-                println!("Tried to send all themes but something went wrong {:?}", e);
-            }
-        }
+    let language = language(guild_id, author.id).await;
+    let all_ideas = format_all_ideas(guild_id).await?;
+    if all_ideas.is_empty() {
+        return send_message(http, original_channel, author.id,
+            Msg::NoIdeasSubmitted.text(language)
+        ).await
     }
-    else {
-        send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to see all the theme ideas.", ORGANIZER)
-        ).await?;
-        println!("Tried to see all theme ideas without required role \"{}\"", ORGANIZER);
+    let send_result = send_embeds(http, original_channel, author.id,
+        &Msg::AllThemesTitle.text(language),
+        &format!("```{}```", all_ideas)
+    )
+    .await
+    .context("Failed to send all themes");
+
+    match send_result {
+        Ok(_) => {},
+        Err(e) => {
+            error!(guild = %guild_id, user = %author.id, error = ?e,
+                "Failed to send all themes");
+            send_message(http, original_channel, author.id,
+                Msg::AllThemesSendFailed.text(language)
+            )
+            .await?;
+        }
     }
     Ok(())
 }
 
 pub async fn handle_show_theme_count(
     original_channel: ChannelId,
-    guild: GuildId,
+    guild_id: GuildId,
     author: &User,
-    http: HttpClient
+    http: &dyn DiscordApi
 ) -> Result<()> {
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
-        let theme_count = PersistentState::instance().lock().unwrap().theme_ideas.len();
-        let send_result = send_message(&http, original_channel, author.id,
-            format!("There are **{}** submitted theme ideas.", theme_count)
-        )
-        .await
-        .context("Failed to send theme idea count");
-
-        match send_result {
-            Ok(_) => {},
-            Err(e) => {
-                send_message(&http, original_channel, author.id,
-                    "Failed to send theme idea count. I don't know how this happened."
-                )
-                .await?;
-                println!("Tried to send theme idea count but something went wrong {:?}", e);
-            }
+    let language = language(guild_id, author.id).await;
+    let theme_count = PersistentState::for_guild(guild_id).await?.lock().await.all_theme_ideas().count();
+    let send_result = send_message(http, original_channel, author.id,
+        Msg::ThemeCount { count: theme_count }.text(language)
+    )
+    .await
+    .context("Failed to send theme idea count");
+
+    match send_result {
+        Ok(_) => {},
+        Err(e) => {
+            error!(guild = %guild_id, user = %author.id, error = ?e,
+                "Failed to send theme idea count");
+            send_message(http, original_channel, author.id,
+                Msg::ThemeCountSendFailed.text(language)
+            )
+            .await?;
         }
     }
-    else {
-        send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to see the theme idea count.", ORGANIZER)
-        ).await?;
-        println!("Tried to see theme idea count without required role \"{}\"", ORGANIZER);
-    }
     Ok(())
 }
 
-fn do_theme_generation() -> String {
-    let mut rng = rand::thread_rng();
-    let ref theme_ideas = PersistentState::instance().lock().unwrap().theme_ideas;
-    let mut selected = theme_ideas
-        .iter()
-        .map(|(_, idea)| idea)
-        .choose_multiple(&mut rng, 2);
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(GenerateTheme);
+    registry.register(ShowAllThemes);
+    registry.register(ShowThemeCount);
+    registry.register(SetThemeLimit);
+    registry.register(Categorize);
+}
 
-    // Per documetation: The order of chose_multiple is not random. To achieve
-    // that, shuffle the result
-    selected.shuffle(&mut rng);
+struct GenerateTheme;
 
-    if selected.len() != 2 {
-        "Not enough ideas have been submitted yet.".to_string()
+#[async_trait]
+impl Command for GenerateTheme {
+    fn name(&self) -> &'static str { "generatetheme" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeGenerateTheme }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ThemeGenerate)
     }
-    else {
-        format!("The theme is: {} {}", selected[0], selected[1])
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_generate_theme(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct ShowAllThemes;
+
+#[async_trait]
+impl Command for ShowAllThemes {
+    fn name(&self) -> &'static str { "showallthemes" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeShowAllThemes }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ThemeView)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_show_all_themes(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct ShowThemeCount;
+
+#[async_trait]
+impl Command for ShowThemeCount {
+    fn name(&self) -> &'static str { "showthemecount" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeShowThemeCount }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ThemeView)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_show_theme_count(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
     }
 }
 
-fn format_all_ideas() -> String {
-    let ref theme_ideas = PersistentState::instance().lock().unwrap().theme_ideas;
+pub async fn handle_set_theme_limit<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let language = language(guild_id, author.id).await;
+    let msg = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        match rest_command {
+            [limit] => match limit.parse::<usize>() {
+                Ok(limit) if limit > 0 => {
+                    state.set_max_theme_ideas(author.id, limit)?;
+                    Msg::ThemeLimitSet { limit }
+                }
+                _ => Msg::InvalidThemeLimit { limit },
+            },
+            _ => Msg::ThemeLimitSet { limit: state.max_theme_ideas },
+        }
+    };
+    send_message(http, original_channel, author.id, msg.text(language)).await
+}
 
-    let all_ideas = theme_ideas
-        .iter()
-        .map(|(_, idea)| idea.to_string())
+struct SetThemeLimit;
+
+#[async_trait]
+impl Command for SetThemeLimit {
+    fn name(&self) -> &'static str { "themelimit" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageThemeLimit) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeThemeLimit }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_set_theme_limit(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+pub async fn handle_categorize<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let language = language(guild_id, author.id).await;
+    let message = match rest_command {
+        [category, idea @ ..] if !idea.is_empty() => match parse_category(category) {
+            Some(category) => {
+                let idea = idea.join(" ");
+                let count = PersistentState::for_guild(guild_id).await?.lock().await
+                    .categorize_theme_ideas(author.id, &idea, category)?;
+                if count == 0 {
+                    Msg::NoMatchingIdeas { idea: &idea }.text(language)
+                }
+                else {
+                    Msg::IdeasCategorized {
+                        idea: &idea,
+                        category: category_name(category),
+                        count,
+                    }.text(language)
+                }
+            }
+            None => Msg::InvalidCategory { category }.text(language),
+        },
+        _ => Msg::ProperUsage {
+            command: Categorize.name(),
+            usage: &Msg::UsageCategorize.text(language),
+        }.text(language),
+    };
+    send_message(http, original_channel, author.id, message).await
+}
+
+struct Categorize;
+
+#[async_trait]
+impl Command for Categorize {
+    fn name(&self) -> &'static str { "categorize" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageCategorize) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeCategorize }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ThemeGenerate)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_categorize(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+async fn do_theme_generation(guild_id: GuildId, language: Language) -> Result<String> {
+    let state = PersistentState::for_guild(guild_id).await?;
+    let state = state.lock().await;
+    // Created after the lock since the thread rng can't be held across awaits
+    let mut rng = rand::thread_rng();
+    Ok(match generate_theme(&state.sorted_theme_ideas(), &state.theme_templates, &mut rng) {
+        Some(theme) => Msg::Theme { theme: &theme }.text(language),
+        None => Msg::NotEnoughIdeas.text(language),
+    })
+}
+
+async fn format_all_ideas(guild_id: GuildId) -> Result<String> {
+    let state = PersistentState::for_guild(guild_id).await?;
+    let state = state.lock().await;
+
+    let all_ideas = state.all_theme_ideas()
+        .map(|idea| idea.to_string())
         .collect::<Vec<String>>()
         .join(", ");
 
-    all_ideas
+    Ok(all_ideas)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::discord::fake::{test_guild, test_message, test_user, Call, FakeDiscord};
+    use crate::utils::MESSAGE_LIMIT;
+
+    const USER: UserId = UserId(10);
+    const COMMANDS: ChannelId = ChannelId(20);
+    const LOG: ChannelId = ChannelId(30);
+
+    #[tokio::test]
+    async fn unsent_themes_are_logged_as_failures() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        PersistentState::for_guild(guild).await.unwrap().lock().await
+            .set_log_channel(USER, LOG).unwrap();
+        // Only the theme itself fails to send
+        let failed = AtomicBool::new(false);
+        discord.fail_on(move |call| match call {
+            Call::CreateMessage(channel_id, _) if *channel_id == COMMANDS =>
+                !failed.swap(true, Ordering::SeqCst),
+            _ => false,
+        });
+
+        handle_generate_theme(COMMANDS, guild, &test_user(USER), &discord).await.unwrap();
+
+        let mention = format!("<@{}> ", USER);
+        let replies = discord.messages(COMMANDS);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[1], format!("{}{}", mention, Msg::ThemeSendFailed.text(Language::En)));
+        let titles = discord.calls().into_iter()
+            .filter_map(|call| match call {
+                Call::CreateEmbed(channel_id, _, embed) if channel_id == LOG => embed.title,
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!("Theme generation failed".to_string()));
+    }
+
+    #[tokio::test]
+    async fn failures_to_tell_the_user_are_returned() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        discord.fail_on(|call| match call {
+            Call::CreateMessage(..) => true,
+            _ => false,
+        });
+
+        let result = handle_generate_theme(COMMANDS, guild, &test_user(USER), &discord).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn long_idea_lists_are_split() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        {
+            let state = PersistentState::for_guild(guild).await.unwrap();
+            let mut state = state.lock().await;
+            state.max_theme_ideas = 100;
+            let ideas = (0..100)
+                .map(|i| ThemeIdea { text: format!("{} {}", i, "idea ".repeat(10)), category: None })
+                .collect();
+            state.theme_ideas.insert(USER, ideas);
+        }
+        let msg = test_message(COMMANDS, USER, "!ideas");
+
+        handle_theme_pm(&discord, &msg, guild, "!ideas").await.unwrap();
+
+        let replies = discord.messages(COMMANDS);
+        assert!(replies.len() > 1);
+        assert!(replies.iter().all(|reply| reply.chars().count() <= MESSAGE_LIMIT));
+    }
+
+    #[tokio::test]
+    async fn no_ideas_are_shown_as_such() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+
+        handle_show_all_themes(COMMANDS, guild, &test_user(USER), &discord).await.unwrap();
+
+        assert_eq!(discord.messages(COMMANDS), vec!(
+            format!("<@{}> {}", USER, Msg::NoIdeasSubmitted.text(Language::En))
+        ));
+    }
 }
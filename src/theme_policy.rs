@@ -0,0 +1,455 @@
+use std::fmt::Display;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use twilight::model::{
+    id::{ChannelId, GuildId, UserId},
+    user::User,
+};
+
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::discord::DiscordApi;
+use crate::locale::{language, Language, Msg};
+use crate::permission::Capability;
+use crate::state::PersistentState;
+use crate::storage::Change;
+use crate::utils::{Result, send_message};
+
+lazy_static! {
+    static ref LINK_REGEX: Regex = Regex::new(
+        r"(?i)(https?://|www\.|discord\.gg/|\b[a-z0-9-]+\.(com|net|org|io|gg|se|xyz|me|tv)\b)"
+    ).unwrap();
+    static ref MENTION_REGEX: Regex = Regex::new(r"<(@[!&]?|#)\d+>|@everyone|@here").unwrap();
+}
+
+/// Characters that aren't letters but may still be part of a theme idea
+const ALLOWED_PUNCTUATION: &'static str = "-'’&!?.,:";
+
+/// Writing systems that organizers can limit theme ideas to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Cjk,
+    Hangul,
+}
+
+impl Script {
+    pub const ALL: &'static [Script] = &[
+        Script::Latin,
+        Script::Greek,
+        Script::Cyrillic,
+        Script::Hebrew,
+        Script::Arabic,
+        Script::Cjk,
+        Script::Hangul,
+    ];
+
+    /// Name used in the config and in commands
+    pub fn name(self) -> &'static str {
+        match self {
+            Script::Latin => "latin",
+            Script::Greek => "greek",
+            Script::Cyrillic => "cyrillic",
+            Script::Hebrew => "hebrew",
+            Script::Arabic => "arabic",
+            Script::Cjk => "cjk",
+            Script::Hangul => "hangul",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Script> {
+        Script::ALL.iter().cloned().find(|script| script.name() == name.to_lowercase())
+    }
+
+    /**
+      Whether the letter belongs to the script. Only the blocks that are in
+      common use are covered, anything else counts as no script at all
+    */
+    fn contains(self, c: char) -> bool {
+        let ranges: &[(char, char)] = match self {
+            Script::Latin => &[
+                ('a', 'z'), ('A', 'Z'), ('\u{C0}', '\u{24F}'), ('\u{1E00}', '\u{1EFF}'),
+            ],
+            Script::Greek => &[('\u{370}', '\u{3FF}'), ('\u{1F00}', '\u{1FFF}')],
+            Script::Cyrillic => &[('\u{400}', '\u{52F}')],
+            Script::Hebrew => &[('\u{590}', '\u{5FF}')],
+            Script::Arabic => &[('\u{600}', '\u{6FF}'), ('\u{750}', '\u{77F}')],
+            Script::Cjk => &[
+                ('\u{3040}', '\u{30FF}'), ('\u{3400}', '\u{4DBF}'), ('\u{4E00}', '\u{9FFF}'),
+            ],
+            Script::Hangul => &[('\u{1100}', '\u{11FF}'), ('\u{AC00}', '\u{D7AF}')],
+        };
+        ranges.iter().any(|(first, last)| *first <= c && c <= *last)
+    }
+}
+
+/**
+  What a theme idea has to look like on a server. Servers start out with the
+  policy under `[themes.policy]` in the config
+*/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ThemePolicy {
+    pub max_words: usize,
+    /// Longest idea in characters, counting the spaces between words
+    pub max_length: usize,
+    /// Scripts the letters of ideas must be in. Empty allows any
+    pub scripts: Vec<Script>,
+    pub allow_links: bool,
+    pub allow_mentions: bool,
+}
+
+impl Default for ThemePolicy {
+    fn default() -> Self {
+        Self {
+            max_words: 3,
+            max_length: 32,
+            scripts: Vec::new(),
+            allow_links: false,
+            allow_mentions: false,
+        }
+    }
+}
+
+/// Why a theme idea was not accepted
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rejection {
+    Empty,
+    TooManyWords { max: usize },
+    TooLong { max: usize },
+    /// Characters like zero width spaces and direction marks, which can make
+    /// ideas look different from what they are
+    Invisible,
+    Script { character: char },
+    Link,
+    Mention,
+}
+
+impl Rejection {
+    /// The reason in the language, to send back to the user
+    pub fn message(&self, language: Language) -> String {
+        match self {
+            Self::Empty => Msg::IdeaEmpty.text(language),
+            Self::TooManyWords { max } => Msg::IdeaTooManyWords { max: *max }.text(language),
+            Self::TooLong { max } => Msg::IdeaTooLong { max: *max }.text(language),
+            Self::Invisible => Msg::IdeaInvisible.text(language),
+            Self::Script { character } =>
+                Msg::IdeaScript { character: &character.to_string() }.text(language),
+            Self::Link => Msg::IdeaLink.text(language),
+            Self::Mention => Msg::IdeaMention.text(language),
+        }
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message(Language::En))
+    }
+}
+
+impl ThemePolicy {
+    /**
+      Checks the idea against the policy. Any kind of whitespace separates
+      words, so an accepted idea is returned with single spaces between them
+    */
+    pub fn check(&self, idea: &str) -> std::result::Result<String, Rejection> {
+        if idea.chars().any(|c| (c.is_control() && !c.is_whitespace()) || is_invisible(c)) {
+            return Err(Rejection::Invisible)
+        }
+        let words = idea.split(char::is_whitespace)
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        if words.is_empty() {
+            return Err(Rejection::Empty)
+        }
+        let idea = words.join(" ");
+
+        if !self.allow_mentions && MENTION_REGEX.is_match(&idea) {
+            return Err(Rejection::Mention)
+        }
+        if !self.allow_links && LINK_REGEX.is_match(&idea) {
+            return Err(Rejection::Link)
+        }
+        if words.len() > self.max_words {
+            return Err(Rejection::TooManyWords { max: self.max_words })
+        }
+        if idea.chars().count() > self.max_length {
+            return Err(Rejection::TooLong { max: self.max_length })
+        }
+        if !self.scripts.is_empty() {
+            let outside = idea.chars().find(|c| {
+                !c.is_whitespace()
+                    && !c.is_numeric()
+                    && !ALLOWED_PUNCTUATION.contains(*c)
+                    && !self.scripts.iter().any(|script| script.contains(*c))
+            });
+            if let Some(character) = outside {
+                return Err(Rejection::Script { character })
+            }
+        }
+        Ok(idea)
+    }
+
+    /// The scripts as they are written in commands
+    fn scripts_text(&self) -> String {
+        if self.scripts.is_empty() {
+            "any".to_string()
+        }
+        else {
+            self.scripts.iter().map(|script| script.name()).collect::<Vec<_>>().join(",")
+        }
+    }
+
+    /// Changes a setting given as text, returning whether the value was valid
+    fn set(&mut self, setting: Setting, value: &str) -> bool {
+        match setting {
+            Setting::Words | Setting::Length => match value.parse::<usize>() {
+                Ok(number) if number > 0 => {
+                    if setting == Setting::Words {
+                        self.max_words = number;
+                    }
+                    else {
+                        self.max_length = number;
+                    }
+                    true
+                }
+                _ => false,
+            },
+            Setting::Scripts => {
+                if value == "any" {
+                    self.scripts = Vec::new();
+                    return true
+                }
+                let scripts = value.split(',').map(Script::from_name).collect::<Option<Vec<_>>>();
+                match scripts {
+                    Some(scripts) if !scripts.is_empty() => {
+                        self.scripts = scripts;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Setting::Links | Setting::Mentions => {
+                let allow = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return false,
+                };
+                if setting == Setting::Links {
+                    self.allow_links = allow;
+                }
+                else {
+                    self.allow_mentions = allow;
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Characters that take up no space, which `char::is_whitespace` doesn't cover
+fn is_invisible(c: char) -> bool {
+    match c {
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}'
+            | '\u{FEFF}' | '\u{AD}' => true,
+        _ => false,
+    }
+}
+
+/// The parts of the policy that can be changed with the themepolicy command
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Setting {
+    Words,
+    Length,
+    Scripts,
+    Links,
+    Mentions,
+}
+
+impl Setting {
+    const ALL: &'static [Setting] = &[
+        Setting::Words,
+        Setting::Length,
+        Setting::Scripts,
+        Setting::Links,
+        Setting::Mentions,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Setting::Words => "words",
+            Setting::Length => "length",
+            Setting::Scripts => "scripts",
+            Setting::Links => "links",
+            Setting::Mentions => "mentions",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Setting> {
+        Setting::ALL.iter().cloned().find(|setting| setting.name() == name.to_lowercase())
+    }
+}
+
+impl PersistentState {
+    fn set_theme_policy(
+        &mut self,
+        actor: UserId,
+        policy: ThemePolicy,
+        setting: Setting,
+    ) -> Result<()> {
+        let description = format!("Set the theme policy {} to {}", setting.name(), match setting {
+            Setting::Words => policy.max_words.to_string(),
+            Setting::Length => policy.max_length.to_string(),
+            Setting::Scripts => policy.scripts_text(),
+            Setting::Links => policy.allow_links.to_string(),
+            Setting::Mentions => policy.allow_mentions.to_string(),
+        });
+        self.theme_policy = policy;
+        self.save_change(Change::Settings)?;
+        self.record(actor, description, None)
+    }
+}
+
+pub async fn handle_theme_policy<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let language = language(guild_id, author.id).await;
+    let state = PersistentState::for_guild(guild_id).await?;
+    let mut state = state.lock().await;
+    let problem = match rest_command {
+        [] => None,
+        [setting, value] => match Setting::from_name(setting) {
+            Some(setting) => {
+                let mut policy = state.theme_policy.clone();
+                if policy.set(setting, &value.to_lowercase()) {
+                    state.set_theme_policy(author.id, policy, setting)?;
+                    None
+                }
+                else {
+                    Some(Msg::InvalidPolicyValue { setting: setting.name(), value }.text(language))
+                }
+            }
+            None => {
+                let settings = Setting::ALL.iter()
+                    .map(|setting| setting.name())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Some(Msg::UnknownPolicySetting { setting, settings: &settings }.text(language))
+            }
+        },
+        _ => Some(Msg::ProperUsage {
+            command: SetThemePolicy.name(),
+            usage: &Msg::UsageThemePolicy.text(language),
+        }.text(language)),
+    };
+    let policy = &state.theme_policy;
+    let message = problem.unwrap_or_else(|| Msg::ThemePolicyShown {
+        words: policy.max_words,
+        length: policy.max_length,
+        scripts: &policy.scripts_text(),
+        links: policy.allow_links,
+        mentions: policy.allow_mentions,
+    }.text(language));
+    drop(state);
+    send_message(http, original_channel, author.id, message).await
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(SetThemePolicy);
+}
+
+struct SetThemePolicy;
+
+#[async_trait]
+impl Command for SetThemePolicy {
+    fn name(&self) -> &'static str { "themepolicy" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageThemePolicy) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeThemePolicy }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_theme_policy(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latin_only() -> ThemePolicy {
+        ThemePolicy { scripts: vec!(Script::Latin), ..ThemePolicy::default() }
+    }
+
+    #[test]
+    fn whitespace_is_normalized() {
+        assert_eq!(ThemePolicy::default().check("  Space \t frogs\n"), Ok("Space frogs".to_string()));
+    }
+
+    #[test]
+    fn empty_and_invisible_ideas_are_rejected() {
+        let policy = ThemePolicy::default();
+        assert_eq!(policy.check(" \n "), Err(Rejection::Empty));
+        assert_eq!(policy.check("Space\u{200B}frogs"), Err(Rejection::Invisible));
+        assert_eq!(policy.check("Space\u{7}frogs"), Err(Rejection::Invisible));
+    }
+
+    #[test]
+    fn words_and_length_are_limited() {
+        let policy = ThemePolicy::default();
+        assert!(policy.check("one two three").is_ok());
+        assert_eq!(policy.check("one two three four"), Err(Rejection::TooManyWords { max: 3 }));
+        assert!(policy.check(&"a".repeat(32)).is_ok());
+        assert_eq!(policy.check(&"a".repeat(33)), Err(Rejection::TooLong { max: 32 }));
+    }
+
+    #[test]
+    fn links_and_mentions_are_rejected_unless_allowed() {
+        let policy = ThemePolicy::default();
+        for link in &["https://frogs", "www.frogs.se", "discord.gg/frogs", "frogs.com"] {
+            assert_eq!(policy.check(link), Err(Rejection::Link), "{}", link);
+        }
+        for mention in &["<@10>", "<@!10>", "<@&10>", "<#10>", "@everyone", "@here"] {
+            assert_eq!(policy.check(mention), Err(Rejection::Mention), "{}", mention);
+        }
+
+        let policy = ThemePolicy { allow_links: true, allow_mentions: true, ..ThemePolicy::default() };
+        assert!(policy.check("frogs.com").is_ok());
+        assert!(policy.check("<@10>").is_ok());
+    }
+
+    #[test]
+    fn letters_must_be_in_the_scripts() {
+        let policy = latin_only();
+        assert!(policy.check("Grodor på Mars").is_ok());
+        assert!(policy.check("Level 2: frogs!").is_ok());
+        assert_eq!(policy.check("Космос"), Err(Rejection::Script { character: 'К' }));
+        assert!(ThemePolicy::default().check("Космос").is_ok());
+    }
+
+    #[test]
+    fn settings_are_parsed() {
+        let mut policy = ThemePolicy::default();
+        assert!(policy.set(Setting::Words, "5"));
+        assert_eq!(policy.max_words, 5);
+        assert!(!policy.set(Setting::Length, "0"));
+        assert!(policy.set(Setting::Scripts, "latin,greek"));
+        assert_eq!(policy.scripts, vec!(Script::Latin, Script::Greek));
+        assert!(!policy.set(Setting::Scripts, "latin,klingon"));
+        assert!(policy.set(Setting::Scripts, "any"));
+        assert!(policy.scripts.is_empty());
+        assert!(!policy.set(Setting::Links, "yes"));
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use async_trait::async_trait;
+use rand::Rng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use serde_derive::{Serialize, Deserialize};
+use twilight::model::{
+    id::{ChannelId, GuildId, UserId},
+    user::User,
+};
+
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::discord::DiscordApi;
+use crate::locale::{language, Language, Msg};
+use crate::permission::Capability;
+use crate::state::PersistentState;
+use crate::storage::Change;
+use crate::theme::ThemeIdea;
+use crate::utils::{Result, send_message};
+
+/// Slot of a template that any idea can fill
+const ANY_SLOT: &'static str = "any";
+
+/// Kinds of words that theme ideas can be tagged with, for templates to ask for
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Adjective,
+    Noun,
+    Verb,
+    Place,
+}
+
+impl Category {
+    pub const ALL: &'static [Category] = &[
+        Category::Adjective,
+        Category::Noun,
+        Category::Verb,
+        Category::Place,
+    ];
+
+    /// Name used in templates, commands and the database
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Adjective => "adjective",
+            Category::Noun => "noun",
+            Category::Verb => "verb",
+            Category::Place => "place",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Category> {
+        Category::ALL.iter().cloned().find(|category| category.name() == name.to_lowercase())
+    }
+}
+
+/// A piece of a template
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Part<'a> {
+    Text(&'a str),
+    /// Filled with an idea of the category, or of any category for None
+    Slot(Option<Category>),
+}
+
+/// Why a template was not accepted
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplateError {
+    NoSlots,
+    UnknownSlot(String),
+    /// A `{` without a `}` after it
+    Unclosed,
+}
+
+impl TemplateError {
+    /// The reason in the language, to send back to the organizer
+    pub fn message(&self, language: Language) -> String {
+        match self {
+            Self::NoSlots => Msg::TemplateNoSlots.text(language),
+            Self::UnknownSlot(slot) => Msg::TemplateUnknownSlot { slot }.text(language),
+            Self::Unclosed => Msg::TemplateUnclosed.text(language),
+        }
+    }
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message(Language::En))
+    }
+}
+
+/// Checks that the template can be used to generate themes
+pub fn check_template(template: &str) -> std::result::Result<(), TemplateError> {
+    parse_template(template).map(|_| ())
+}
+
+/**
+  Splits a template like "{noun} of {place}" into its text and slots. Slots
+  are the name of a category, or `any` for an idea of any category
+*/
+fn parse_template(template: &str) -> std::result::Result<Vec<Part<'_>>, TemplateError> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        let length = rest[start..].find('}').ok_or(TemplateError::Unclosed)?;
+        let name = rest[start + 1..start + length].trim();
+        let slot = if name.eq_ignore_ascii_case(ANY_SLOT) {
+            None
+        }
+        else {
+            Some(Category::from_name(name).ok_or_else(|| TemplateError::UnknownSlot(name.to_string()))?)
+        };
+        parts.push(Part::Slot(slot));
+        rest = &rest[start + length + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    if slots(&parts).is_empty() {
+        return Err(TemplateError::NoSlots)
+    }
+    Ok(parts)
+}
+
+fn slots(parts: &[Part]) -> Vec<Option<Category>> {
+    parts.iter().filter_map(|part| match part {
+        Part::Slot(slot) => Some(*slot),
+        Part::Text(_) => None,
+    }).collect()
+}
+
+/// Whether there are enough different ideas to fill every slot of the template
+fn can_fill(parts: &[Part], ideas: &[&ThemeIdea]) -> bool {
+    let slots = slots(parts);
+    let enough_of_each = Category::ALL.iter().all(|category| {
+        let wanted = slots.iter().filter(|slot| **slot == Some(*category)).count();
+        wanted <= ideas.iter().filter(|idea| idea.category == Some(*category)).count()
+    });
+    enough_of_each && slots.len() <= ideas.len()
+}
+
+/**
+  Makes a theme from a random template that there are enough ideas for,
+  filling each slot with a different random idea of its category. Returns
+  None if no template can be filled.
+
+  Everything random comes from `rng`, so a seeded rng and ideas in the same
+  order always give the same theme
+*/
+pub fn generate_theme(
+    ideas: &[&ThemeIdea],
+    templates: &[String],
+    rng: &mut impl Rng,
+) -> Option<String> {
+    // Several users can have submitted the same idea
+    let mut seen = HashSet::new();
+    let ideas = ideas.iter()
+        .cloned()
+        .filter(|idea| seen.insert(idea.text.to_lowercase()))
+        .collect::<Vec<_>>();
+
+    let usable = templates.iter()
+        .filter_map(|template| parse_template(template).ok())
+        .filter(|parts| can_fill(parts, &ideas))
+        .collect::<Vec<_>>();
+    let parts = usable.choose(rng)?;
+
+    // Slots asking for a category are filled first so that slots taking any
+    // idea can't use up the ideas they need
+    let mut used = HashSet::new();
+    let mut fillings = vec!(None; parts.len());
+    for any in &[false, true] {
+        for (i, part) in parts.iter().enumerate() {
+            let slot = match part {
+                Part::Slot(slot) if slot.is_none() == *any => *slot,
+                _ => continue,
+            };
+            let (index, idea) = ideas.iter()
+                .enumerate()
+                .filter(|(index, idea)| !used.contains(index) && (slot.is_none() || idea.category == slot))
+                .choose(rng)?;
+            used.insert(index);
+            fillings[i] = Some(idea.text.as_str());
+        }
+    }
+
+    Some(parts.iter().zip(fillings).map(|(part, filling)| match part {
+        Part::Text(text) => *text,
+        Part::Slot(_) => filling.unwrap_or_default(),
+    }).collect())
+}
+
+impl PersistentState {
+    fn add_theme_template(&mut self, actor: UserId, template: &str) -> Result<()> {
+        self.theme_templates.push(template.to_string());
+        self.save_change(Change::Settings)?;
+        self.record(actor, format!("Added the theme template {}", template), None)
+    }
+
+    /// Removes the template at the index, returning it if there was one
+    fn remove_theme_template(&mut self, actor: UserId, index: usize) -> Result<Option<String>> {
+        if index >= self.theme_templates.len() {
+            return Ok(None)
+        }
+        let template = self.theme_templates.remove(index);
+        self.save_change(Change::Settings)?;
+        self.record(actor, format!("Removed the theme template {}", template), None)?;
+        Ok(Some(template))
+    }
+}
+
+pub async fn handle_theme_templates<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let language = language(guild_id, author.id).await;
+    let state = PersistentState::for_guild(guild_id).await?;
+    let mut state = state.lock().await;
+    let message = match rest_command {
+        [] => {
+            let templates = state.theme_templates.iter()
+                .enumerate()
+                .map(|(i, template)| format!("{}. `{}`", i + 1, template))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Msg::ThemeTemplates { templates: &templates }.text(language)
+        }
+        [command, template @ ..] if command.eq_ignore_ascii_case("add") && !template.is_empty() => {
+            let template = template.join(" ");
+            match check_template(&template) {
+                Ok(()) => {
+                    state.add_theme_template(author.id, &template)?;
+                    Msg::TemplateAdded { template: &template }.text(language)
+                }
+                Err(e) => e.message(language),
+            }
+        }
+        [command, number] if command.eq_ignore_ascii_case("remove") => {
+            if state.theme_templates.len() == 1 {
+                Msg::LastTemplate.text(language)
+            }
+            else {
+                let removed = match number.parse::<usize>() {
+                    Ok(number) if number > 0 => state.remove_theme_template(author.id, number - 1)?,
+                    _ => None,
+                };
+                match removed {
+                    Some(template) => Msg::TemplateRemoved { template: &template }.text(language),
+                    None => Msg::NoSuchTemplate { number }.text(language),
+                }
+            }
+        }
+        _ => Msg::ProperUsage {
+            command: ThemeTemplates.name(),
+            usage: &Msg::UsageThemeTemplates.text(language),
+        }.text(language),
+    };
+    drop(state);
+    send_message(http, original_channel, author.id, message).await
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(ThemeTemplates);
+}
+
+struct ThemeTemplates;
+
+#[async_trait]
+impl Command for ThemeTemplates {
+    fn name(&self) -> &'static str { "themetemplates" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageThemeTemplates) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeThemeTemplates }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ServerConfigure)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_theme_templates(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    fn idea(text: &str, category: Option<Category>) -> ThemeIdea {
+        ThemeIdea { text: text.to_string(), category }
+    }
+
+    fn templates(templates: &[&str]) -> Vec<String> {
+        templates.iter().map(|template| template.to_string()).collect()
+    }
+
+    /// The themes that the seeds 0 to 49 generate
+    fn generate_all(ideas: &[ThemeIdea], templates: &[String]) -> Vec<Option<String>> {
+        let ideas = ideas.iter().collect::<Vec<_>>();
+        (0..50)
+            .map(|seed| generate_theme(&ideas, templates, &mut StdRng::seed_from_u64(seed)))
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_theme() {
+        let ideas = vec!(
+            idea("Frogs", None),
+            idea("Space", None),
+            idea("Time", None),
+            idea("Loops", None),
+        );
+        let templates = templates(&["{any} {any}", "{any} and {any}"]);
+        let themes = generate_all(&ideas, &templates);
+        assert_eq!(themes, generate_all(&ideas, &templates));
+        // Different seeds still give different themes
+        assert!(themes.iter().collect::<HashSet<_>>().len() > 1);
+    }
+
+    #[test]
+    fn slots_get_different_ideas_of_their_category() {
+        let ideas = vec!(
+            idea("Angry", Some(Category::Adjective)),
+            idea("Frogs", Some(Category::Noun)),
+            idea("Robots", Some(Category::Noun)),
+            idea("Space", Some(Category::Place)),
+            idea("Jumping", Some(Category::Verb)),
+        );
+        for theme in generate_all(&ideas, &templates(&["{noun} of {place}", "{noun} and {noun}"])) {
+            let theme = theme.unwrap();
+            assert!(
+                theme == "Frogs of Space" || theme == "Robots of Space"
+                    || theme == "Frogs and Robots" || theme == "Robots and Frogs",
+                "{}", theme
+            );
+        }
+    }
+
+    #[test]
+    fn any_slots_leave_the_tagged_ideas_to_the_slots_that_need_them() {
+        let ideas = vec!(idea("Frogs", Some(Category::Noun)), idea("Time", None));
+        for theme in generate_all(&ideas, &templates(&["{any} {noun}"])) {
+            assert_eq!(theme.unwrap(), "Time Frogs");
+        }
+    }
+
+    #[test]
+    fn templates_without_enough_ideas_are_skipped() {
+        let ideas = vec!(idea("Frogs", Some(Category::Noun)), idea("Time", None));
+        let templates = templates(&["{adjective} {noun}", "{any} {any} {any}", "{noun}!"]);
+        for theme in generate_all(&ideas, &templates) {
+            assert_eq!(theme.unwrap(), "Frogs!");
+        }
+        assert!(generate_all(&ideas, &templates[..2]).iter().all(Option::is_none));
+        assert!(generate_all(&[], &templates).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn repeated_ideas_count_once() {
+        let ideas = vec!(idea("Frogs", None), idea("frogs", None), idea("FROGS", None));
+        assert!(generate_all(&ideas, &templates(&["{any} {any}"])).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn invalid_templates_are_skipped() {
+        let ideas = vec!(idea("Frogs", None));
+        let templates = templates(&["{any", "{colour}", "No slots", "The {ANY}"]);
+        for theme in generate_all(&ideas, &templates) {
+            assert_eq!(theme.unwrap(), "The Frogs");
+        }
+    }
+}
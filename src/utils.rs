@@ -1,38 +1,233 @@
 use std::fmt::Display;
 
-use twilight::{
-    http::{
-        Client as HttpClient,
-        error::{
-            Error as DiscordError,
-            ResponseError,
-        },
-    },
-    model::id::{ChannelId, UserId},
+use anyhow::Context;
+use twilight::model::{
+    channel::embed::Embed,
+    id::{ChannelId, UserId},
 };
 
+use crate::discord::DiscordApi;
+
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
 
+/// Discord's limit on the length of a message
+pub const MESSAGE_LIMIT: usize = 2000;
+/// Discord's limit on the length of an embed description
+pub const EMBED_DESCRIPTION_LIMIT: usize = 2048;
+
+const FENCE: &'static str = "```";
+/// Longest code block language that is carried over when a block is split
+const MAX_FENCE_LANGUAGE: usize = 16;
+
+/**
+  Sends a message mentioning the user. Content that is too long for one
+  message is split into several, see `split_message`. Stops at the first
+  chunk that can't be sent
+*/
 pub async fn send_message(
-    http: &HttpClient,
+    http: &dyn DiscordApi,
     channel_id: ChannelId,
     user_id: UserId,
     content: impl Into<String> + Display,
 ) -> Result<()> {
-    let context = "send_message";
-    match http.create_message(channel_id)
-        .content(format!("<@{}> {}", user_id, content))
-        .await {
-        Err(DiscordError::Response{source: ResponseError::Client{response: r}}) => {
-            println!("{}: The response was a client side error: {}", context,
-                match r.text().await {
-                    Ok(text) => text,
-                    _ => "(Response unavailable)".to_string(),
-                }
-            );
-        },
-        Err(e) => println!("{}: The response was an unknown error: {:?}", context, e),
-        _ => {}
-    };
+    send_plain(http, channel_id, &format!("<@{}> {}", user_id, content)).await
+        .with_context(|| format!("Failed to send a message to {}", user_id))
+}
+
+/**
+  Sends a message without mentioning anyone, for private messages where
+  there is only one user to talk to. Split like in `send_message`
+*/
+pub async fn send_plain(http: &dyn DiscordApi, channel_id: ChannelId, content: &str) -> Result<()> {
+    for chunk in split_message(content, MESSAGE_LIMIT) {
+        http.create_message(channel_id, chunk).await
+            .with_context(|| format!("Failed to send a message in {}", channel_id))?;
+    }
     Ok(())
 }
+
+/**
+  Sends the content as embeds with the title, mentioning the user above the
+  first one. Content that doesn't fit in one embed is split like in
+  `send_message`, with the page number added to the title
+*/
+pub async fn send_embeds(
+    http: &dyn DiscordApi,
+    channel_id: ChannelId,
+    user_id: UserId,
+    title: &str,
+    content: &str,
+) -> Result<()> {
+    let chunks = split_message(content, EMBED_DESCRIPTION_LIMIT);
+    let pages = chunks.len();
+    for (page, chunk) in chunks.into_iter().enumerate() {
+        let title = if pages > 1 {
+            format!("{} ({}/{})", title, page + 1, pages)
+        }
+        else {
+            title.to_string()
+        };
+        let mention = if page == 0 { Some(format!("<@{}>", user_id)) } else { None };
+        http.create_embed(channel_id, mention, embed(title, chunk)).await
+            .with_context(|| format!("Failed to send an embed to {} in {}", user_id, channel_id))?;
+    }
+    Ok(())
+}
+
+/// An embed with only a title and a description
+pub fn embed(title: impl Into<String>, description: impl Into<String>) -> Embed {
+    Embed {
+        author: None,
+        color: None,
+        description: Some(description.into()),
+        fields: Vec::new(),
+        footer: None,
+        image: None,
+        kind: "rich".to_string(),
+        provider: None,
+        thumbnail: None,
+        timestamp: None,
+        title: Some(title.into()),
+        url: None,
+        video: None,
+    }
+}
+
+/**
+  Splits the content into chunks of at most `limit` characters. Chunks end
+  at a line break if there is one in the second half of the chunk, and
+  otherwise at a space.
+
+  Code blocks that are split are closed at the end of the chunk and opened
+  again, with the same language, at the start of the next
+*/
+pub fn split_message(content: &str, limit: usize) -> Vec<String> {
+    // Leave room for closing a code block
+    let budget = limit - FENCE.len() - 1;
+    let mut chunks = Vec::new();
+    let mut rest = content.to_string();
+
+    while rest.chars().count() > limit {
+        let window_end = rest.char_indices().nth(budget).map(|(i, _)| i).unwrap();
+        let window = &rest[..window_end];
+        // Don't make tiny chunks just because there is an early line break
+        let min_end = window.char_indices().nth(budget / 2).map(|(i, _)| i).unwrap();
+
+        let (mut end, separator) = match window.rfind('\n') {
+            Some(i) if i >= min_end => (i, 1),
+            _ => match window.rfind(' ') {
+                Some(i) if i >= min_end => (i, 1),
+                _ => (window_end, 0),
+            },
+        };
+        // Don't cut a fence in half
+        if separator == 0 {
+            while end > min_end && rest[..end].ends_with('`') {
+                end -= 1;
+            }
+        }
+
+        let mut chunk = rest[..end].to_string();
+        let mut next = rest[end + separator..].to_string();
+        if let Some(language) = unclosed_fence(&chunk) {
+            chunk.push('\n');
+            chunk.push_str(FENCE);
+            next = format!("{}{}\n{}", FENCE, language, next);
+        }
+        chunks.push(chunk);
+        rest = next;
+    }
+    if !rest.trim().is_empty() {
+        chunks.push(rest);
+    }
+    chunks
+}
+
+/// The language of the code block left open at the end of the text, if any
+fn unclosed_fence(text: &str) -> Option<String> {
+    let mut open = None;
+    let mut rest = text;
+    while let Some(i) = rest.find(FENCE) {
+        rest = &rest[i + FENCE.len()..];
+        open = match open {
+            Some(_) => None,
+            None => {
+                // Only a word directly followed by a line break is a language
+                let line = rest.split('\n').next().unwrap_or("");
+                let is_language = rest.contains('\n')
+                    && line.len() <= MAX_FENCE_LANGUAGE
+                    && line.chars().all(|c| c.is_ascii_alphanumeric() || "+-#".contains(c));
+                Some(if is_language { line.to_string() } else { String::new() })
+            }
+        };
+    }
+    open
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_within(chunks: &[String], limit: usize) {
+        for chunk in chunks {
+            assert!(chunk.chars().count() <= limit, "{} is longer than {}", chunk, limit);
+        }
+    }
+
+    #[test]
+    fn short_messages_are_kept_whole() {
+        assert_eq!(split_message("Hello there", 20), vec!("Hello there"));
+    }
+
+    #[test]
+    fn split_code_blocks_are_closed_and_reopened() {
+        let content = format!("Ideas:\n```rust\n{}```", "let idea = 1;\n".repeat(10));
+        let chunks = split_message(&content, 60);
+
+        assert!(chunks.len() > 1);
+        assert_within(&chunks, 60);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.matches(FENCE).count() % 2, 0, "chunk {} is left open: {}", i, chunk);
+            if i > 0 {
+                assert!(chunk.starts_with("```rust\n"), "chunk {} isn't reopened: {}", i, chunk);
+            }
+        }
+        assert!(chunks[0].ends_with("\n```"));
+    }
+
+    #[test]
+    fn text_without_breaks_is_cut_where_it_has_to() {
+        let content = "a".repeat(250);
+        let chunks = split_message(&content, 100);
+
+        assert_within(&chunks, 100);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), content);
+    }
+
+    #[test]
+    fn fences_are_not_cut_in_half() {
+        // A cut after 16 characters would leave a single backtick at the end
+        let content = format!("{}```{}", "a".repeat(15), "b".repeat(30));
+        let chunks = split_message(&content, 20);
+
+        assert_within(&chunks, 20);
+        assert_eq!(chunks[0], "a".repeat(15));
+        assert!(chunks[1].starts_with("```b"), "{}", chunks[1]);
+    }
+
+    #[test]
+    fn multibyte_text_is_cut_between_characters() {
+        let content = "🐸".repeat(99) + &"å".repeat(5);
+        let chunks = split_message(&content, 100);
+
+        assert_within(&chunks, 100);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks.concat(), content);
+
+        let words = "åäö ".repeat(60);
+        let chunks = split_message(&words, 100);
+        assert_within(&chunks, 100);
+        assert!(chunks.iter().all(|chunk| chunk.split(' ').all(|word| word.is_empty() || word == "åäö")));
+    }
+}
@@ -0,0 +1,788 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use rand::Rng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use tracing::error;
+use twilight::model::{
+    channel::{Reaction, ReactionType},
+    id::{ChannelId, GuildId, MessageId, UserId},
+    user::User,
+};
+
+use crate::command::{Command, CommandContext, CommandRegistry};
+use crate::discord::{self, DiscordApi};
+use crate::locale::{language, Language, Msg};
+use crate::log_channel::{log_action, Outcome};
+use crate::permission::Capability;
+use crate::retry::{retry, Retry};
+use crate::state::PersistentState;
+use crate::storage::Change;
+use crate::theme_template::generate_theme;
+use crate::utils::{Result, send_message};
+
+/// Reactions used to vote for the candidates, in order
+const CANDIDATE_EMOJIS: &[&str] = &["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣", "🔟"];
+
+/// Most rounds a vote can have
+const MAX_ROUNDS: usize = 5;
+
+/// How often the deadlines of votes are checked
+const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Longest wait before trying again to end a round that Discord kept failing
+const MAX_DEADLINE_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// A theme that can be voted for
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Candidate {
+    pub theme: String,
+    /// Reacting with this emoji on the vote message is a vote for the theme
+    pub emoji: String,
+    /// Who voted for the theme in the current round
+    pub voters: HashSet<UserId>,
+}
+
+/**
+  A vote on the theme of the jam. Participants vote by reacting to the
+  message of the round, and can vote for as many candidates as they like but
+  only once for each.
+
+  Every round but the last drops the lower scoring half of the candidates,
+  and the ones left are voted on from scratch in a new message
+*/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Vote {
+    pub channel_id: ChannelId,
+    /// The message of the current round
+    pub message_id: MessageId,
+    /// The current round, counting from 1
+    pub round: usize,
+    /// Length in seconds of each of the rounds after the current one
+    pub later_rounds: Vec<i64>,
+    /// Unix time after which reactions no longer change the votes
+    pub deadline: i64,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Vote {
+    fn rounds(&self) -> usize {
+        self.round + self.later_rounds.len()
+    }
+
+    /// The candidates with the most votes, unless nobody voted
+    fn winners(&self) -> Vec<&Candidate> {
+        let most = self.candidates.iter().map(|c| c.voters.len()).max().unwrap_or(0);
+        if most == 0 {
+            return Vec::new()
+        }
+        self.candidates.iter().filter(|c| c.voters.len() == most).collect()
+    }
+
+    /// Every candidate and its votes, one per line
+    fn results(&self, language: Language) -> String {
+        self.candidates.iter()
+            .map(|c| format!("{} {}", c.emoji, Msg::VoteCount { theme: &c.theme, votes: c.voters.len() }
+                .text(language)
+            ))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The candidates of the round as they are announced
+    fn list(&self) -> String {
+        self.candidates.iter()
+            .map(|c| format!("{} {}", c.emoji, c.theme))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /**
+      Drops the lower scoring half of the candidates, keeping at least two,
+      and returns the themes that were dropped. Ties at the cut are broken at
+      random, and the candidates that are kept stay in the same order
+    */
+    fn eliminate(&mut self, rng: &mut impl Rng) -> Vec<String> {
+        let keep = std::cmp::max(2, (self.candidates.len() + 1) / 2);
+        let mut ranking = (0..self.candidates.len()).collect::<Vec<_>>();
+        ranking.shuffle(rng);
+        // The sort is stable, so the shuffle decides the order of ties
+        ranking.sort_by_key(|&i| Reverse(self.candidates[i].voters.len()));
+        let kept = ranking.into_iter().take(keep).collect::<HashSet<_>>();
+
+        let (kept, dropped) = self.candidates.drain(..)
+            .enumerate()
+            .partition::<Vec<_>, _>(|(i, _)| kept.contains(i));
+        self.candidates = kept.into_iter().map(|(_, candidate)| candidate).collect();
+        dropped.into_iter().map(|(_, candidate)| candidate.theme).collect()
+    }
+
+    /// Moves on to the next round, where every candidate starts without votes
+    fn next_round(&mut self, now: i64) {
+        self.round += 1;
+        self.deadline = now + self.later_rounds.remove(0);
+        for (candidate, emoji) in self.candidates.iter_mut().zip(CANDIDATE_EMOJIS) {
+            candidate.emoji = emoji.to_string();
+            candidate.voters.clear();
+        }
+    }
+}
+
+/// Where the candidates of a vote come from
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Source {
+    /// Themes that follow the templates, like the ones generatetheme makes
+    Generated,
+    /// Single ideas
+    Pool,
+}
+
+impl PersistentState {
+    /**
+      Records that the user reacted, or took back a reaction, on the open
+      vote. Ballots are left out of the journal, since every reaction would
+      be an entry and the reactions on the message already show them
+    */
+    fn record_ballot(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        emoji: &str,
+        added: bool,
+    ) -> Result<()> {
+        let vote = match &mut self.vote {
+            Some(vote) if vote.message_id == message_id => vote,
+            _ => return Ok(()),
+        };
+        if Utc::now().timestamp() > vote.deadline {
+            return Ok(())
+        }
+        let changed = match vote.candidates.iter_mut().find(|c| c.emoji == emoji) {
+            Some(candidate) if added => candidate.voters.insert(user_id),
+            Some(candidate) => candidate.voters.remove(&user_id),
+            None => false,
+        };
+        if changed {
+            self.save_change(Change::Ballot(user_id))?;
+        }
+        Ok(())
+    }
+
+    /// Opens the vote, or the next round of it which ends the one before
+    fn start_round(&mut self, actor: UserId, vote: Vote) -> Result<()> {
+        let description = if vote.round == 1 {
+            "Started a theme vote".to_string()
+        }
+        else {
+            format!("Ended round {} and started round {} of the theme vote", vote.round - 1, vote.round)
+        };
+        self.vote = Some(vote);
+        self.save_change(Change::Vote)?;
+        self.record(actor, description, None)
+    }
+
+    /**
+      Takes the open vote out of the state to end its current round. With
+      `due_only`, the vote is only taken if its deadline has passed. The end
+      of the round is recorded once the next round or the winner is posted
+    */
+    fn take_vote(&mut self, due_only: bool) -> Result<Option<Vote>> {
+        match &self.vote {
+            Some(vote) if !due_only || Utc::now().timestamp() > vote.deadline => {}
+            _ => return Ok(None),
+        }
+        let vote = self.vote.take();
+        self.save_change(Change::Vote)?;
+        Ok(vote)
+    }
+
+    /// Puts back a vote whose round could not be ended, unless another vote was started
+    fn restore_vote(&mut self, actor: UserId, vote: Vote) -> Result<()> {
+        if self.vote.is_none() {
+            let description = format!("Kept round {} of the theme vote open after a failure", vote.round);
+            self.vote = Some(vote);
+            self.save_change(Change::Vote)?;
+            self.record(actor, description, None)?;
+        }
+        Ok(())
+    }
+
+    /**
+      Closes a vote whose round could not be ended because of a failure that
+      trying again won't fix, along with its next round if that was opened
+    */
+    fn close_failed_vote(&mut self, actor: UserId, vote: &Vote) -> Result<()> {
+        if self.vote.as_ref().map_or(false, |open| open.channel_id == vote.channel_id) {
+            self.vote = None;
+            self.save_change(Change::Vote)?;
+        }
+        self.record(actor, format!("Closed the theme vote after round {} could not be ended", vote.round), None)
+    }
+
+    /// Picks up to `count` different themes to vote on from the theme ideas
+    fn shortlist(&self, count: usize, source: Source) -> Vec<String> {
+        // Created here since the thread rng can't be held across awaits
+        let mut rng = rand::thread_rng();
+        match source {
+            Source::Pool => {
+                let mut themes = self.all_theme_ideas()
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .choose_multiple(&mut rng, count);
+                themes.shuffle(&mut rng);
+                themes.into_iter().cloned().collect()
+            }
+            Source::Generated => {
+                let ideas = self.sorted_theme_ideas();
+                let mut themes = Vec::new();
+                // Generated themes can repeat, so give up after a while on small pools
+                for _ in 0..count * 10 {
+                    if themes.len() == count {
+                        break
+                    }
+                    match generate_theme(&ideas, &self.theme_templates, &mut rng) {
+                        Some(theme) if !themes.contains(&theme) => themes.push(theme),
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                themes
+            }
+        }
+    }
+}
+
+/// Parses durations like 30m, 2h and 1d into seconds
+fn parse_duration(text: &str) -> Option<i64> {
+    lazy_static! {
+        static ref DURATION_REGEX: Regex = Regex::new(r"^(\d{1,4})([mhd])$").unwrap();
+    }
+    let captures = DURATION_REGEX.captures(text)?;
+    let amount = captures[1].parse::<i64>().ok()?;
+    let unit = match &captures[2] {
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => 24 * 60 * 60,
+    };
+    if amount == 0 {
+        return None
+    }
+    Some(amount * unit)
+}
+
+/// A unix time as it is shown to users
+fn format_time(time: i64) -> String {
+    NaiveDateTime::from_timestamp(time, 0).format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/**
+  Posts the message of the current round of the vote, after `intro` if there
+  is one, and opens the round
+*/
+async fn post_round(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    actor: UserId,
+    mut vote: Vote,
+    intro: Option<String>,
+    language: Language,
+) -> Result<()> {
+    let announcement = Msg::VoteAnnouncement {
+        round: vote.round,
+        rounds: vote.rounds(),
+        candidates: &vote.list(),
+        deadline: &format_time(vote.deadline),
+    }.text(language);
+    let content = match intro {
+        Some(intro) => format!("{}\n\n{}", intro, announcement),
+        None => announcement,
+    };
+    let message = http.create_message(vote.channel_id, content).await?;
+    vote.message_id = message.id;
+
+    // Saved before reacting so that no early votes are missed
+    PersistentState::for_guild(guild_id).await?.lock().await
+        .start_round(actor, vote.clone())?;
+    for candidate in &vote.candidates {
+        retry(Retry::Idempotent, "add reaction",
+            || http.create_reaction(vote.channel_id, message.id, candidate.emoji.clone())
+        ).await?;
+    }
+    Ok(())
+}
+
+/**
+  Ends the current round of the open vote. Every round but the last drops
+  half of the candidates and opens the next round, while the last one
+  announces the theme. Returns the channel of the vote, or None if there was
+  no vote to end.
+
+  If Discord fails for a while the vote stays open so that the round can be
+  ended again later, otherwise the vote is closed and the organizers are told
+*/
+async fn end_round(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    actor: UserId,
+    due_only: bool,
+) -> Result<Option<ChannelId>> {
+    let (vote, language) = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let mut state = state.lock().await;
+        (state.take_vote(due_only)?, state.language)
+    };
+    let vote = match vote {
+        Some(vote) => vote,
+        None => return Ok(None),
+    };
+
+    let channel_id = vote.channel_id;
+    // Everything sent about the vote is for everyone, so it is in the server language
+    let result = if vote.later_rounds.is_empty() {
+        announce_winner(http, guild_id, actor, &vote, language).await
+    }
+    else {
+        let mut next = vote.clone();
+        let results = next.results(language);
+        let dropped = next.eliminate(&mut rand::thread_rng());
+        next.next_round(Utc::now().timestamp());
+        let intro = Msg::VoteRoundEnded {
+            round: vote.round,
+            results: &results,
+            dropped: &dropped.join(", "),
+        }.text(language);
+        let result = post_round(http, guild_id, actor, next, Some(intro.clone()), language).await;
+        if result.is_ok() {
+            log_action(http, guild_id, actor, format!("Theme vote round {} ended", vote.round),
+                Outcome::Done(intro)
+            ).await;
+        }
+        result
+    };
+    if let Err(e) = result {
+        let state = PersistentState::for_guild(guild_id).await?;
+        if discord::is_transient(&e) {
+            state.lock().await.restore_vote(actor, vote)?;
+        }
+        else {
+            state.lock().await.close_failed_vote(actor, &vote)?;
+            log_action(http, guild_id, actor, "Theme vote closed",
+                Outcome::Failed(format!("Round {} could not be ended: {}", vote.round, e))
+            ).await;
+        }
+        return Err(e)
+    }
+    Ok(Some(channel_id))
+}
+
+async fn announce_winner(
+    http: &dyn DiscordApi,
+    guild_id: GuildId,
+    actor: UserId,
+    vote: &Vote,
+    language: Language,
+) -> Result<()> {
+    let results = vote.results(language);
+    let winners = vote.winners();
+    let tied = winners.iter().map(|c| c.theme.as_str()).collect::<Vec<_>>().join(", ");
+    let announcement = match winners.as_slice() {
+        [] => Msg::VoteNoVotes,
+        [winner] => Msg::VoteWinner {
+            theme: &winner.theme,
+            results: &results,
+        },
+        _ => Msg::VoteTie {
+            themes: &tied,
+            results: &results,
+        },
+    }.text(language);
+    http.create_message(vote.channel_id, announcement.clone()).await?;
+    PersistentState::for_guild(guild_id).await?.lock().await
+        .record(actor, format!("Ended round {} of the theme vote", vote.round), None)?;
+    log_action(http, guild_id, actor, "Theme vote closed", Outcome::Done(announcement)).await;
+    Ok(())
+}
+
+/**
+  Ends the rounds of the votes of every guild once their deadline has
+  passed, acting as the bot. Runs until the bot stops.
+
+  Guilds where Discord failed to end the round are tried again later, waiting
+  twice as long after each failure in a row
+*/
+pub async fn watch_deadlines(http: Arc<dyn DiscordApi>, current_user_id: UserId) {
+    let mut interval = tokio::time::interval(DEADLINE_CHECK_INTERVAL);
+    // The failures in a row of each guild and when to try it again
+    let mut backoff = HashMap::<GuildId, (u32, Instant)>::new();
+    loop {
+        interval.tick().await;
+        for guild_id in PersistentState::loaded_guilds() {
+            match backoff.get(&guild_id) {
+                Some((_, next_try)) if Instant::now() < *next_try => continue,
+                _ => {}
+            }
+            match end_round(&*http, guild_id, current_user_id, true).await {
+                Err(e) if discord::is_transient(&e) => {
+                    let failures = backoff.get(&guild_id).map_or(0, |(failures, _)| *failures) + 1;
+                    let delay = deadline_backoff(failures);
+                    error!(guild = %guild_id, error = ?e, retry_in_secs = delay.as_secs(),
+                        "Failed to end theme vote round");
+                    backoff.insert(guild_id, (failures, Instant::now() + delay));
+                }
+                Err(e) => {
+                    error!(guild = %guild_id, error = ?e, "Failed to end theme vote round, closed the vote");
+                    backoff.remove(&guild_id);
+                }
+                Ok(_) => {
+                    backoff.remove(&guild_id);
+                }
+            }
+        }
+    }
+}
+
+/// How long to wait before trying to end a round again after the number of failures in a row
+fn deadline_backoff(failures: u32) -> Duration {
+    let delay = DEADLINE_CHECK_INTERVAL * 2u32.pow(failures.min(16));
+    std::cmp::min(delay, MAX_DEADLINE_BACKOFF)
+}
+
+pub async fn handle_vote_reaction(
+    reaction: &Reaction,
+    current_user_id: UserId,
+    added: bool,
+) -> Result<()> {
+    let guild_id = match reaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    if reaction.user_id == current_user_id {
+        return Ok(())
+    }
+    if let ReactionType::Unicode { name } = &reaction.emoji {
+        PersistentState::for_guild(guild_id).await?.lock().await
+            .record_ballot(reaction.message_id, reaction.user_id, name, added)?;
+    }
+    Ok(())
+}
+
+pub async fn handle_start_vote<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    lazy_static! {
+        static ref CHANNEL_MENTION_REGEX: Regex = Regex::new(r"^<#(\d+)>$").unwrap();
+    }
+    let language = language(guild_id, author.id).await;
+    let usage = Msg::ProperUsage {
+        command: StartVote.name(),
+        usage: &Msg::UsageStartVote.text(language),
+    }.text(language);
+
+    let (mention, count, rest) = match rest_command {
+        [mention, count, rest @ ..] => (mention, count, rest),
+        _ => return send_message(http, original_channel, author.id, usage).await,
+    };
+    let (durations, source) = match rest.split_last() {
+        Some((&"generated", durations)) => (durations, Source::Generated),
+        Some((&"pool", durations)) => (durations, Source::Pool),
+        _ => (rest, Source::Generated),
+    };
+    if durations.is_empty() {
+        return send_message(http, original_channel, author.id, usage).await
+    }
+    let channel_id = match CHANNEL_MENTION_REGEX.captures(mention)
+        .and_then(|captures| captures[1].parse::<u64>().ok())
+    {
+        Some(id) => ChannelId(id),
+        None => return send_message(http, original_channel, author.id,
+            Msg::InvalidChannelReference { usage: &usage }.text(language)
+        ).await,
+    };
+    let count = match count.parse::<usize>() {
+        Ok(count) if count >= 2 && count <= CANDIDATE_EMOJIS.len() => count,
+        _ => return send_message(http, original_channel, author.id,
+            Msg::InvalidCandidateCount { max: CANDIDATE_EMOJIS.len() }.text(language)
+        ).await,
+    };
+    if durations.len() > MAX_ROUNDS {
+        return send_message(http, original_channel, author.id,
+            Msg::TooManyRounds { max: MAX_ROUNDS }.text(language)
+        ).await
+    }
+    if let Some(duration) = durations.iter().find(|duration| parse_duration(duration).is_none()) {
+        return send_message(http, original_channel, author.id,
+            Msg::InvalidDuration { duration }.text(language)
+        ).await
+    }
+    let mut durations = durations.iter().filter_map(|duration| parse_duration(duration));
+
+    let (themes, server_language) = {
+        let state = PersistentState::for_guild(guild_id).await?;
+        let state = state.lock().await;
+        if let Some(vote) = &state.vote {
+            let channel = vote.channel_id;
+            drop(state);
+            return send_message(http, original_channel, author.id,
+                Msg::VoteAlreadyOpen { channel }.text(language)
+            ).await
+        }
+        (state.shortlist(count, source), state.language)
+    };
+    if themes.len() < 2 {
+        return send_message(http, original_channel, author.id,
+            Msg::NotEnoughIdeas.text(language)
+        ).await
+    }
+
+    let vote = Vote {
+        channel_id,
+        message_id: MessageId(0),
+        round: 1,
+        deadline: Utc::now().timestamp() + durations.next().unwrap(),
+        later_rounds: durations.collect(),
+        candidates: themes.into_iter()
+            .zip(CANDIDATE_EMOJIS)
+            .map(|(theme, emoji)| Candidate { theme, emoji: emoji.to_string(), voters: HashSet::new() })
+            .collect(),
+    };
+    let description = format!(
+        "In <#{}>, {} rounds, the first until {}:\n{}",
+        channel_id, vote.rounds(), format_time(vote.deadline), vote.list()
+    );
+    // The vote is for everyone, so it is in the server language
+    post_round(http, guild_id, author.id, vote, None, server_language).await?;
+
+    send_message(http, original_channel, author.id,
+        Msg::VoteStarted { channel: channel_id }.text(language)
+    ).await?;
+    log_action(http, guild_id, author.id, "Theme vote started", Outcome::Done(description)).await;
+    Ok(())
+}
+
+pub async fn handle_close_vote(
+    original_channel: ChannelId,
+    guild_id: GuildId,
+    author: &User,
+    http: &dyn DiscordApi,
+) -> Result<()> {
+    let language = language(guild_id, author.id).await;
+    match end_round(http, guild_id, author.id, false).await? {
+        None => send_message(http, original_channel, author.id,
+            Msg::NoVoteOpen.text(language)
+        ).await,
+        Some(channel) if channel != original_channel => send_message(
+            http, original_channel, author.id,
+            Msg::VoteClosed { channel }.text(language)
+        ).await,
+        Some(_) => Ok(()),
+    }
+}
+
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register(StartVote);
+    registry.register(CloseVote);
+}
+
+struct StartVote;
+
+#[async_trait]
+impl Command for StartVote {
+    fn name(&self) -> &'static str { "startvote" }
+    fn usage(&self) -> Option<Msg<'static>> { Some(Msg::UsageStartVote) }
+    fn description(&self) -> Msg<'static> { Msg::DescribeStartVote }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ThemeGenerate)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, args: &[&str]) -> Result<()> {
+        handle_start_vote(args, ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+struct CloseVote;
+
+#[async_trait]
+impl Command for CloseVote {
+    fn name(&self) -> &'static str { "closevote" }
+    fn description(&self) -> Msg<'static> { Msg::DescribeCloseVote }
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::ThemeGenerate)
+    }
+
+    async fn run(&self, ctx: &CommandContext<'_>, _args: &[&str]) -> Result<()> {
+        handle_close_vote(ctx.msg.channel_id, ctx.guild_id, &ctx.msg.author, ctx.http).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::discord::fake::{test_guild, Call, FakeDiscord};
+    use crate::storage::storage;
+
+    const BOT: UserId = UserId(1);
+    const VOTES: ChannelId = ChannelId(30);
+
+    fn candidate(theme: &str, emoji: &str, voters: &[u64]) -> Candidate {
+        Candidate {
+            theme: theme.to_string(),
+            emoji: emoji.to_string(),
+            voters: voters.iter().map(|&id| UserId(id)).collect(),
+        }
+    }
+
+    /// Opens a vote of three candidates whose first round is over
+    async fn open_vote(guild: GuildId, later_rounds: Vec<i64>) {
+        PersistentState::for_guild(guild).await.unwrap().lock().await.vote = Some(Vote {
+            channel_id: VOTES,
+            message_id: MessageId(500),
+            round: 1,
+            later_rounds,
+            deadline: Utc::now().timestamp() - 1,
+            candidates: vec!(
+                candidate("Frogs", CANDIDATE_EMOJIS[0], &[10, 11]),
+                candidate("Space", CANDIDATE_EMOJIS[1], &[10]),
+                candidate("Time", CANDIDATE_EMOJIS[2], &[]),
+            ),
+        });
+    }
+
+    /// A vote whose candidates got the votes, in order
+    fn vote_with(votes: &[u64]) -> Vote {
+        Vote {
+            channel_id: VOTES,
+            message_id: MessageId(500),
+            round: 1,
+            later_rounds: vec!(3600),
+            deadline: 0,
+            candidates: votes.iter().enumerate()
+                .map(|(i, &count)| candidate(&format!("Theme {}", i), CANDIDATE_EMOJIS[i],
+                    &(0..count).map(|voter| 100 + voter).collect::<Vec<_>>()
+                ))
+                .collect(),
+        }
+    }
+
+    fn themes(vote: &Vote) -> Vec<&str> {
+        vote.candidates.iter().map(|c| c.theme.as_str()).collect()
+    }
+
+    async fn open_vote_of(guild: GuildId) -> Option<Vote> {
+        PersistentState::for_guild(guild).await.unwrap().lock().await.vote.clone()
+    }
+
+    fn journal(guild: GuildId) -> Vec<String> {
+        storage().load_journal(guild).unwrap().into_iter().map(|entry| entry.action).collect()
+    }
+
+    #[test]
+    fn elimination_keeps_the_better_half_but_at_least_two() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for count in 2..=CANDIDATE_EMOJIS.len() {
+            // Later candidates got more votes
+            let mut vote = vote_with(&(0..count as u64).collect::<Vec<_>>());
+            let dropped = vote.eliminate(&mut rng);
+
+            let keep = std::cmp::max(2, (count + 1) / 2);
+            assert_eq!(vote.candidates.len(), keep, "{} candidates", count);
+            assert_eq!(dropped.len(), count - keep, "{} candidates", count);
+            let expected = (count - keep..count).map(|i| format!("Theme {}", i)).collect::<Vec<_>>();
+            assert_eq!(themes(&vote), expected, "{} candidates", count);
+        }
+    }
+
+    #[test]
+    fn elimination_breaks_ties_at_random() {
+        let mut kept = HashSet::new();
+        for seed in 0..50 {
+            let mut vote = vote_with(&[3, 1, 1, 1, 0]);
+            vote.eliminate(&mut StdRng::seed_from_u64(seed));
+            assert_eq!(vote.candidates.len(), 3);
+            assert_eq!(vote.candidates[0].theme, "Theme 0");
+            assert!(!themes(&vote).contains(&"Theme 4"));
+            kept.insert(themes(&vote).join(", "));
+        }
+        // Two of the three tied candidates are kept, and every pair comes up
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn elimination_keeps_the_order_of_the_candidates() {
+        let mut vote = vote_with(&[1, 5, 0, 4, 2, 3]);
+        let dropped = vote.eliminate(&mut StdRng::seed_from_u64(7));
+        assert_eq!(themes(&vote), vec!("Theme 1", "Theme 3", "Theme 5"));
+        assert_eq!(dropped, vec!("Theme 0", "Theme 2", "Theme 4"));
+    }
+
+    #[tokio::test]
+    async fn ended_rounds_are_recorded_once_the_next_one_is_posted() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        open_vote(guild, vec!(3600)).await;
+
+        assert_eq!(end_round(&discord, guild, BOT, true).await.unwrap(), Some(VOTES));
+
+        let vote = open_vote_of(guild).await.unwrap();
+        assert_eq!(vote.round, 2);
+        assert_eq!(vote.candidates.len(), 2);
+        assert_eq!(journal(guild), vec!("Ended round 1 and started round 2 of the theme vote"));
+    }
+
+    #[tokio::test]
+    async fn rounds_that_cant_be_posted_close_the_vote() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        discord.fail_on(|call| matches!(call, Call::CreateMessage(channel, _) if *channel == VOTES));
+        open_vote(guild, vec!(3600)).await;
+
+        assert!(end_round(&discord, guild, BOT, true).await.is_err());
+
+        assert!(open_vote_of(guild).await.is_none());
+        assert_eq!(journal(guild), vec!("Closed the theme vote after round 1 could not be ended"));
+    }
+
+    #[tokio::test]
+    async fn the_last_round_is_recorded_once_the_winner_is_announced() {
+        let guild = test_guild();
+        let discord = FakeDiscord::new();
+        open_vote(guild, Vec::new()).await;
+
+        end_round(&discord, guild, BOT, true).await.unwrap();
+
+        assert!(open_vote_of(guild).await.is_none());
+        assert!(discord.messages(VOTES)[0].contains("Frogs"));
+        assert_eq!(journal(guild), vec!("Ended round 1 of the theme vote"));
+    }
+
+    #[tokio::test]
+    async fn votes_put_back_after_a_failure_are_recorded() {
+        let guild = test_guild();
+        let state = PersistentState::for_guild(guild).await.unwrap();
+
+        state.lock().await.restore_vote(BOT, vote_with(&[1, 0])).unwrap();
+
+        assert_eq!(open_vote_of(guild).await.unwrap().round, 1);
+        assert_eq!(journal(guild), vec!("Kept round 1 of the theme vote open after a failure"));
+    }
+
+    #[test]
+    fn deadline_backoff_doubles_up_to_a_limit() {
+        assert_eq!(deadline_backoff(1), DEADLINE_CHECK_INTERVAL * 2);
+        assert_eq!(deadline_backoff(2), DEADLINE_CHECK_INTERVAL * 4);
+        assert_eq!(deadline_backoff(100), MAX_DEADLINE_BACKOFF);
+    }
+}